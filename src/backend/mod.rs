@@ -0,0 +1,3 @@
+mod verilog;
+
+pub use verilog::*;
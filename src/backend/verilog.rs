@@ -0,0 +1,336 @@
+use crate::parser::Literal;
+use crate::utils::binary_format;
+use crate::verilog_model::{LogicElem, LogicTree, Module, Signal, SignalKey, SignalSource, Wire};
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Verilog,
+    SystemVerilog,
+}
+
+impl Dialect {
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            Dialect::Verilog => "v",
+            Dialect::SystemVerilog => "sv",
+        };
+    }
+
+    fn net_type(&self) -> &'static str {
+        return match self {
+            Dialect::Verilog => "wire",
+            Dialect::SystemVerilog => "logic",
+        };
+    }
+}
+
+/// a verilog identifier from a sheet name, like `RV32I` or `Zicsr (draft)`
+pub fn module_name(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        ident.insert(0, '_');
+    }
+    return ident;
+}
+
+pub struct VerilogPrinter<'a> {
+    pub module: &'a Module,
+    pub name: &'a str,
+    pub dialect: Dialect,
+}
+
+impl<'a> VerilogPrinter<'a> {
+    pub fn new(module: &'a Module, name: &'a str, dialect: Dialect) -> Self {
+        return VerilogPrinter {
+            module,
+            name,
+            dialect,
+        };
+    }
+
+    fn signal(&self, key: &SignalKey) -> &Signal {
+        return &self.module.get_signals()[key];
+    }
+
+    fn declare(&self, signal: &Signal) -> String {
+        return match signal.length {
+            1 => format!("{} {}", self.dialect.net_type(), signal.key.as_str()),
+            len => format!(
+                "{} [{}:0] {}",
+                self.dialect.net_type(),
+                len - 1,
+                signal.key.as_str()
+            ),
+        };
+    }
+
+    /// notes of a signal as line comments
+    fn write_doc(&self, f: &mut fmt::Formatter<'_>, indent: &str, signal: &Signal) -> fmt::Result {
+        for line in signal.doc.iter().flat_map(|doc| doc.lines()) {
+            writeln!(f, "{}// {}", indent, line.trim_end())?;
+        }
+        return Ok(());
+    }
+
+    fn write_wire(&self, f: &mut fmt::Formatter<'_>, wire: &Wire) -> fmt::Result {
+        match wire {
+            Wire::Constant(c, w) => {
+                return write!(f, "{}'b{}", w, binary_format(*w, *c));
+            }
+            Wire::Independent { signal, idx } => {
+                let name = signal.as_str();
+                if idx.low() == 0 && idx.len() == self.signal(signal).length {
+                    return write!(f, "{}", name);
+                }
+                if idx.len() == 1 {
+                    return write!(f, "{}[{}]", name, idx.low());
+                }
+                return write!(f, "{}[{}:{}]", name, idx.high(), idx.low());
+            }
+            Wire::Multiple { signal, idxs } => {
+                let name = signal.as_str();
+                write!(f, "{{")?;
+                for (i, idx) in idxs.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    if idx.len() == 1 {
+                        write!(f, "{}[{}]", name, idx.low())?;
+                    } else {
+                        write!(f, "{}[{}:{}]", name, idx.high(), idx.low())?;
+                    }
+                }
+                return write!(f, "}}");
+            }
+            Wire::Compose { wires } => {
+                if wires.len() == 1 {
+                    return self.write_wire(f, &wires[0]);
+                }
+                write!(f, "{{")?;
+                for (i, w) in wires.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    self.write_wire(f, w)?;
+                }
+                return write!(f, "}}");
+            }
+        }
+    }
+
+    /// operand of an operator, parenthesized unless it is a single wire
+    fn write_operand(&self, f: &mut fmt::Formatter<'_>, tree: &LogicTree) -> fmt::Result {
+        match &**tree {
+            LogicElem::Unit(_) | LogicElem::Combine(_) | LogicElem::Param(_) => {
+                return self.write_logic(f, tree);
+            }
+            _ => {
+                write!(f, "(")?;
+                self.write_logic(f, tree)?;
+                return write!(f, ")");
+            }
+        }
+    }
+
+    fn write_binary(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        lhs: &LogicTree,
+        op: &str,
+        rhs: &LogicTree,
+    ) -> fmt::Result {
+        self.write_operand(f, lhs)?;
+        write!(f, " {} ", op)?;
+        return self.write_operand(f, rhs);
+    }
+
+    fn write_logic(&self, f: &mut fmt::Formatter<'_>, tree: &LogicTree) -> fmt::Result {
+        match &**tree {
+            LogicElem::Unit(w) => {
+                return self.write_wire(f, w);
+            }
+            LogicElem::Param(name) => {
+                return write!(f, "{}", name);
+            }
+            LogicElem::Combine(trees) => {
+                write!(f, "{{")?;
+                for (i, t) in trees.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    self.write_logic(f, t)?;
+                }
+                return write!(f, "}}");
+            }
+            LogicElem::BitNot(t) => {
+                write!(f, "~")?;
+                return self.write_operand(f, t);
+            }
+            LogicElem::LogicNot(t) => {
+                write!(f, "!")?;
+                return self.write_operand(f, t);
+            }
+            LogicElem::BitAnd(a, b) => self.write_binary(f, a, "&", b),
+            LogicElem::BitOr(a, b) => self.write_binary(f, a, "|", b),
+            LogicElem::BitXor(a, b) => self.write_binary(f, a, "^", b),
+            LogicElem::LogicAnd(a, b) => self.write_binary(f, a, "&&", b),
+            LogicElem::LogicOr(a, b) => self.write_binary(f, a, "||", b),
+            LogicElem::LogicXor(a, b) => {
+                // verilog has no logical xor, compare the reduced operands instead
+                write!(f, "(|")?;
+                self.write_operand(f, a)?;
+                write!(f, ") ^ (|")?;
+                self.write_operand(f, b)?;
+                return write!(f, ")");
+            }
+            LogicElem::TernaryCond(c, t, e) => {
+                self.write_operand(f, c)?;
+                write!(f, " ? ")?;
+                self.write_operand(f, t)?;
+                write!(f, " : ")?;
+                return self.write_operand(f, e);
+            }
+            LogicElem::LogicShiftLeft(t, n) => {
+                self.write_operand(f, t)?;
+                return write!(f, " << {}", n);
+            }
+            LogicElem::LogicShiftRight(t, n) => {
+                self.write_operand(f, t)?;
+                return write!(f, " >> {}", n);
+            }
+            LogicElem::Add(a, b) => self.write_binary(f, a, "+", b),
+            LogicElem::Sub(a, b) => self.write_binary(f, a, "-", b),
+            LogicElem::Mul(a, b) => self.write_binary(f, a, "*", b),
+            LogicElem::Div(a, b) => self.write_binary(f, a, "/", b),
+            LogicElem::Equal(a, b) => self.write_binary(f, a, "==", b),
+            LogicElem::NotEqual(a, b) => self.write_binary(f, a, "!=", b),
+            LogicElem::GreateThan(a, b) => self.write_binary(f, a, ">", b),
+            LogicElem::GreateThanEqual(a, b) => self.write_binary(f, a, ">=", b),
+            LogicElem::LessThan(a, b) => self.write_binary(f, a, "<", b),
+            LogicElem::LessThanEqual(a, b) => self.write_binary(f, a, "<=", b),
+        }
+    }
+}
+
+impl<'a> Display for VerilogPrinter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inputs = self.module.get_inputs();
+        let outputs = self.module.get_outputs();
+        let ports: Vec<(&str, &SignalKey)> = inputs
+            .iter()
+            .map(|k| ("input ", k))
+            .chain(outputs.iter().map(|k| ("output", k)))
+            .collect();
+
+        writeln!(f, "module {} (", self.name)?;
+        for (i, (dir, key)) in ports.iter().enumerate() {
+            let sep = if i + 1 == ports.len() { "" } else { "," };
+            self.write_doc(f, "    ", self.signal(key))?;
+            writeln!(f, "    {} {}{}", dir, self.declare(self.signal(key)), sep)?;
+        }
+        writeln!(f, ");")?;
+
+        // a param with don't care bits only ever compares masked, so it is never referred to
+        let params: Vec<&(String, Literal)> = self
+            .module
+            .get_params()
+            .iter()
+            .filter(|(_, value)| value.known())
+            .collect();
+        if !params.is_empty() {
+            writeln!(f)?;
+            for (name, value) in params {
+                writeln!(f, "localparam {} = {};", name, value)?;
+            }
+        }
+
+        let mut signals: Vec<&Signal> = self.module.get_signals().values().collect();
+        signals.sort_by(|a, b| a.key.as_str().cmp(b.key.as_str()));
+
+        writeln!(f)?;
+        for signal in signals
+            .iter()
+            .filter(|s| !inputs.contains(&s.key) && !outputs.contains(&s.key))
+        {
+            self.write_doc(f, "", signal)?;
+            writeln!(f, "{};", self.declare(signal))?;
+        }
+
+        writeln!(f)?;
+        for signal in &signals {
+            match &signal.from {
+                SignalSource::Wire(w) => {
+                    write!(f, "assign {} = ", signal.key.as_str())?;
+                    self.write_wire(f, w)?;
+                    writeln!(f, ";")?;
+                }
+                SignalSource::Logic(t) => {
+                    write!(f, "assign {} = ", signal.key.as_str())?;
+                    self.write_logic(f, t)?;
+                    writeln!(f, ";")?;
+                }
+                SignalSource::Unconnected | SignalSource::Input => {}
+            }
+        }
+
+        writeln!(f)?;
+        return writeln!(f, "endmodule");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verilog_model::WireIndex;
+
+    #[test]
+    fn emit_module() {
+        let mut module = Module::new();
+        module.new_input("i_instr".into(), 32).unwrap();
+        module.new_output("o_imm".into(), 12).unwrap();
+        let instr = module.get_signals()["i_instr"].clone();
+        let opcode: LogicTree = LogicElem::Unit(instr.range(0..7).unwrap()).into();
+        let is_op: LogicTree = LogicElem::Unit(Wire::bit(7, 0b0110011)).into();
+        module
+            .add_signal(Signal::new(
+                "is_op".into(),
+                1,
+                SignalSource::Logic(opcode.equal(is_op)),
+            ))
+            .unwrap();
+        module.get_signals_mut().get_mut("o_imm").unwrap().from =
+            SignalSource::Wire(Wire::compose(vec![
+                instr.single(31).unwrap(),
+                instr
+                    .multiple(vec![WireIndex::new(7, 7), WireIndex::new(30, 25)])
+                    .unwrap(),
+                Wire::bit(4, 0),
+            ]));
+        module.document("i_instr", "instruction word\nhttps://riscv.org");
+        module.document("is_op", " OP major opcode ");
+        module.document("is_op", "OP major opcode");
+
+        let text = format!("{}", VerilogPrinter::new(&module, "top", Dialect::Verilog));
+        assert_eq!(
+            text,
+            "module top (\n    \
+             // instruction word\n    \
+             // https://riscv.org\n    \
+             input  wire [31:0] i_instr,\n    \
+             output wire [11:0] o_imm\n\
+             );\n\n\
+             // OP major opcode\n\
+             wire is_op;\n\n\
+             assign is_op = i_instr[6:0] == 7'b0110011;\n\
+             assign o_imm = {i_instr[31], {i_instr[7], i_instr[30:25]}, 4'b0000};\n\n\
+             endmodule\n"
+        );
+        assert_eq!("RV32I", module_name("RV32I"));
+        assert_eq!("Zicsr__draft_", module_name("Zicsr (draft)"));
+        assert_eq!("_32bit", module_name("32bit"));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::backend::Dialect;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::PathBuf;
+
+pub struct Options {
+    pub input: PathBuf,
+    /// empty for every sheet of the workbook
+    pub sheets: Vec<String>,
+    /// output directory, or output file when it is not a directory
+    pub output: PathBuf,
+    pub target: Dialect,
+    /// decode hidden and struck through table rows too
+    pub include_disabled: bool,
+    /// copy of the workbook with a report sheet of the generated signals
+    pub annotate: Option<PathBuf>,
+    pub verbose: u8,
+}
+
+fn target(name: &str) -> Result<Dialect, String> {
+    return match name {
+        "verilog" | "v" => Ok(Dialect::Verilog),
+        "systemverilog" | "sv" => Ok(Dialect::SystemVerilog),
+        _ => Err(format!("unknown target `{}`", name)),
+    };
+}
+
+fn command() -> Command<'static> {
+    return Command::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Generate decoder logic from spreadsheet tables")
+        .arg(
+            Arg::new("input")
+                .help("Input workbook: .xlsx, .ods, .fods, or a .csv/.tsv table")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("sheet")
+                .short('s')
+                .long("sheet")
+                .help("Sheet to generate, may be repeated, every sheet by default")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Output directory, or output file for a single sheet")
+                .default_value(".")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("target")
+                .short('t')
+                .long("target")
+                .help("Target backend: verilog, systemverilog")
+                .default_value("verilog")
+                .value_parser(target),
+        )
+        .arg(
+            Arg::new("include-disabled")
+                .long("include-disabled")
+                .help("Decode hidden and struck through table rows too")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("annotate")
+                .long("annotate")
+                .value_name("FILE")
+                .help("Also write a copy of the workbook with a report sheet of the generated signals")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print table details, repeat for more")
+                .action(ArgAction::Count),
+        );
+}
+
+fn options(matches: &ArgMatches) -> Options {
+    return Options {
+        input: matches.get_one::<PathBuf>("input").unwrap().clone(),
+        sheets: matches
+            .get_many::<String>("sheet")
+            .map(|sheets| sheets.cloned().collect())
+            .unwrap_or_default(),
+        output: matches.get_one::<PathBuf>("output").unwrap().clone(),
+        target: *matches.get_one::<Dialect>("target").unwrap(),
+        include_disabled: matches.get_flag("include-disabled"),
+        annotate: matches.get_one::<PathBuf>("annotate").cloned(),
+        verbose: *matches.get_one::<u8>("verbose").unwrap(),
+    };
+}
+
+pub fn parse() -> Options {
+    return options(&command().get_matches());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_options() {
+        let parse = |args: &[&str]| {
+            let matches = command().try_get_matches_from(args)?;
+            Ok::<Options, clap::Error>(options(&matches))
+        };
+        let opts = parse(&["sheet_logic", "in.xlsx"]).unwrap();
+        assert_eq!(PathBuf::from("in.xlsx"), opts.input);
+        assert!(opts.sheets.is_empty());
+        assert_eq!(PathBuf::from("."), opts.output);
+        assert_eq!(Dialect::Verilog, opts.target);
+        assert!(!opts.include_disabled);
+        assert_eq!(None, opts.annotate);
+        assert_eq!(0, opts.verbose);
+
+        let opts = parse(&[
+            "sheet_logic",
+            "in.xlsx",
+            "-s",
+            "RV32I",
+            "-s",
+            "RV32M",
+            "-t",
+            "sv",
+            "-vv",
+            "-o",
+            "out",
+        ])
+        .unwrap();
+        assert_eq!(vec!["RV32I", "RV32M"], opts.sheets);
+        assert_eq!(PathBuf::from("out"), opts.output);
+        assert_eq!(Dialect::SystemVerilog, opts.target);
+        assert_eq!(2, opts.verbose);
+
+        assert!(parse(&["sheet_logic", "in.xlsx", "-t", "vhdl"]).is_err());
+        assert!(parse(&["sheet_logic"]).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use quick_xml::{self, events::Event, Reader};
+
+use super::{get_xml_attribute_string, CellPosition, ExcelError};
+
+/// (cell, text) of the notes in a comments part like `xl/comments1.xml`
+pub fn comments(part: &str, xml: &str) -> Result<Vec<(CellPosition, String)>, ExcelError> {
+    let mut comments = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    // whitespace of runs is significant, like in shared strings
+    reader.trim_text(false);
+    let mut buf = Vec::with_capacity(64);
+
+    let mut cell: Option<CellPosition> = None;
+    let mut selected = false;
+    let mut phonetic = false;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => match e.name() {
+                b"comment" => {
+                    let r = get_xml_attribute_string(e, b"ref").unwrap_or_default();
+                    cell = match CellPosition::parse(&r) {
+                        Some(pos) => Some(pos),
+                        None => return Err(ExcelError::BadCellReference(r)),
+                    };
+                    text.clear();
+                }
+                b"rPh" => phonetic = true,
+                b"t" => selected = cell.is_some() && !phonetic,
+                _ => {}
+            },
+            Ok(Event::End(ref e)) => match e.name() {
+                b"comment" => {
+                    if let Some(pos) = cell.take() {
+                        comments.push((pos, text.trim().to_string()));
+                    }
+                }
+                b"rPh" => phonetic = false,
+                b"t" => selected = false,
+                _ => {}
+            },
+            Ok(Event::Text(e)) if selected => {
+                let t = e
+                    .unescape_and_decode(&reader)
+                    .map_err(|e| ExcelError::Xml {
+                        part: part.into(),
+                        position: reader.buffer_position(),
+                        error: e,
+                    })?;
+                text.push_str(&t);
+            }
+            Ok(Event::Eof) => {
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ExcelError::Xml {
+                    part: part.into(),
+                    position: reader.buffer_position(),
+                    error: e,
+                });
+            }
+        }
+        buf.clear();
+    }
+    return Ok(comments);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sheet_comments() {
+        let xml = r#"<comments><authors><author>hart</author></authors><commentList>
+<comment ref="B8" authorId="0"><text><r><rPr><b/></rPr><t>hart:</t></r><r><t xml:space="preserve">
+see unprivileged spec 2.4</t></r></text></comment>
+<comment ref="A1" authorId="0"><text><t>input</t></text></comment>
+</commentList></comments>"#;
+        let comments = comments("xl/comments1.xml", xml).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    CellPosition::new("B8"),
+                    "hart:\nsee unprivileged spec 2.4".into()
+                ),
+                (CellPosition::new("A1"), "input".into())
+            ],
+            comments
+        );
+    }
+}
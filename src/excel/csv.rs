@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use super::{CellPosition, CellRange, CellValue, ExcelError, Row, Sheet};
+
+/// a cell continuing the cell to its left, like a horizontal merge
+pub const CSV_MERGE_LEFT: &str = "<<";
+/// a cell continuing the cell above, like a vertical merge
+pub const CSV_MERGE_UP: &str = "^^";
+
+/// plain text table, one sheet named after the file
+pub struct Csv {
+    name: String,
+    content: String,
+    delimiter: char,
+}
+
+/// a field of a record, quoted fields are never merge markers
+#[derive(Default)]
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+#[allow(dead_code)]
+impl Csv {
+    /// `.tsv` and `.tab` files are tab separated, anything else comma separated
+    pub fn open(file: &str) -> Result<Csv, ExcelError> {
+        let path = Path::new(file);
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let delimiter = match path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .as_deref()
+        {
+            Some("tsv") | Some("tab") => '\t',
+            _ => ',',
+        };
+        return Ok(Csv::from_content(
+            &name,
+            fs::read_to_string(file)?,
+            delimiter,
+        ));
+    }
+
+    pub fn from_content(name: &str, content: String, delimiter: char) -> Csv {
+        return Csv {
+            name: name.into(),
+            content,
+            delimiter,
+        };
+    }
+
+    pub fn sheets(&self) -> Vec<String> {
+        return vec![self.name.clone()];
+    }
+
+    /// a cell followed by `<<` cells and with `^^` cells below it is merged over all of them,
+    /// the rows under a merge continue every column of it
+    pub fn sheet(&self, name: &str) -> Result<Sheet, ExcelError> {
+        if name != self.name {
+            return Err(ExcelError::UnknownSheet(name.into()));
+        }
+        let records = self.records()?;
+        let marker = |r: usize, c: usize| -> Option<&str> {
+            return records
+                .get(r)
+                .and_then(|record| record.get(c))
+                .filter(|f| !f.quoted && (f.text == CSV_MERGE_LEFT || f.text == CSV_MERGE_UP))
+                .map(|f| f.text.as_str());
+        };
+
+        let mut sheet = Sheet::default();
+        let mut covered: HashSet<(usize, usize)> = HashSet::new();
+        let mut merges = Vec::new();
+        for (ridx, record) in records.iter().enumerate() {
+            let mut cells = Vec::new();
+            for (cidx, field) in record.iter().enumerate() {
+                if let Some(m) = marker(ridx, cidx) {
+                    if !covered.contains(&(ridx, cidx)) {
+                        return Err(ExcelError::BadMergeRange(format!(
+                            "`{}` at {}",
+                            m,
+                            CellPosition::from_tuple((ridx as u32, cidx as u16))
+                        )));
+                    }
+                    continue;
+                }
+                if !field.text.is_empty() {
+                    cells.push((cidx as u16, CellValue::Text(Rc::new(field.text.clone()))));
+                }
+
+                let mut width = 1;
+                while marker(ridx, cidx + width) == Some(CSV_MERGE_LEFT) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while marker(ridx + height, cidx) == Some(CSV_MERGE_UP)
+                    && (1..width).all(|i| marker(ridx + height, cidx + i).is_some())
+                {
+                    height += 1;
+                }
+                if width > 1 || height > 1 {
+                    for r in ridx..ridx + height {
+                        for c in cidx..cidx + width {
+                            covered.insert((r, c));
+                        }
+                    }
+                    merges.push(CellRange {
+                        begin: (ridx as u32, cidx as u16).into(),
+                        end: ((ridx + height - 1) as u32, (cidx + width - 1) as u16).into(),
+                    });
+                }
+            }
+            sheet.push_row(Row::new(ridx as u32, cells));
+        }
+        for range in &merges {
+            sheet.merge(range)?;
+        }
+        return Ok(sheet);
+    }
+
+    /// quoted fields may hold delimiters, newlines and `""` for a quote
+    fn records(&self) -> Result<Vec<Vec<Field>>, ExcelError> {
+        let content = self
+            .content
+            .strip_prefix('\u{feff}')
+            .unwrap_or(&self.content);
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = Field::default();
+        let mut in_quote = false;
+        let mut line: usize = 1;
+        let mut quote_line: usize = 0;
+
+        let mut chars = content.chars().peekable();
+        while let Some(c) = chars.next() {
+            if in_quote {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.text.push('"');
+                    }
+                    '"' => in_quote = false,
+                    _ => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        field.text.push(c);
+                    }
+                }
+                continue;
+            }
+            match c {
+                '"' if field.text.is_empty() && !field.quoted => {
+                    in_quote = true;
+                    field.quoted = true;
+                    quote_line = line;
+                }
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' => {
+                    line += 1;
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                _ if c == self.delimiter => {
+                    record.push(std::mem::take(&mut field));
+                }
+                _ => field.text.push(c),
+            }
+        }
+        if in_quote {
+            return Err(ExcelError::UnterminatedQuote { line: quote_line });
+        }
+        // the last line may miss its newline
+        if !record.is_empty() || !field.text.is_empty() || field.quoted {
+            record.push(field);
+            records.push(record);
+        }
+        return Ok(records);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_table() {
+        let content =
+            "#input,[31:0]i_instr,,\r\n#match,opcode,<<,<<,funct3\n,\"a,\"\"b\"\"\",\"<<\"\n\n";
+        let csv = Csv::from_content("RV32I", content.into(), ',');
+        assert_eq!(vec!["RV32I"], csv.sheets());
+        let sheet = csv.sheet("RV32I").unwrap();
+        assert_eq!(4, sheet.rows());
+        assert_eq!("[31:0]i_instr", sheet.content(0, 1).unwrap().0.as_str());
+        let (content, merged) = sheet.content(1, 3).unwrap();
+        assert_eq!("opcode", content.as_str());
+        let merged = merged.unwrap();
+        assert_eq!(CellPosition { row: 0, col: 2 }, merged.offset);
+        assert_eq!(CellPosition { row: 1, col: 3 }, merged.size);
+        assert_eq!("funct3", sheet.content(1, 4).unwrap().0.as_str());
+        assert_eq!("a,\"b\"", sheet.content(2, 1).unwrap().0.as_str());
+        assert_eq!("<<", sheet.content(2, 2).unwrap().0.as_str());
+
+        let tsv = Csv::from_content("t", "#end\t<<\n".into(), '\t');
+        assert_eq!(
+            Some(&CellValue::Text(Rc::new("#end".into()))),
+            tsv.sheet("t").unwrap().value(0, 1)
+        );
+        let grid = Csv::from_content("t", "a,<<,b\n^^,^^,c\n^^,<<\n".into(), ',');
+        let sheet = grid.sheet("t").unwrap();
+        let (content, merged) = sheet.content(2, 1).unwrap();
+        assert_eq!("a", content.as_str());
+        assert_eq!(CellPosition { row: 3, col: 2 }, merged.unwrap().size);
+        assert_eq!("c", sheet.content(1, 2).unwrap().0.as_str());
+        for bad in ["<<,a\n", "a\n,^^\n", "a,<<\n^^\n"] {
+            let bad = Csv::from_content("t", bad.into(), ',');
+            assert!(matches!(bad.sheet("t"), Err(ExcelError::BadMergeRange(_))));
+        }
+        let bad = Csv::from_content("t", "a\n\"b\n".into(), ',');
+        assert!(matches!(
+            bad.sheet("t"),
+            Err(ExcelError::UnterminatedQuote { line: 2 })
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::io;
+use zip::result::ZipError;
+
+#[derive(Debug)]
+pub enum ExcelError {
+    Io(io::Error),
+    Zip(ZipError),
+    /// part of the package is missing, like `xl/workbook.xml`
+    MissingPart(String),
+    /// malformed xml in a part at byte offset
+    Xml {
+        part: String,
+        position: usize,
+        error: quick_xml::Error,
+    },
+    BadCellReference(String),
+    SharedStringOutOfRange {
+        idx: String,
+        len: usize,
+    },
+    BadMergeRange(String),
+    /// `<v>` does not match the type of its cell
+    BadCellValue(String),
+    UnknownSheet(String),
+    /// sheet name excel does not accept, or one the workbook already has
+    BadSheetName(String),
+    /// relationship id referenced by a part is not in its relationships part
+    MissingRelationship {
+        part: String,
+        id: String,
+    },
+    /// quoted field of a csv table still open at the end of the file
+    UnterminatedQuote {
+        line: usize,
+    },
+}
+
+impl fmt::Display for ExcelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ExcelError::Io(e) => write!(f, "{}", e),
+            ExcelError::Zip(e) => write!(f, "{}", e),
+            ExcelError::MissingPart(part) => write!(f, "missing part `{}`", part),
+            ExcelError::Xml {
+                part,
+                position,
+                error,
+            } => write!(f, "bad xml in `{}` at {}: {}", part, position, error),
+            ExcelError::BadCellReference(r) => write!(f, "bad cell reference `{}`", r),
+            ExcelError::SharedStringOutOfRange { idx, len } => write!(
+                f,
+                "shared string `{}` out of range, {} shared strings",
+                idx, len
+            ),
+            ExcelError::BadMergeRange(r) => write!(f, "bad merge range `{}`", r),
+            ExcelError::BadCellValue(v) => write!(f, "bad cell value `{}`", v),
+            ExcelError::UnknownSheet(name) => write!(f, "no sheet named `{}`", name),
+            ExcelError::BadSheetName(name) => write!(f, "bad sheet name `{}`", name),
+            ExcelError::MissingRelationship { part, id } => {
+                write!(f, "missing relationship `{}` of `{}`", id, part)
+            }
+            ExcelError::UnterminatedQuote { line } => {
+                write!(f, "unterminated quote starting at line {}", line)
+            }
+        };
+    }
+}
+
+impl std::error::Error for ExcelError {}
+
+impl From<io::Error> for ExcelError {
+    fn from(e: io::Error) -> Self {
+        return ExcelError::Io(e);
+    }
+}
+
+impl From<ZipError> for ExcelError {
+    fn from(e: ZipError) -> Self {
+        return ExcelError::Zip(e);
+    }
+}
@@ -1,125 +1,358 @@
 use quick_xml::{self, events::Event, Reader};
-use std::fs;
-use std::io::{Cursor, Read};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
 use std::rc::Rc;
-use zip::ZipArchive;
+use zip::{read::ZipFile, result::ZipError, ZipArchive};
 
-use super::{get_xml_attribute, Sheet};
+use super::{
+    cell_fonts, comments, get_xml_attribute, get_xml_attribute_string, relationships, rels_part,
+    DefinedName, ExcelError, Font, Sheet, SheetRows,
+};
+
+/// (display name, worksheet part)
+type SheetPart = (String, String);
 
 #[allow(dead_code)]
-pub struct Excel {
+pub struct Excel<R: Read + Seek> {
     shared_strings: Vec<Rc<String>>,
-    archive: ZipArchive<Cursor<Vec<u8>>>,
+    /// font of each cell format
+    fonts: Vec<Font>,
+    pub(super) sheets: Vec<SheetPart>,
+    names: Vec<DefinedName>,
+    pub(super) archive: ZipArchive<R>,
+}
+
+impl Excel<BufReader<File>> {
+    pub fn open(file: &str) -> Result<Excel<BufReader<File>>, ExcelError> {
+        return Excel::from_reader(BufReader::new(File::open(file)?));
+    }
 }
 
 #[allow(dead_code)]
-impl Excel {
-    pub fn open(file: &str) -> Excel {
-        let reader = std::io::Cursor::new(fs::read(file).expect(file));
-        let mut archive = ZipArchive::new(reader).expect(file);
-        return Excel {
-            shared_strings: Excel::shared_strings(&mut archive),
+impl<R: Read + Seek> Excel<R> {
+    /// parts are read from `reader` when they are needed, not loaded up front
+    pub fn from_reader(reader: R) -> Result<Excel<R>, ExcelError> {
+        let mut archive = ZipArchive::new(reader)?;
+        let (sheets, names) = Excel::workbook(&mut archive)?;
+        return Ok(Excel {
+            shared_strings: Excel::shared_strings(&mut archive)?,
+            fonts: Excel::fonts(&mut archive)?,
+            sheets,
+            names,
             archive,
+        });
+    }
+
+    fn get_part<'a>(
+        archive: &'a mut ZipArchive<R>,
+        path: &str,
+    ) -> Result<BufReader<ZipFile<'a>>, ExcelError> {
+        return match archive.by_name(path) {
+            Ok(doc) => Ok(BufReader::new(doc)),
+            Err(ZipError::FileNotFound) => Err(ExcelError::MissingPart(path.into())),
+            Err(e) => Err(e.into()),
         };
     }
 
-    fn get_xml(archive: &mut ZipArchive<Cursor<Vec<u8>>>, path: &str) -> String {
-        let mut doc = archive.by_name(path).expect(path);
+    pub(super) fn get_xml(archive: &mut ZipArchive<R>, path: &str) -> Result<String, ExcelError> {
         let mut content = String::new();
-        doc.read_to_string(&mut content).unwrap();
-        return content;
+        Excel::get_part(archive, path)?.read_to_string(&mut content)?;
+        return Ok(content);
     }
 
-    fn shared_strings(archive: &mut ZipArchive<Cursor<Vec<u8>>>) -> Vec<Rc<String>> {
-        let content = Excel::get_xml(archive, "xl/sharedStrings.xml");
-        let mut reader = Reader::from_str(&content);
-        reader.trim_text(true);
-
-        let mut buf = Vec::with_capacity(64);
+    fn shared_strings(archive: &mut ZipArchive<R>) -> Result<Vec<Rc<String>>, ExcelError> {
+        let part = "xl/sharedStrings.xml";
+        let content = match Excel::get_part(archive, part) {
+            Ok(content) => content,
+            // a workbook without any text has no shared strings
+            Err(ExcelError::MissingPart(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        return parse_shared_strings(part, content);
+    }
 
-        let vec_len = match reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) if e.name() == b"sst" => get_xml_attribute(e, b"count"),
-            _ => None,
-        }
-        .and_then(|attr| unsafe { std::str::from_utf8_unchecked(&attr).parse::<usize>().ok() });
+    fn fonts(archive: &mut ZipArchive<R>) -> Result<Vec<Font>, ExcelError> {
+        let part = "xl/styles.xml";
+        return match Excel::get_xml(archive, part) {
+            Ok(xml) => cell_fonts(part, &xml),
+            // every cell has the default format
+            Err(ExcelError::MissingPart(_)) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        };
+    }
 
-        let mut shared_strings: Vec<Rc<String>> = Vec::with_capacity(match vec_len {
-            Some(len) => len,
-            None => 128,
-        });
+    /// (display name, worksheet part) of every sheet, following the workbook relationships,
+    /// and the defined names
+    fn workbook(
+        archive: &mut ZipArchive<R>,
+    ) -> Result<(Vec<SheetPart>, Vec<DefinedName>), ExcelError> {
+        let part = "xl/workbook.xml";
+        let content = Excel::get_xml(archive, part)?;
+        let rels_part = rels_part(part);
+        let rels = relationships(part, &Excel::get_xml(archive, &rels_part)?)?;
 
-        let mut selected: bool = false;
+        let mut sheets = Vec::new();
+        let mut names = Vec::new();
+        // (name, localSheetId) of the `<definedName>` being read
+        let mut name: Option<(String, Option<usize>)> = None;
+        let mut reader = Reader::from_str(&content);
+        reader.trim_text(true);
 
+        let mut buf = Vec::with_capacity(64);
         loop {
             match reader.read_event(&mut buf) {
-                Ok(Event::Start(ref e)) => match e.name() {
-                    b"t" => {
-                        selected = true;
+                Ok(Event::Start(ref e)) if e.name() == b"definedName" => {
+                    name = Some((
+                        get_xml_attribute_string(e, b"name").unwrap_or_default(),
+                        get_xml_attribute_string(e, b"localSheetId")
+                            .and_then(|id| id.parse::<usize>().ok()),
+                    ));
+                }
+                Ok(Event::Text(ref e)) if name.is_some() => {
+                    let definition =
+                        e.unescape_and_decode(&reader)
+                            .map_err(|e| ExcelError::Xml {
+                                part: part.into(),
+                                position: reader.buffer_position(),
+                                error: e,
+                            })?;
+                    let (name, local) = name.take().unwrap();
+                    // names of formulas are not something a table can use
+                    if let Some(value) = DefinedName::parse_value(&definition) {
+                        names.push((name, local, value));
                     }
-                    _ => {}
-                },
-                Ok(Event::End(ref e)) => match e.name() {
-                    b"t" => {
-                        selected = false;
+                }
+                Ok(Event::End(ref e)) if e.name() == b"definedName" => name = None,
+                Ok(Event::Empty(ref e)) => match e.name() {
+                    b"sheet" => {
+                        let name = get_xml_attribute_string(e, b"name").unwrap_or_default();
+                        // the relationship namespace prefix is usually, but not always, `r`
+                        let id = e
+                            .attributes()
+                            .flatten()
+                            .find(|a| a.key == b"r:id" || a.key.ends_with(b":id"))
+                            .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+                            .unwrap_or_default();
+                        match rels.get(&id) {
+                            Some(rel) => sheets.push((name, rel.target.clone())),
+                            None => {
+                                return Err(ExcelError::MissingRelationship {
+                                    part: rels_part,
+                                    id,
+                                })
+                            }
+                        }
                     }
                     _ => {}
                 },
-                Ok(Event::Text(e)) => {
-                    if selected {
-                        let value = Rc::new(String::from_utf8(e.to_vec()).unwrap());
-                        shared_strings.push(value);
-                    }
-                }
                 Ok(Event::Eof) => {
                     break;
                 }
                 Ok(_) => {}
                 Err(e) => {
-                    panic!("{} {:?}", reader.buffer_position(), e);
+                    return Err(ExcelError::Xml {
+                        part: part.into(),
+                        position: reader.buffer_position(),
+                        error: e,
+                    });
                 }
             }
             buf.clear();
         }
-        return shared_strings;
+
+        // local names refer to sheets by their position in the workbook
+        let names = names
+            .into_iter()
+            .filter_map(|(name, local, value)| {
+                let scope = match local {
+                    Some(id) => Some(sheets.get(id)?.0.clone()),
+                    None => None,
+                };
+                Some(DefinedName { name, scope, value })
+            })
+            .collect();
+        return Ok((sheets, names));
     }
 
-    pub fn sheets(&mut self) -> Vec<String> {
-        let mut sheets = Vec::new();
-        let content = Excel::get_xml(&mut self.archive, "xl/workbook.xml");
+    pub fn names(&self) -> &[DefinedName] {
+        return &self.names;
+    }
 
-        let mut reader = Reader::from_str(&content);
-        reader.trim_text(true);
+    /// display names of the sheets, in workbook order
+    pub fn sheets(&self) -> Vec<String> {
+        return self.sheets.iter().map(|(name, _)| name.clone()).collect();
+    }
 
-        let mut buf = Vec::with_capacity(64);
-        loop {
-            match reader.read_event(&mut buf) {
-                Ok(Event::Empty(ref e)) => match e.name() {
-                    b"sheet" => {
-                        if let Some(a) = get_xml_attribute(e, b"name") {
-                            sheets.push(String::from_utf8(a.to_vec()).unwrap().to_lowercase());
-                        };
+    fn sheet_part(&self, sheet: &str) -> Result<String, ExcelError> {
+        return match self.sheets.iter().find(|(name, _)| name == sheet) {
+            Some((_, part)) => Ok(part.clone()),
+            None => Err(ExcelError::UnknownSheet(sheet.into())),
+        };
+    }
+
+    /// rows of a sheet read lazily from the package, for tables too large to hold at once,
+    /// `sheet` gathers them into sparse rows
+    pub fn rows(
+        &mut self,
+        sheet: &str,
+    ) -> Result<SheetRows<'_, BufReader<ZipFile<'_>>>, ExcelError> {
+        let part = self.sheet_part(sheet)?;
+        let source = Excel::get_part(&mut self.archive, &part)?;
+        return Ok(SheetRows::new(
+            &part,
+            source,
+            &self.shared_strings,
+            &self.fonts,
+        ));
+    }
+
+    /// cells of a sheet with the comments and hyperlinks found through its relationships
+    pub fn sheet(&mut self, sheet: &str) -> Result<Sheet, ExcelError> {
+        let part = self.sheet_part(sheet)?;
+        let rels_part = rels_part(&part);
+        let rels = match Excel::get_xml(&mut self.archive, &rels_part) {
+            Ok(xml) => relationships(&part, &xml)?,
+            // a sheet without comments, links or drawings has no relationships
+            Err(ExcelError::MissingPart(_)) => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut rows = self.rows(sheet)?;
+        let mut cells = Sheet::from_rows(&mut rows)?;
+        for link in rows.hyperlinks() {
+            let target = match (&link.id, &link.location) {
+                (Some(id), location) => match (rels.get(id), location) {
+                    (Some(rel), Some(location)) => format!("{}#{}", rel.target, location),
+                    (Some(rel), None) => rel.target.clone(),
+                    (None, _) => {
+                        return Err(ExcelError::MissingRelationship {
+                            part: rels_part,
+                            id: id.clone(),
+                        })
                     }
-                    _ => {}
                 },
-                Ok(Event::Eof) => {
-                    break;
-                }
-                Ok(_) => {}
-                Err(e) => {
-                    panic!("{} {:?}", reader.buffer_position(), e);
+                (None, Some(location)) => format!("#{}", location),
+                (None, None) => continue,
+            };
+            let target = Rc::new(target);
+            // only cells of the sheet, a link may cover whole columns
+            let rows = cells.rows();
+            for row in link.range.rows().take_while(|row| (*row as usize) < rows) {
+                let cols = cells.cols(row as usize);
+                for col in link.range.cols().take_while(|col| (*col as usize) < cols) {
+                    cells.links.insert((row, col).into(), target.clone());
                 }
             }
-            buf.clear();
         }
+        drop(rows);
+
+        for rel in rels
+            .values()
+            .filter(|r| r.rel_type == "comments" && !r.external)
+        {
+            let xml = Excel::get_xml(&mut self.archive, &rel.target)?;
+            for (pos, text) in comments(&rel.target, &xml)? {
+                cells.comments.insert(pos, Rc::new(text));
+            }
+        }
+        return Ok(cells);
+    }
+}
+
+/// one shared string per `<si>`, rich text runs are concatenated and phonetic runs skipped
+fn parse_shared_strings<B: BufRead>(part: &str, content: B) -> Result<Vec<Rc<String>>, ExcelError> {
+    let mut reader = Reader::from_reader(content);
+    // whitespace of runs is significant, like `<t xml:space="preserve"> </t>`
+    reader.trim_text(false);
+
+    let mut buf = Vec::with_capacity(64);
+    let mut shared_strings: Vec<Rc<String>> = Vec::new();
 
-        return sheets;
+    let mut selected: bool = false;
+    let mut phonetic: bool = false;
+    let mut value = String::new();
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => match e.name() {
+                b"sst" => {
+                    let count = get_xml_attribute(e, b"uniqueCount")
+                        .or_else(|| get_xml_attribute(e, b"count"))
+                        .and_then(|a| String::from_utf8_lossy(&a).parse::<usize>().ok());
+                    // the count is only a hint, do not trust it with the memory
+                    shared_strings.reserve(count.unwrap_or(128).min(1 << 16));
+                }
+                b"si" => {
+                    value.clear();
+                }
+                b"rPh" => {
+                    phonetic = true;
+                }
+                b"t" => {
+                    selected = !phonetic;
+                }
+                _ => {}
+            },
+            Ok(Event::End(ref e)) => match e.name() {
+                b"si" => {
+                    shared_strings.push(Rc::new(value.clone()));
+                }
+                b"rPh" => {
+                    phonetic = false;
+                }
+                b"t" => {
+                    selected = false;
+                }
+                _ => {}
+            },
+            Ok(Event::Empty(ref e)) if e.name() == b"si" => {
+                shared_strings.push(Rc::new(String::new()));
+            }
+            Ok(Event::Text(e)) => {
+                if selected {
+                    let text = e
+                        .unescape_and_decode(&reader)
+                        .map_err(|e| ExcelError::Xml {
+                            part: part.into(),
+                            position: reader.buffer_position(),
+                            error: e,
+                        })?;
+                    value.push_str(&text);
+                }
+            }
+            Ok(Event::Eof) => {
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ExcelError::Xml {
+                    part: part.into(),
+                    position: reader.buffer_position(),
+                    error: e,
+                });
+            }
+        }
+        buf.clear();
     }
+    return Ok(shared_strings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn sheet(&mut self, sheet: &str) -> Sheet {
-        let sheet_xml = Excel::get_xml(
-            &mut self.archive,
-            format!("xl/worksheets/{}.xml", sheet).as_str(),
-        );
-        return Sheet::from_xml(&sheet_xml, &self.shared_strings);
+    #[test]
+    fn shared_strings_rich_text() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<sst count="4" uniqueCount="4">
+<si><t>#input</t></si>
+<si><r><rPr><b/></rPr><t>imm</t></r><r><t xml:space="preserve">[11:0] </t></r><rPh sb="0" eb="1"><t>x</t></rPh></si>
+<si><t/></si>
+<si><t>rd</t></si>
+</sst>"#;
+        let ss = parse_shared_strings("xl/sharedStrings.xml", xml.as_bytes()).unwrap();
+        let ss: Vec<&str> = ss.iter().map(|s| s.as_str()).collect();
+        assert_eq!(vec!["#input", "imm[11:0] ", "", "rd"], ss);
     }
 }
@@ -0,0 +1,532 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, tag_no_case, take_while, take_while1},
+    character::complete::{alpha1, char, digit1, multispace0},
+    combinator::{all_consuming, map, map_opt, opt, recognize, value},
+    multi::{fold_many0, separated_list0},
+    number::complete::double,
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult,
+};
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use super::{cell_position_parser::sheet_name, CellPosition, CellValue};
+
+/// a cell of a formula, `$` fixes the row or the column when the formula is copied
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormulaCell {
+    pub pos: CellPosition,
+    pub col_fixed: bool,
+    pub row_fixed: bool,
+}
+
+/// the part of the excel formula language encodings are written in
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Reference {
+        sheet: Option<String>,
+        begin: FormulaCell,
+        end: FormulaCell,
+    },
+    /// upper case name without the `_xlfn.` prefix of newer functions
+    Call(String, Vec<Formula>),
+    /// `&`, `+`, `-`, `*` or `/`
+    Binary(char, Box<Formula>, Box<Formula>),
+    Negate(Box<Formula>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormulaError {
+    /// not a formula this evaluator reads
+    Syntax,
+    /// a function, or a reference into another sheet
+    Unsupported(String),
+    /// `#VALUE!`, an operand of the wrong type
+    Value,
+    /// `#NUM!`, like a number too wide for the places asked for
+    Num,
+    /// `#DIV/0!`
+    Div0,
+    /// a cell referred to is a formula not evaluated yet
+    Pending(CellPosition),
+}
+
+impl Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            FormulaError::Syntax => write!(f, "not understood"),
+            FormulaError::Unsupported(what) => write!(f, "`{}` is not supported", what),
+            FormulaError::Value => write!(f, "#VALUE!"),
+            FormulaError::Num => write!(f, "#NUM!"),
+            FormulaError::Div0 => write!(f, "#DIV/0!"),
+            FormulaError::Pending(pos) => write!(f, "{} has no value", pos),
+        };
+    }
+}
+
+mod formula_parser {
+    use super::*;
+
+    fn ws<'a, O>(
+        inner: impl FnMut(&'a str) -> IResult<&'a str, O>,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+        return delimited(multispace0, inner, multispace0);
+    }
+
+    /// `"7'b"` with `""` as an escaped quote
+    fn text(input: &str) -> IResult<&str, Formula> {
+        return map(
+            delimited(
+                char('"'),
+                fold_many0(
+                    alt((is_not("\""), value("\"", tag("\"\"")))),
+                    String::new,
+                    |mut text, s| {
+                        text.push_str(s);
+                        text
+                    },
+                ),
+                char('"'),
+            ),
+            Formula::Text,
+        )(input);
+    }
+
+    fn cell(input: &str) -> IResult<&str, FormulaCell> {
+        return map_opt(
+            tuple((opt(char('$')), alpha1, opt(char('$')), digit1)),
+            |(col_fixed, col, row_fixed, row): (Option<char>, &str, Option<char>, &str)| {
+                CellPosition::parse(&format!("{}{}", col, row)).map(|pos| FormulaCell {
+                    pos,
+                    col_fixed: col_fixed.is_some(),
+                    row_fixed: row_fixed.is_some(),
+                })
+            },
+        )(input);
+    }
+
+    fn reference(input: &str) -> IResult<&str, Formula> {
+        return map(
+            tuple((
+                opt(terminated(sheet_name, char('!'))),
+                cell,
+                opt(preceded(char(':'), cell)),
+            )),
+            |(sheet, begin, end)| Formula::Reference {
+                sheet,
+                end: end.unwrap_or_else(|| begin.clone()),
+                begin,
+            },
+        )(input);
+    }
+
+    fn call(input: &str) -> IResult<&str, Formula> {
+        let name = recognize(pair(
+            take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'),
+            take_while(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.'),
+        ));
+        return map(
+            pair(
+                terminated(name, ws(char('('))),
+                terminated(separated_list0(char(','), expression), char(')')),
+            ),
+            |(name, args)| {
+                let name = name.to_uppercase();
+                let name = name.strip_prefix("_XLFN.").unwrap_or(&name).to_string();
+                Formula::Call(name, args)
+            },
+        )(input);
+    }
+
+    fn primary(input: &str) -> IResult<&str, Formula> {
+        return ws(alt((
+            text,
+            call,
+            value(Formula::Bool(true), tag_no_case("TRUE")),
+            value(Formula::Bool(false), tag_no_case("FALSE")),
+            reference,
+            map(double, Formula::Number),
+            delimited(char('('), expression, char(')')),
+            map(preceded(char('-'), primary), |f| {
+                Formula::Negate(Box::new(f))
+            }),
+            preceded(char('+'), primary),
+        )))(input);
+    }
+
+    /// left associative operators of one precedence level
+    fn binary<'a>(
+        ops: &'static str,
+        operand: fn(&'a str) -> IResult<&'a str, Formula>,
+        input: &'a str,
+    ) -> IResult<&'a str, Formula> {
+        let (input, first) = operand(input)?;
+        return fold_many0(
+            pair(ws(nom::character::complete::one_of(ops)), operand),
+            move || first.clone(),
+            |lhs, (op, rhs)| Formula::Binary(op, Box::new(lhs), Box::new(rhs)),
+        )(input);
+    }
+
+    fn term(input: &str) -> IResult<&str, Formula> {
+        return binary("*/", primary, input);
+    }
+
+    fn sum(input: &str) -> IResult<&str, Formula> {
+        return binary("+-", term, input);
+    }
+
+    pub fn expression(input: &str) -> IResult<&str, Formula> {
+        return binary("&", sum, input);
+    }
+
+    /// the text of `<f>`, with or without the leading `=`
+    pub fn formula(input: &str) -> Option<Formula> {
+        return match all_consuming(preceded(ws(opt(char('='))), expression))(input) {
+            Ok((_, formula)) => Some(formula),
+            Err(_) => None,
+        };
+    }
+}
+
+impl Display for FormulaCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let col_fixed = if self.col_fixed { "$" } else { "" };
+        let row_fixed = if self.row_fixed { "$" } else { "" };
+        return write!(
+            f,
+            "{}{}{}{}",
+            col_fixed,
+            CellPosition::column_name(self.pos.col),
+            row_fixed,
+            self.pos.row + 1
+        );
+    }
+}
+
+impl Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // operands that are operations themselves are parenthesized, whatever the precedence
+        let operand = |f: &mut fmt::Formatter<'_>, formula: &Formula| match formula {
+            Formula::Binary(..) => write!(f, "({})", formula),
+            _ => write!(f, "{}", formula),
+        };
+        return match self {
+            Formula::Text(text) => write!(f, "\"{}\"", text.replace('"', "\"\"")),
+            Formula::Number(n) => write!(f, "{}", CellValue::Number(*n)),
+            Formula::Bool(b) => write!(f, "{}", CellValue::Bool(*b)),
+            Formula::Reference { sheet, begin, end } => {
+                if let Some(sheet) = sheet {
+                    write!(f, "'{}'!", sheet.replace('\'', "''"))?;
+                }
+                if begin == end {
+                    return write!(f, "{}", begin);
+                }
+                write!(f, "{}:{}", begin, end)
+            }
+            Formula::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Formula::Binary(op, lhs, rhs) => {
+                operand(f, lhs)?;
+                write!(f, "{}", op)?;
+                operand(f, rhs)
+            }
+            Formula::Negate(formula) => {
+                write!(f, "-")?;
+                operand(f, formula)
+            }
+        };
+    }
+}
+
+/// value of a cell of the sheet, `None` for an empty cell
+pub type CellLookup<'a> = dyn Fn(&CellPosition) -> Result<Option<CellValue>, FormulaError> + 'a;
+
+#[allow(dead_code)]
+impl Formula {
+    pub fn parse(text: &str) -> Option<Formula> {
+        return formula_parser::formula(text);
+    }
+
+    /// the formula copied `rows` down and `cols` right, like the cells of a shared formula,
+    /// `None` when a reference leaves the sheet
+    pub fn shifted(&self, rows: i64, cols: i64) -> Option<Formula> {
+        let shift = |cell: &FormulaCell| {
+            let row = if cell.row_fixed { 0 } else { rows };
+            let col = if cell.col_fixed { 0 } else { cols };
+            let row = u32::try_from(cell.pos.row as i64 + row).ok()?;
+            let col = u16::try_from(cell.pos.col as i64 + col)
+                .ok()
+                .filter(|col| (*col as u32) < CellPosition::MAX_COLS)?;
+            Some(FormulaCell {
+                pos: (row, col).into(),
+                ..cell.clone()
+            })
+        };
+        return Some(match self {
+            Formula::Reference { sheet, begin, end } => Formula::Reference {
+                sheet: sheet.clone(),
+                begin: shift(begin)?,
+                end: shift(end)?,
+            },
+            Formula::Call(name, args) => Formula::Call(
+                name.clone(),
+                args.iter()
+                    .map(|arg| arg.shifted(rows, cols))
+                    .collect::<Option<Vec<_>>>()?,
+            ),
+            Formula::Binary(op, lhs, rhs) => Formula::Binary(
+                *op,
+                Box::new(lhs.shifted(rows, cols)?),
+                Box::new(rhs.shifted(rows, cols)?),
+            ),
+            Formula::Negate(formula) => Formula::Negate(Box::new(formula.shifted(rows, cols)?)),
+            _ => self.clone(),
+        });
+    }
+
+    /// values of the cells of an operand, a range gives its cells in row order
+    fn values(&self, cell: &CellLookup) -> Result<Vec<CellValue>, FormulaError> {
+        return match self {
+            Formula::Reference { sheet: Some(_), .. } => {
+                Err(FormulaError::Unsupported(self.to_string()))
+            }
+            Formula::Reference {
+                sheet: None,
+                begin,
+                end,
+            } => {
+                let mut values = Vec::new();
+                for row in begin.pos.row..=end.pos.row {
+                    for col in begin.pos.col..=end.pos.col {
+                        let value = cell(&(row, col).into())?;
+                        values
+                            .push(value.unwrap_or_else(|| CellValue::Text(Rc::new(String::new()))));
+                    }
+                }
+                Ok(values)
+            }
+            _ => Ok(vec![self.evaluate(cell)?]),
+        };
+    }
+
+    pub fn evaluate(&self, cell: &CellLookup) -> Result<CellValue, FormulaError> {
+        return match self {
+            Formula::Text(text) => Ok(CellValue::Text(Rc::new(text.clone()))),
+            Formula::Number(n) => Ok(CellValue::Number(*n)),
+            Formula::Bool(b) => Ok(CellValue::Bool(*b)),
+            Formula::Reference { .. } => {
+                let mut values = self.values(cell)?;
+                match values.len() {
+                    1 => Ok(values.remove(0)),
+                    _ => Err(FormulaError::Value),
+                }
+            }
+            Formula::Negate(formula) => Ok(CellValue::Number(-number(&formula.evaluate(cell)?)?)),
+            Formula::Binary('&', lhs, rhs) => Ok(CellValue::Text(Rc::new(format!(
+                "{}{}",
+                lhs.evaluate(cell)?,
+                rhs.evaluate(cell)?
+            )))),
+            Formula::Binary(op, lhs, rhs) => {
+                let lhs = number(&lhs.evaluate(cell)?)?;
+                let rhs = number(&rhs.evaluate(cell)?)?;
+                match op {
+                    '+' => Ok(CellValue::Number(lhs + rhs)),
+                    '-' => Ok(CellValue::Number(lhs - rhs)),
+                    '*' => Ok(CellValue::Number(lhs * rhs)),
+                    _ if rhs == 0.0 => Err(FormulaError::Div0),
+                    _ => Ok(CellValue::Number(lhs / rhs)),
+                }
+            }
+            Formula::Call(name, args) => match name.as_str() {
+                "CONCAT" | "CONCATENATE" => {
+                    let mut text = String::new();
+                    for arg in args {
+                        for value in arg.values(cell)? {
+                            text.push_str(&value.to_string());
+                        }
+                    }
+                    Ok(CellValue::Text(Rc::new(text)))
+                }
+                _ => match radix(name) {
+                    Some((from, to)) => {
+                        let args = args
+                            .iter()
+                            .map(|arg| arg.evaluate(cell))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        convert(from, to, &args)
+                    }
+                    None => Err(FormulaError::Unsupported(name.clone())),
+                },
+            },
+        };
+    }
+}
+
+fn number(value: &CellValue) -> Result<f64, FormulaError> {
+    return match value {
+        CellValue::Number(n) => Ok(*n),
+        CellValue::Bool(b) => Ok(*b as u8 as f64),
+        CellValue::Text(text) if text.trim().is_empty() => Ok(0.0),
+        CellValue::Text(text) => text.trim().parse::<f64>().map_err(|_| FormulaError::Value),
+    };
+}
+
+/// (from, to) radix of the conversion functions, like `DEC2BIN`
+fn radix(name: &str) -> Option<(u32, u32)> {
+    let radix = |name: &str| match name {
+        "BIN" => Some(2),
+        "DEC" => Some(10),
+        "HEX" => Some(16),
+        _ => None,
+    };
+    let (from, to) = name.split_once('2')?;
+    return match (radix(from)?, radix(to)?) {
+        (from, to) if from != to => Some((from, to)),
+        _ => None,
+    };
+}
+
+/// `DEC2BIN(number, [places])` and the like, with the limits of excel:
+/// binary and hexadecimal of 10 digits with the top bit set are negative,
+/// a result that does not fit 10 digits or more than 10 places are `#NUM!`
+fn convert(from: u32, to: u32, args: &[CellValue]) -> Result<CellValue, FormulaError> {
+    let (value, places) = match args {
+        [value] => (value, None),
+        [value, places] => (value, Some(number(places)?.trunc())),
+        _ => return Err(FormulaError::Value),
+    };
+    if let Some(places) = places {
+        if !(0.0..=10.0).contains(&places) {
+            return Err(FormulaError::Num);
+        }
+    }
+    let n: i128 = match from {
+        10 => number(value)?.trunc() as i128,
+        _ => {
+            let digits = value.to_string();
+            let digits = digits.trim();
+            if digits.is_empty() || digits.len() > 10 {
+                return Err(FormulaError::Num);
+            }
+            let n = i128::from_str_radix(digits, from).map_err(|_| FormulaError::Num)?;
+            let full = (from as i128).pow(10);
+            if digits.len() == 10 && n >= full / 2 {
+                n - full
+            } else {
+                n
+            }
+        }
+    };
+    if to == 10 {
+        return Ok(CellValue::Number(n as f64));
+    }
+    // 10 digits of the target radix, the upper half is for negative numbers
+    let half = (to as i128).pow(10) / 2;
+    if n < -half || n >= half {
+        return Err(FormulaError::Num);
+    }
+    // negative numbers take all 10 digits, whatever the places
+    let digits = match (n < 0, to) {
+        (true, 2) => format!("{:b}", n + (2i128).pow(10)),
+        (true, _) => format!("{:X}", n + (16i128).pow(10)),
+        (false, 2) => format!("{:b}", n),
+        (false, _) => format!("{:X}", n),
+    };
+    return match places {
+        Some(places) if n >= 0 => {
+            if (places as usize) < digits.len() {
+                return Err(FormulaError::Num);
+            }
+            Ok(CellValue::Text(Rc::new(format!(
+                "{:0>width$}",
+                digits,
+                width = places as usize
+            ))))
+        }
+        _ => Ok(CellValue::Text(Rc::new(digits))),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_formula() {
+        let cell = |pos: &CellPosition| match pos.to_string().as_str() {
+            "A5" => Ok(Some(CellValue::Number(51.0))),
+            "B5" => Ok(Some(CellValue::Text(Rc::new("3".into())))),
+            "C5" => Err(FormulaError::Pending(pos.clone())),
+            _ => Ok(None),
+        };
+        let eval = |text: &str| Formula::parse(text).unwrap().evaluate(&cell);
+        let text = |s: &str| Ok(CellValue::Text(Rc::new(s.into())));
+
+        assert_eq!(text("7'b0110011"), eval("=\"7'b\"&DEC2BIN(A5,7)"));
+        assert_eq!(
+            text("7'b0110011"),
+            eval("=_xlfn.CONCAT(\"7'b\", dec2bin($A$5, 7))")
+        );
+        assert_eq!(
+            text("3'b011"),
+            eval("=CONCATENATE(B5,\"'b\",HEX2BIN(B5,B5))")
+        );
+        assert_eq!(text("33"), eval("BIN2HEX(\"110011\")"));
+        assert_eq!(text("1111111101"), eval("DEC2BIN(-3)"));
+        assert_eq!(Ok(CellValue::Number(-3.0)), eval("BIN2DEC(\"1111111101\")"));
+        assert_eq!(text("a\"b3"), eval("\"a\"\"b\"&(1+2*1)"));
+        assert_eq!(text("513x"), eval("CONCAT(A4:B5,\"x\")"));
+        assert_eq!(Err(FormulaError::Num), eval("DEC2BIN(A5,3)"));
+        for out_of_range in [
+            "DEC2BIN(1,1e12)",
+            "DEC2BIN(1,11)",
+            "DEC2BIN(1,-1)",
+            "DEC2BIN(512)",
+            "DEC2BIN(-513)",
+            "HEX2BIN(\"200\")",
+            "HEX2BIN(\"FFFFFFFDFF\")",
+            "DEC2HEX(549755813888)",
+            "DEC2HEX(-549755813889)",
+        ] {
+            assert_eq!(
+                Err(FormulaError::Num),
+                eval(out_of_range),
+                "{}",
+                out_of_range
+            );
+        }
+        assert_eq!(text("1000000000"), eval("DEC2BIN(-512)"));
+        assert_eq!(text("0111111111"), eval("DEC2BIN(511,10)"));
+        assert_eq!(text("1000000000"), eval("HEX2BIN(\"FFFFFFFE00\")"));
+        assert_eq!(text("8000000000"), eval("DEC2HEX(-549755813888)"));
+        assert_eq!(Err(FormulaError::Div0), eval("A5/Z9"));
+        assert!(matches!(eval("C5&\"\""), Err(FormulaError::Pending(_))));
+        assert!(matches!(
+            eval("Sheet2!A1"),
+            Err(FormulaError::Unsupported(_))
+        ));
+        assert!(matches!(eval("SUM(A5)"), Err(FormulaError::Unsupported(_))));
+        assert_eq!(None, Formula::parse("=\"7'b\"&"));
+
+        let formula = Formula::parse("=\"x\"&DEC2BIN(A5+$B$5,A$1)").unwrap();
+        assert_eq!(
+            "\"x\"&DEC2BIN(A7+$B$5,A$1)",
+            formula.shifted(2, 0).unwrap().to_string()
+        );
+        assert_eq!(None, formula.shifted(0, -1));
+    }
+}
@@ -1,10 +1,34 @@
+mod comment;
+mod csv;
+mod error;
 mod excel;
-mod sheet;
+mod formula;
+mod name;
+mod ods;
 mod position;
+mod relationship;
+mod sheet;
+mod source;
+mod stream;
+mod style;
+mod workbook;
+mod writer;
 
+pub use comment::*;
+pub use csv::*;
+pub use error::*;
 pub use excel::*;
-pub use sheet::*;
+pub use formula::*;
+pub use name::*;
+pub use ods::*;
 pub use position::*;
+pub use relationship::*;
+pub use sheet::*;
+pub use source::*;
+pub use stream::*;
+pub use style::*;
+pub use workbook::*;
+pub use writer::*;
 
 use quick_xml::events::BytesStart;
 use quick_xml::{self};
@@ -21,3 +45,11 @@ fn get_xml_attribute<'a>(e: &'a BytesStart, key: &[u8]) -> Option<Cow<'a, [u8]>>
     }
     return None;
 }
+
+/// unescaped attribute value
+fn get_xml_attribute_string(e: &BytesStart, key: &[u8]) -> Option<String> {
+    return get_xml_attribute(e, key).map(|a| match quick_xml::escape::unescape(&a) {
+        Ok(a) => String::from_utf8_lossy(&a).into_owned(),
+        Err(_) => String::from_utf8_lossy(&a).into_owned(),
+    });
+}
@@ -0,0 +1,81 @@
+use super::CellReference;
+
+/// what a defined name stands for
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameValue {
+    Reference(CellReference),
+    /// a text or number given in the definition itself
+    Constant(String),
+}
+
+/// `<definedName>` of a workbook, or a named range of an OpenDocument spreadsheet
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefinedName {
+    pub name: String,
+    /// sheet the name is local to, `None` for a name of the whole workbook
+    pub scope: Option<String>,
+    pub value: NameValue,
+}
+
+#[allow(dead_code)]
+impl DefinedName {
+    /// `Sheet1!$A$1:$C$1`, `"0110011"` or `7`, with or without a leading `=`,
+    /// formulas and broken references like `#REF!` give `None`
+    pub fn parse_value(definition: &str) -> Option<NameValue> {
+        let definition = definition.trim();
+        let definition = definition.strip_prefix('=').unwrap_or(definition).trim();
+        if let Some(r) = CellReference::parse(definition) {
+            return Some(NameValue::Reference(r));
+        }
+        if let Some(text) = definition
+            .strip_prefix('"')
+            .and_then(|text| text.strip_suffix('"'))
+        {
+            // quotes inside are doubled, a lone one ends the text early
+            let parts: Vec<&str> = text.split("\"\"").collect();
+            if parts.iter().any(|part| part.contains('"')) {
+                return None;
+            }
+            return Some(NameValue::Constant(parts.join("\"")));
+        }
+        if definition.parse::<f64>().is_ok() {
+            return Some(NameValue::Constant(definition.into()));
+        }
+        return None;
+    }
+
+    /// names are looked up without regard to case, like excel does
+    pub fn key(name: &str) -> String {
+        return name.to_uppercase();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defined_name_value() {
+        assert_eq!(
+            Some(NameValue::Reference(
+                CellReference::parse("Shared!A1:C1").unwrap()
+            )),
+            DefinedName::parse_value("Shared!$A$1:$C$1")
+        );
+        assert_eq!(
+            Some(NameValue::Constant("0110011".into())),
+            DefinedName::parse_value("=\"0110011\"")
+        );
+        assert_eq!(
+            Some(NameValue::Constant("say \"hi\"".into())),
+            DefinedName::parse_value("\"say \"\"hi\"\"\"")
+        );
+        assert_eq!(
+            Some(NameValue::Constant("7".into())),
+            DefinedName::parse_value("7")
+        );
+        assert_eq!(None, DefinedName::parse_value("#REF!"));
+        assert_eq!(None, DefinedName::parse_value("SUM(Shared!A1:A3)"));
+        assert_eq!(None, DefinedName::parse_value("\"a\"b\""));
+    }
+}
@@ -0,0 +1,686 @@
+use quick_xml::{
+    self,
+    events::{BytesStart, Event},
+    Reader,
+};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::rc::Rc;
+use zip::{result::ZipError, ZipArchive};
+
+use super::{
+    CellPosition, CellRange, CellValue, DefinedName, ExcelError, Font, NameValue, Row, Sheet,
+};
+
+/// OpenDocument spreadsheet, either packaged `.ods` or flat `.fods`
+pub struct Ods {
+    part: String,
+    content: String,
+    sheets: Vec<String>,
+    /// fonts of the cell styles, by style name
+    fonts: HashMap<String, Font>,
+    names: Vec<DefinedName>,
+}
+
+/// unescaped value of an attribute by its local name, whatever its namespace prefix is
+fn get_ods_attribute(e: &BytesStart, local: &[u8]) -> Option<String> {
+    return e
+        .attributes()
+        .flatten()
+        .find(|a| a.key.rsplit(|c| *c == b':').next() == Some(local))
+        .map(|a| match a.unescaped_value() {
+            Ok(v) => String::from_utf8_lossy(&v).into_owned(),
+            Err(_) => String::from_utf8_lossy(&a.value).into_owned(),
+        });
+}
+
+/// `$Shared.$A$1:.$C$1` as an excel reference, `'Shared'!$A$1:$C$1`
+fn ods_address(address: &str) -> String {
+    let mut sheet: Option<&str> = None;
+    let mut cells = Vec::new();
+    for part in address.split(':') {
+        let cell = match part.rsplit_once('.') {
+            Some((name, cell)) => {
+                let name = name.trim_start_matches('$');
+                if sheet.is_none() && !name.is_empty() {
+                    sheet = Some(name);
+                }
+                cell
+            }
+            None => part,
+        };
+        cells.push(cell);
+    }
+    return match sheet {
+        Some(name) if name.starts_with('\'') => format!("{}!{}", name, cells.join(":")),
+        Some(name) => format!("'{}'!{}", name.replace('\'', "''"), cells.join(":")),
+        None => cells.join(":"),
+    };
+}
+
+/// `of:="0110011"` or `of:=[$Shared.$A$1]` of a `<table:named-expression>`
+fn ods_expression(expression: &str) -> Option<NameValue> {
+    let expression = expression.strip_prefix("of:").unwrap_or(expression);
+    let formula = expression.trim().trim_start_matches('=').trim();
+    return match formula
+        .strip_prefix('[')
+        .and_then(|address| address.strip_suffix(']'))
+    {
+        Some(address) => DefinedName::parse_value(&ods_address(address)),
+        None => DefinedName::parse_value(formula),
+    };
+}
+
+fn get_ods_count(e: &BytesStart, local: &[u8]) -> Result<u32, ExcelError> {
+    return match get_ods_attribute(e, local) {
+        Some(n) => match n.parse::<u32>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(ExcelError::BadCellValue(n)),
+        },
+        None => Ok(1),
+    };
+}
+
+/// `<table:table-cell>` being read
+struct OdsCell {
+    covered: bool,
+    value_type: Option<String>,
+    value: Option<String>,
+    repeat: u32,
+    /// (rows, cols)
+    span: (u32, u32),
+    text: String,
+    paragraphs: usize,
+    /// text of the `<office:annotation>` of the cell
+    comment: String,
+    comment_paragraphs: usize,
+    /// first `<text:a>` target of the cell
+    link: Option<String>,
+    font: Font,
+}
+
+/// cells, merges and notes of the `<table:table-row>` being read
+#[derive(Default)]
+struct OdsRow {
+    cells: Vec<(u16, CellValue)>,
+    /// (first col, (rows, cols)) of the merges starting in the row
+    merges: Vec<(u32, (u32, u32))>,
+    comments: Vec<(u16, Rc<String>)>,
+    links: Vec<(u16, Rc<String>)>,
+    fonts: Vec<(u16, Font)>,
+}
+
+impl OdsRow {
+    fn is_empty(&self) -> bool {
+        return self.cells.is_empty()
+            && self.merges.is_empty()
+            && self.comments.is_empty()
+            && self.links.is_empty();
+    }
+}
+
+impl OdsCell {
+    fn new(
+        e: &BytesStart,
+        covered: bool,
+        fonts: &HashMap<String, Font>,
+    ) -> Result<OdsCell, ExcelError> {
+        let value_type = get_ods_attribute(e, b"value-type");
+        let value = match value_type.as_deref() {
+            Some("boolean") => get_ods_attribute(e, b"boolean-value"),
+            _ => get_ods_attribute(e, b"value"),
+        };
+        return Ok(OdsCell {
+            covered,
+            value_type,
+            value,
+            repeat: get_ods_count(e, b"number-columns-repeated")?,
+            span: (
+                get_ods_count(e, b"number-rows-spanned")?,
+                get_ods_count(e, b"number-columns-spanned")?,
+            ),
+            text: String::new(),
+            paragraphs: 0,
+            comment: String::new(),
+            comment_paragraphs: 0,
+            link: None,
+            font: get_ods_attribute(e, b"style-name")
+                .and_then(|style| fonts.get(&style))
+                .cloned()
+                .unwrap_or_default(),
+        });
+    }
+
+    /// text of the cell, or of its annotation
+    fn text_mut(&mut self, annotation: bool) -> &mut String {
+        return match annotation {
+            true => &mut self.comment,
+            false => &mut self.text,
+        };
+    }
+
+    /// paragraphs are joined by newlines
+    fn paragraph(&mut self, annotation: bool) {
+        let (text, paragraphs) = match annotation {
+            true => (&mut self.comment, &mut self.comment_paragraphs),
+            false => (&mut self.text, &mut self.paragraphs),
+        };
+        if *paragraphs > 0 {
+            text.push('\n');
+        }
+        *paragraphs += 1;
+    }
+
+    /// covered cells are hidden by a merge, their content is not shown
+    fn value(&self) -> Result<Option<CellValue>, ExcelError> {
+        if self.covered {
+            return Ok(None);
+        }
+        let number = |v: &Option<String>| match v.as_deref().map(|v| v.parse::<f64>()) {
+            Some(Ok(n)) => Ok(Some(CellValue::Number(n))),
+            _ => Err(ExcelError::BadCellValue(v.clone().unwrap_or_default())),
+        };
+        return match self.value_type.as_deref() {
+            Some("float") | Some("percentage") | Some("currency") => number(&self.value),
+            Some("boolean") => match self.value.as_deref() {
+                Some("true") => Ok(Some(CellValue::Bool(true))),
+                Some("false") => Ok(Some(CellValue::Bool(false))),
+                v => Err(ExcelError::BadCellValue(v.unwrap_or_default().into())),
+            },
+            // strings, dates and times are taken as they are shown
+            Some(_) => Ok(Some(CellValue::Text(Rc::new(self.text.clone())))),
+            None if !self.text.is_empty() => Ok(Some(CellValue::Text(Rc::new(self.text.clone())))),
+            None => Ok(None),
+        };
+    }
+}
+
+#[allow(dead_code)]
+impl Ods {
+    /// `.fods` files are read as flat xml, anything else as a package
+    pub fn open(file: &str) -> Result<Ods, ExcelError> {
+        let flat = Path::new(file)
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("fods"))
+            .unwrap_or(false);
+        if flat {
+            return Ods::from_content(file, fs::read_to_string(file)?);
+        }
+        let mut archive = ZipArchive::new(BufReader::new(File::open(file)?))?;
+        let part = "content.xml";
+        let mut content = String::new();
+        match archive.by_name(part) {
+            Ok(mut doc) => doc.read_to_string(&mut content)?,
+            Err(ZipError::FileNotFound) => return Err(ExcelError::MissingPart(part.into())),
+            Err(e) => return Err(e.into()),
+        };
+        return Ods::from_content(part, content);
+    }
+
+    pub fn from_content(part: &str, content: String) -> Result<Ods, ExcelError> {
+        let mut sheets = Vec::new();
+        let mut fonts = HashMap::new();
+        let mut names = Vec::new();
+        // tables being read, named expressions inside a table are local to it
+        let mut tables: Vec<String> = Vec::new();
+        // name of the `<style:style>` of cells being read
+        let mut style: Option<String> = None;
+        let mut reader = Reader::from_str(&content);
+        let mut buf = Vec::with_capacity(64);
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok(Event::Start(ref e)) if e.local_name() == b"table" => {
+                    let name = get_ods_attribute(e, b"name").unwrap_or_default();
+                    sheets.push(name.clone());
+                    tables.push(name);
+                }
+                Ok(Event::Empty(ref e)) if e.local_name() == b"table" => {
+                    sheets.push(get_ods_attribute(e, b"name").unwrap_or_default());
+                }
+                Ok(Event::End(ref e)) if e.local_name() == b"table" => {
+                    tables.pop();
+                }
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
+                    if e.local_name() == b"named-range"
+                        || e.local_name() == b"named-expression" =>
+                {
+                    let value = match e.local_name() {
+                        b"named-range" => get_ods_attribute(e, b"cell-range-address")
+                            .and_then(|address| DefinedName::parse_value(&ods_address(&address))),
+                        _ => get_ods_attribute(e, b"expression")
+                            .and_then(|expression| ods_expression(&expression)),
+                    };
+                    if let (Some(name), Some(value)) = (get_ods_attribute(e, b"name"), value) {
+                        names.push(DefinedName {
+                            name,
+                            scope: tables.last().cloned(),
+                            value,
+                        });
+                    }
+                }
+                Ok(Event::Start(ref e)) if e.local_name() == b"style" => {
+                    style = get_ods_attribute(e, b"name").filter(|_| {
+                        get_ods_attribute(e, b"family").as_deref() == Some("table-cell")
+                    });
+                }
+                Ok(Event::End(ref e)) if e.local_name() == b"style" => style = None,
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e))
+                    if e.local_name() == b"text-properties" =>
+                {
+                    if let Some(style) = &style {
+                        let font = Font {
+                            bold: get_ods_attribute(e, b"font-weight").as_deref() == Some("bold"),
+                            italic: matches!(
+                                get_ods_attribute(e, b"font-style").as_deref(),
+                                Some("italic") | Some("oblique")
+                            ),
+                            strike: !matches!(
+                                get_ods_attribute(e, b"text-line-through-style").as_deref(),
+                                None | Some("none")
+                            ),
+                        };
+                        if !font.is_default() {
+                            fonts.insert(style.clone(), font);
+                        }
+                    }
+                }
+                Ok(Event::Eof) => {
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(ExcelError::Xml {
+                        part: part.into(),
+                        position: reader.buffer_position(),
+                        error: e,
+                    });
+                }
+            }
+            buf.clear();
+        }
+        return Ok(Ods {
+            part: part.into(),
+            content,
+            sheets,
+            fonts,
+            names,
+        });
+    }
+
+    /// names of the tables, in document order
+    pub fn sheets(&self) -> Vec<String> {
+        return self.sheets.clone();
+    }
+
+    pub fn names(&self) -> &[DefinedName] {
+        return &self.names;
+    }
+
+    pub fn sheet(&self, name: &str) -> Result<Sheet, ExcelError> {
+        if !self.sheets.iter().any(|s| s == name) {
+            return Err(ExcelError::UnknownSheet(name.into()));
+        }
+
+        let mut sheet = Sheet::default();
+        let mut merges = Vec::new();
+        let mut reader = Reader::from_str(&self.content);
+        let mut buf = Vec::with_capacity(64);
+
+        // nesting of <table:table> inside the selected one, 0 outside of it
+        let mut depth: usize = 0;
+        let mut row: u32 = 0;
+        let mut col: u32 = 0;
+        let mut row_repeat: u32 = 1;
+        let mut row_hidden = false;
+        // columns described by `<table:table-column>` so far
+        let mut columns: u32 = 0;
+        let mut row_content = OdsRow::default();
+        let mut cell: Option<OdsCell> = None;
+        let mut paragraph = false;
+        let mut annotation = false;
+
+        loop {
+            let event = reader.read_event(&mut buf);
+            let empty = matches!(event, Ok(Event::Empty(_)));
+            match event {
+                Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.local_name() {
+                    // a table nested in a cell of the selected one
+                    b"table" if depth > 0 && !empty => depth += 1,
+                    b"table"
+                        if depth == 0
+                            && !empty
+                            && get_ods_attribute(e, b"name").as_deref() == Some(name) =>
+                    {
+                        depth = 1;
+                    }
+                    b"table" => {}
+                    _ if depth != 1 => {}
+                    b"table-column" => {
+                        let repeat = get_ods_count(e, b"number-columns-repeated")?;
+                        if Ods::hidden(e) {
+                            let end = columns.saturating_add(repeat).min(CellPosition::MAX_COLS);
+                            sheet.hidden_cols.extend(columns as u16..end as u16);
+                        }
+                        columns = columns.saturating_add(repeat);
+                    }
+                    b"table-row" => {
+                        row_repeat = get_ods_count(e, b"number-rows-repeated")?;
+                        row_hidden = Ods::hidden(e);
+                        col = 0;
+                        if empty {
+                            row = row.saturating_add(row_repeat);
+                        }
+                    }
+                    b"table-cell" | b"covered-table-cell" => {
+                        let covered = e.local_name() == b"covered-table-cell";
+                        let c = OdsCell::new(e, covered, &self.fonts)?;
+                        match empty {
+                            true => col = Ods::place_cell(&c, row, col, &mut row_content)?,
+                            false => cell = Some(c),
+                        }
+                    }
+                    b"annotation" => annotation = !empty,
+                    b"p" | b"h" => {
+                        if let Some(c) = cell.as_mut() {
+                            c.paragraph(annotation);
+                        }
+                        paragraph = !empty;
+                    }
+                    b"s" => {
+                        if let Some(c) = cell.as_mut() {
+                            let n = get_ods_count(e, b"c")?;
+                            c.text_mut(annotation).push_str(&" ".repeat(n as usize));
+                        }
+                    }
+                    b"tab" => {
+                        if let Some(c) = cell.as_mut() {
+                            c.text_mut(annotation).push('\t');
+                        }
+                    }
+                    b"line-break" => {
+                        if let Some(c) = cell.as_mut() {
+                            c.text_mut(annotation).push('\n');
+                        }
+                    }
+                    b"a" if !annotation => {
+                        if let Some(c) = cell.as_mut() {
+                            if c.link.is_none() {
+                                c.link = get_ods_attribute(e, b"href");
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+
+                Ok(Event::Text(ref e)) if depth == 1 && paragraph => {
+                    let text = e
+                        .unescape_and_decode(&reader)
+                        .map_err(|e| ExcelError::Xml {
+                            part: self.part.clone(),
+                            position: reader.buffer_position(),
+                            error: e,
+                        })?;
+                    if let Some(c) = cell.as_mut() {
+                        c.text_mut(annotation).push_str(&text);
+                    }
+                }
+
+                Ok(Event::End(ref e)) => match e.local_name() {
+                    b"table" if depth > 1 => depth -= 1,
+                    b"table" if depth == 1 => {
+                        break;
+                    }
+                    _ if depth != 1 => {}
+                    b"annotation" => annotation = false,
+                    b"p" | b"h" => paragraph = false,
+                    b"table-cell" | b"covered-table-cell" => {
+                        if let Some(c) = cell.take() {
+                            col = Ods::place_cell(&c, row, col, &mut row_content)?;
+                        }
+                    }
+                    b"table-row" => {
+                        // repeated rows are only laid out when they hold something
+                        let content = std::mem::take(&mut row_content);
+                        if !content.is_empty() {
+                            for r in row..row.saturating_add(row_repeat) {
+                                sheet.push_row(Row {
+                                    row: r,
+                                    cells: content.cells.clone(),
+                                    hidden: row_hidden,
+                                    fonts: content.fonts.clone(),
+                                    formulas: Vec::new(),
+                                });
+                                for &(c, (rows, cols)) in &content.merges {
+                                    // a span over rows starts on the first of the repeated rows only
+                                    if rows > 1 && r != row {
+                                        continue;
+                                    }
+                                    merges.push(Ods::span(r, c, rows, cols)?);
+                                }
+                                for (c, comment) in &content.comments {
+                                    sheet.comments.insert((r, *c).into(), comment.clone());
+                                }
+                                for (c, link) in &content.links {
+                                    sheet.links.insert((r, *c).into(), link.clone());
+                                }
+                            }
+                        }
+                        row = row.saturating_add(row_repeat);
+                    }
+                    _ => {}
+                },
+
+                Ok(Event::Eof) => {
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(ExcelError::Xml {
+                        part: self.part.clone(),
+                        position: reader.buffer_position(),
+                        error: e,
+                    });
+                }
+            }
+            buf.clear();
+        }
+
+        // merges are laid over the cells once every row is placed
+        for range in &merges {
+            sheet.merge(range)?;
+        }
+        return Ok(sheet);
+    }
+
+    /// range of a cell spanning `rows` and `cols` from `(row, col)`, the range has to fit the sheet
+    fn span(row: u32, col: u32, rows: u32, cols: u32) -> Result<CellRange, ExcelError> {
+        let end_row = row.checked_add(rows - 1);
+        let end_col = col
+            .checked_add(cols - 1)
+            .filter(|c| *c < CellPosition::MAX_COLS);
+        return match (end_row, end_col) {
+            (Some(end_row), Some(end_col)) => Ok(CellRange {
+                begin: (row, col as u16).into(),
+                end: (end_row, end_col as u16).into(),
+            }),
+            _ => Err(ExcelError::BadMergeRange(format!(
+                "{}:{}x{}",
+                CellPosition::from_tuple((row, col as u16)),
+                rows,
+                cols
+            ))),
+        };
+    }
+
+    /// collapsed and filtered out rows and columns are both hidden
+    fn hidden(e: &BytesStart) -> bool {
+        return matches!(
+            get_ods_attribute(e, b"visibility").as_deref(),
+            Some("collapse") | Some("filter")
+        );
+    }
+
+    /// lay a possibly repeated cell out from `col`, gives the column after it
+    fn place_cell(
+        cell: &OdsCell,
+        row: u32,
+        col: u32,
+        content: &mut OdsRow,
+    ) -> Result<u32, ExcelError> {
+        let next = col.saturating_add(cell.repeat);
+        let value = cell.value()?;
+        let spanned = !cell.covered && cell.span != (1, 1);
+        let comment = Some(cell.comment.trim())
+            .filter(|c| !c.is_empty())
+            .map(|c| Rc::new(c.to_string()));
+        let link = cell.link.clone().map(Rc::new);
+        if value.is_some() || spanned || comment.is_some() || link.is_some() {
+            // trailing filler cells are repeated up to the last column, keep what fits
+            for c in col..next.min(CellPosition::MAX_COLS) {
+                if let Some(value) = &value {
+                    content.cells.push((c as u16, value.clone()));
+                }
+                if let Some(comment) = &comment {
+                    content.comments.push((c as u16, comment.clone()));
+                }
+                if let Some(link) = &link {
+                    content.links.push((c as u16, link.clone()));
+                }
+                if !cell.font.is_default() {
+                    content.fonts.push((c as u16, cell.font));
+                }
+                if spanned {
+                    Ods::span(row, c, cell.span.0, cell.span.1)?;
+                    content.merges.push((c, cell.span));
+                }
+            }
+        }
+        return Ok(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ods_table() {
+        let content = r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:automatic-styles><style:style style:name="ce1" style:family="table-cell"><style:text-properties style:text-line-through-style="solid" fo:font-weight="bold"/></style:style></office:automatic-styles>
+<office:body><office:spreadsheet>
+<table:table table:name="Other"><table:table-row><table:table-cell office:value-type="string"><text:p>x</text:p></table:table-cell></table:table-row></table:table>
+<table:table table:name="RV32I">
+<table:table-column table:visibility="collapse"/><table:table-column table:number-columns-repeated="1023"/>
+<table:table-row>
+<table:table-cell office:value-type="string" table:number-columns-spanned="2" table:number-rows-spanned="1"><text:p>#input</text:p><office:annotation><dc:creator>hart</dc:creator><text:p>see</text:p><text:p>spec</text:p></office:annotation></table:table-cell>
+<table:covered-table-cell office:value-type="string"><text:p>hidden</text:p></table:covered-table-cell>
+<table:table-cell office:value-type="float" office:value="7"><text:p><text:a xlink:href="https://riscv.org">7</text:a></text:p></table:table-cell>
+<table:table-cell table:number-columns-repeated="1020"/>
+</table:table-row>
+<table:table-row table:number-rows-repeated="2" table:visibility="collapse">
+<table:table-cell table:number-columns-repeated="2"/>
+<table:table-cell office:value-type="string"><text:p>a<text:s text:c="2"/>b</text:p><text:p>c</text:p></table:table-cell>
+<table:table-cell table:style-name="ce1" office:value-type="boolean" office:boolean-value="true"><text:p>TRUE</text:p></table:table-cell>
+</table:table-row>
+<table:table-row table:number-rows-repeated="1048572"><table:table-cell table:number-columns-repeated="1024"/></table:table-row>
+<table:named-expressions><table:named-range table:name="Flags" table:base-cell-address="$RV32I.$A$1" table:cell-range-address=".$C$2:.$D$3"/></table:named-expressions>
+</table:table>
+<table:named-expressions>
+<table:named-range table:name="Inputs" table:base-cell-address="$RV32I.$A$1" table:cell-range-address="$RV32I.$A$1:.$C$1"/>
+<table:named-expression table:name="OPCODE_OP" table:base-cell-address="$RV32I.$A$1" table:expression="of:=&quot;7'b0110011&quot;"/>
+</table:named-expressions>
+</office:spreadsheet></office:body></office:document-content>"#;
+        let ods = Ods::from_content("content.xml", content.into()).unwrap();
+        assert_eq!(vec!["Other", "RV32I"], ods.sheets());
+        let names: Vec<(&str, Option<&str>, String)> = ods
+            .names()
+            .iter()
+            .map(|n| {
+                let value = match &n.value {
+                    NameValue::Reference(r) => r.to_string(),
+                    NameValue::Constant(c) => c.clone(),
+                };
+                (n.name.as_str(), n.scope.as_deref(), value)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("Flags", Some("RV32I"), "C2:D3".to_string()),
+                ("Inputs", None, "RV32I!A1:C1".to_string()),
+                ("OPCODE_OP", None, "7'b0110011".to_string()),
+            ],
+            names
+        );
+        let sheet = ods.sheet("RV32I").unwrap();
+        assert_eq!(3, sheet.rows());
+        let (content, merged) = sheet.content(0, 1).unwrap();
+        assert_eq!("#input", content.as_str());
+        assert_eq!(CellPosition { row: 0, col: 1 }, merged.unwrap().offset);
+        assert_eq!(Some(&CellValue::Number(7.0)), sheet.value(0, 2));
+        assert_eq!(
+            "see\nspec",
+            sheet.comments[&CellPosition::new("A1")].as_str()
+        );
+        assert_eq!(
+            "https://riscv.org",
+            sheet.links[&CellPosition::new("C1")].as_str()
+        );
+        assert_eq!(3, sheet.cols(0));
+        for row in 1..3 {
+            assert_eq!(None, sheet.value(row, 1));
+            assert_eq!("a  b\nc", sheet.content(row, 2).unwrap().0.as_str());
+            assert_eq!(Some(&CellValue::Bool(true)), sheet.value(row, 3));
+            assert!(sheet.hidden_rows.contains(&(row as u32)));
+        }
+        assert_eq!(2, sheet.hidden_rows.len());
+        assert_eq!(vec![&0], sheet.hidden_cols.iter().collect::<Vec<_>>());
+        let font = sheet.fonts[&CellPosition::new("D2")];
+        assert!(font.strike && font.bold && !font.italic);
+        assert!(!sheet.fonts.contains_key(&CellPosition::new("C2")));
+        assert!(matches!(
+            ods.sheet("rv32i"),
+            Err(ExcelError::UnknownSheet(_))
+        ));
+    }
+
+    #[test]
+    fn ods_spans() {
+        // namespaces may have any prefix
+        let content = r#"<o:document-content xmlns:o="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:t="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:x="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<o:body><o:spreadsheet><t:table t:name="T">
+<t:table-row t:number-rows-repeated="2">
+<t:table-cell o:value-type="string" t:number-rows-spanned="2" t:number-columns-spanned="2"><x:p>op</x:p></t:table-cell>
+<t:covered-table-cell/><t:table-cell o:value-type="string" t:number-columns-spanned="2"><x:p>a</x:p></t:table-cell>
+</t:table-row>
+<t:table-row><t:table-cell o:value-type="string"><x:p>#end</x:p></t:table-cell></t:table-row>
+</t:table></o:spreadsheet></o:body></o:document-content>"#;
+        let ods = Ods::from_content("content.xml", content.into()).unwrap();
+        assert_eq!(vec!["T"], ods.sheets());
+        let sheet = ods.sheet("T").unwrap();
+        assert_eq!(3, sheet.rows());
+        // the span over rows is laid once, the span over columns on every repeated row
+        let (content, merged) = sheet.content(1, 1).unwrap();
+        assert_eq!("op", content.as_str());
+        assert_eq!(CellPosition { row: 1, col: 1 }, merged.unwrap().offset);
+        for row in 0..2 {
+            let (content, merged) = sheet.content(row, 3).unwrap();
+            assert_eq!("a", content.as_str());
+            assert_eq!(CellPosition { row: 0, col: 1 }, merged.unwrap().offset);
+        }
+        assert_eq!("#end", sheet.content(2, 0).unwrap().0.as_str());
+
+        for span in [
+            r#"t:number-rows-spanned="4294967295""#,
+            r#"t:number-columns-spanned="4294967295""#,
+        ] {
+            let content = format!(
+                r#"<t:table t:name="T"><t:table-row t:number-rows-repeated="2"/><t:table-row t:number-rows-repeated="3"><t:table-cell o:value-type="string" {}><x:p>x</x:p></t:table-cell></t:table-row></t:table>"#,
+                span
+            );
+            let ods = Ods::from_content("content.xml", content).unwrap();
+            assert!(matches!(ods.sheet("T"), Err(ExcelError::BadMergeRange(_))));
+        }
+    }
+}
@@ -2,37 +2,109 @@ use std::cmp::{Ordering, PartialOrd};
 use std::fmt::{self, Display};
 use std::ops::Range;
 
-mod cell_position_parser {
-    use super::{CellPosition, CellRange};
+pub(super) mod cell_position_parser {
+    use super::{CellPosition, CellRange, CellReference};
     use nom::{
-        bytes::complete::tag,
-        character::complete::{alpha1, u16 as uint16},
-        sequence::{pair, tuple},
+        branch::alt,
+        bytes::complete::{is_not, tag, take_while1},
+        character::complete::{alpha1, u32 as uint32},
+        combinator::{map, map_opt, opt, value},
+        multi::fold_many0,
+        sequence::{delimited, pair, preceded, separated_pair, terminated},
         IResult,
     };
 
-    fn parse_raw_tuple(t: (&str, u16)) -> CellPosition {
-        let col = t.0.chars().fold(0, |sum, c| {
-            (c.to_ascii_uppercase() as u16) - ('A' as u16) + sum
+    /// bijective base-26 column letters, `A` is 0, `Z` is 25, `AA` is 26, up to `XFD`
+    fn column_index(letters: &str) -> Option<u16> {
+        if letters.len() > 3 {
+            return None;
+        }
+        let col = letters.chars().fold(0_u32, |sum, c| {
+            sum * 26 + (c.to_ascii_uppercase() as u32 - 'A' as u32 + 1)
         });
-        return CellPosition { row: t.1 - 1, col };
+        return match col {
+            1..=CellPosition::MAX_COLS => Some((col - 1) as u16),
+            _ => None,
+        };
+    }
+
+    fn column(input: &str) -> IResult<&str, u16> {
+        map_opt(preceded(opt(tag("$")), alpha1), column_index)(input)
+    }
+
+    fn row(input: &str) -> IResult<&str, u32> {
+        map_opt(preceded(opt(tag("$")), uint32), |row: u32| {
+            row.checked_sub(1)
+        })(input)
+    }
+
+    fn cell_position(input: &str) -> IResult<&str, CellPosition> {
+        map(pair(column, row), |(col, row)| CellPosition { row, col })(input)
+    }
+
+    fn cell_range(input: &str) -> IResult<&str, CellRange> {
+        map(
+            separated_pair(cell_position, tag(":"), cell_position),
+            |(begin, end)| CellRange { begin, end },
+        )(input)
+    }
+
+    /// `'RV32I (base)'` with `''` as an escaped quote, or a plain `RV32I`
+    pub fn sheet_name(input: &str) -> IResult<&str, String> {
+        alt((
+            delimited(
+                tag("'"),
+                fold_many0(
+                    alt((is_not("'"), value("'", tag("''")))),
+                    String::new,
+                    |mut name, s| {
+                        name.push_str(s);
+                        name
+                    },
+                ),
+                tag("'"),
+            ),
+            map(
+                take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '.'),
+                String::from,
+            ),
+        ))(input)
     }
 
-    fn cell_position(input: &str) -> IResult<&str, (&str, u16)> {
-        pair(alpha1, uint16)(input)
+    fn reference(input: &str) -> IResult<&str, CellReference> {
+        map(
+            pair(
+                opt(terminated(sheet_name, tag("!"))),
+                alt((
+                    cell_range,
+                    map(cell_position, |pos| CellRange {
+                        begin: pos.clone(),
+                        end: pos,
+                    }),
+                )),
+            ),
+            |(sheet, range)| CellReference { sheet, range },
+        )(input)
     }
 
-    pub fn cell(pos: &str) -> CellPosition {
-        let (_, t) = cell_position(pos).expect(pos);
-        return parse_raw_tuple(t);
+    pub fn cell(pos: &str) -> Option<CellPosition> {
+        return match cell_position(pos) {
+            Ok(("", pos)) => Some(pos),
+            _ => None,
+        };
     }
 
-    pub fn range(range: &str) -> CellRange {
-        let (_, (begin, _, end)) =
-            tuple((cell_position, tag(":"), cell_position))(range).expect(range);
-        return CellRange {
-            begin: parse_raw_tuple(begin),
-            end: parse_raw_tuple(end),
+    pub fn range(range: &str) -> Option<CellRange> {
+        return match cell_range(range) {
+            Ok(("", range)) => Some(range),
+            _ => None,
+        };
+    }
+
+    pub fn cell_reference(r: &str) -> Option<CellReference> {
+        return match reference(r) {
+            Ok(("", r)) => Some(r),
+            _ => None,
         };
     }
 
@@ -41,40 +113,92 @@ mod cell_position_parser {
         use super::*;
         #[test]
         fn excel_position() {
-            assert_eq!(CellPosition { row: 7, col: 2 }, cell("C8"));
+            assert_eq!(Some(CellPosition { row: 7, col: 2 }), cell("C8"));
             assert_eq!(
-                CellRange {
+                Some(CellRange {
                     begin: "C8".into(),
                     end: "C9".into()
-                },
+                }),
                 range("C8:C9")
             );
+            assert_eq!(None, cell("C0"));
+            assert_eq!(None, cell("8C"));
+            assert_eq!(None, range("C8"));
+
+            assert_eq!(Some(CellPosition { row: 0, col: 25 }), cell("Z1"));
+            assert_eq!(Some(CellPosition { row: 0, col: 26 }), cell("AA1"));
+            assert_eq!(Some(CellPosition { row: 0, col: 27 }), cell("ab1"));
+            assert_eq!(Some(CellPosition { row: 0, col: 701 }), cell("ZZ1"));
+            assert_eq!(Some(CellPosition { row: 0, col: 16383 }), cell("XFD1"));
+            assert_eq!(None, cell("XFE1"));
+            assert_eq!(Some(CellPosition { row: 99999, col: 0 }), cell("A100000"));
+            assert_eq!(Some(CellPosition { row: 7, col: 2 }), cell("$C$8"));
+            assert_eq!(Some(CellPosition { row: 7, col: 2 }), cell("C$8"));
+        }
+
+        #[test]
+        fn excel_reference() {
+            let r = cell_reference("'RV32I'!C8").unwrap();
+            assert_eq!(Some("RV32I"), r.sheet.as_deref());
+            assert_eq!(CellRange::new("C8:C8"), r.range);
+            let r = cell_reference("RV32I!$A$1:$B$2").unwrap();
+            assert_eq!(Some("RV32I"), r.sheet.as_deref());
+            assert_eq!(CellRange::new("A1:B2"), r.range);
+            let r = cell_reference("'it''s a sheet'!B2").unwrap();
+            assert_eq!(Some("it's a sheet"), r.sheet.as_deref());
+            assert_eq!(None, cell_reference("C8").unwrap().sheet);
+            assert_eq!(None, cell_reference("'RV32I!C8"));
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct CellPosition {
-    pub row: u16,
+    pub row: u32,
     pub col: u16,
 }
 
 #[allow(dead_code)]
 impl CellPosition {
+    /// columns of a worksheet, `A` to `XFD`
+    pub const MAX_COLS: u32 = 16384;
+
     #[inline]
     pub fn new(pos: &str) -> CellPosition {
+        return cell_position_parser::cell(pos).expect(pos);
+    }
+
+    #[inline]
+    pub fn parse(pos: &str) -> Option<CellPosition> {
         return cell_position_parser::cell(pos);
     }
 
     /// (row, col)
-    pub fn from_tuple(t: (u16, u16)) -> CellPosition {
+    pub fn from_tuple(t: (u32, u16)) -> CellPosition {
         return CellPosition { row: t.0, col: t.1 };
     }
 
     /// (row, col)
-    pub fn tuple(&self) -> (u16, u16) {
+    pub fn tuple(&self) -> (u32, u16) {
         return (self.row, self.col);
     }
+
+    /// column letters, like `AA` for column 26
+    pub fn column_name(col: u16) -> String {
+        let mut letters = Vec::with_capacity(3);
+        let mut n = col as u32 + 1;
+        while n > 0 {
+            n -= 1;
+            letters.push((b'A' + (n % 26) as u8) as char);
+            n /= 26;
+        }
+        return letters.iter().rev().collect();
+    }
+
+    /// A1 notation, like `C8`
+    pub fn to_a1(&self) -> String {
+        return format!("{}{}", CellPosition::column_name(self.col), self.row + 1);
+    }
 }
 
 impl From<&str> for CellPosition {
@@ -84,9 +208,9 @@ impl From<&str> for CellPosition {
     }
 }
 
-impl From<(u16, u16)> for CellPosition {
+impl From<(u32, u16)> for CellPosition {
     #[inline]
-    fn from(t: (u16, u16)) -> Self {
+    fn from(t: (u32, u16)) -> Self {
         return CellPosition::from_tuple(t);
     }
 }
@@ -108,11 +232,11 @@ impl PartialOrd for CellPosition {
 
 impl Display for CellPosition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({}, {})", self.row, self.col)
+        write!(f, "{}", self.to_a1())
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct CellRange {
     pub begin: CellPosition,
     pub end: CellPosition,
@@ -122,10 +246,15 @@ pub struct CellRange {
 impl CellRange {
     #[inline]
     pub fn new(range: &str) -> CellRange {
+        return cell_position_parser::range(range).expect(range);
+    }
+
+    #[inline]
+    pub fn parse(range: &str) -> Option<CellRange> {
         return cell_position_parser::range(range);
     }
 
-    pub fn rows(&self) -> Range<u16> {
+    pub fn rows(&self) -> Range<u32> {
         (self.begin.row)..(self.end.row + 1)
     }
 
@@ -155,6 +284,46 @@ impl Display for CellRange {
     }
 }
 
+/// reference to a cell or a range, optionally on another sheet, like `'RV32I'!$C$8:$D$9`
+#[derive(Debug, PartialEq, Clone)]
+pub struct CellReference {
+    pub sheet: Option<String>,
+    /// a single cell is a range of one cell
+    pub range: CellRange,
+}
+
+#[allow(dead_code)]
+impl CellReference {
+    #[inline]
+    pub fn parse(r: &str) -> Option<CellReference> {
+        return cell_position_parser::cell_reference(r);
+    }
+
+    pub fn is_cell(&self) -> bool {
+        return self.range.begin == self.range.end;
+    }
+}
+
+impl Display for CellReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(sheet) = &self.sheet {
+            let plain = !sheet.is_empty()
+                && sheet
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+            if plain {
+                write!(f, "{}!", sheet)?;
+            } else {
+                write!(f, "'{}'!", sheet.replace('\'', "''"))?;
+            }
+        }
+        return match self.is_cell() {
+            true => write!(f, "{}", self.range.begin),
+            false => write!(f, "{}", self.range),
+        };
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -176,4 +345,20 @@ mod test {
         );
         assert_eq!(CellPosition::new("B6").partial_cmp(&("A7".into())), None);
     }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("C8", CellPosition::new("C8").to_string());
+        assert_eq!("AA1", CellPosition { row: 0, col: 26 }.to_a1());
+        assert_eq!("XFD1048576", CellPosition::new("XFD1048576").to_a1());
+        assert_eq!("B2:AZ70000", CellRange::new("$B$2:AZ70000").to_string());
+        assert_eq!(
+            "RV32I!C8",
+            CellReference::parse("'RV32I'!C8").unwrap().to_string()
+        );
+        assert_eq!(
+            "'it''s'!A1:B2",
+            CellReference::parse("'it''s'!A1:B2").unwrap().to_string()
+        );
+    }
 }
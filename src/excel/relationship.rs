@@ -0,0 +1,131 @@
+use quick_xml::{self, events::Event, Reader};
+use std::collections::HashMap;
+
+use super::{get_xml_attribute_string, ExcelError};
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Relationship {
+    /// last segment of the relationship type, like `worksheet` or `comments`
+    pub rel_type: String,
+    /// part path inside the package, or the raw target of an external relationship
+    pub target: String,
+    pub external: bool,
+}
+
+/// relationships part of a package part, like `xl/_rels/workbook.xml.rels`
+pub fn rels_part(part: &str) -> String {
+    return match part.rfind('/') {
+        Some(idx) => format!("{}/_rels/{}.rels", &part[..idx], &part[idx + 1..]),
+        None => format!("_rels/{}.rels", part),
+    };
+}
+
+/// resolve a relationship target against the part that owns it
+pub fn resolve_target(part: &str, target: &str) -> String {
+    let mut path: Vec<&str> = match target.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => {
+            let mut path: Vec<&str> = part.split('/').collect();
+            path.pop();
+            path
+        }
+    };
+    for seg in target.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                path.pop();
+            }
+            _ => path.push(seg),
+        }
+    }
+    return path.join("/");
+}
+
+/// <Id, relationship> of the relationships owned by `part`
+pub fn relationships(
+    part: &str,
+    rels_xml: &str,
+) -> Result<HashMap<String, Relationship>, ExcelError> {
+    let mut rels = HashMap::new();
+    let mut reader = Reader::from_str(rels_xml);
+    reader.trim_text(true);
+    let mut buf = Vec::with_capacity(64);
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Empty(ref e)) | Ok(Event::Start(ref e)) if e.name() == b"Relationship" => {
+                let id = get_xml_attribute_string(e, b"Id").unwrap_or_default();
+                let rel_type = get_xml_attribute_string(e, b"Type").unwrap_or_default();
+                let target = get_xml_attribute_string(e, b"Target").unwrap_or_default();
+                let external = get_xml_attribute_string(e, b"TargetMode")
+                    .map(|m| m == "External")
+                    .unwrap_or(false);
+                rels.insert(
+                    id,
+                    Relationship {
+                        rel_type: rel_type.rsplit('/').next().unwrap_or_default().into(),
+                        target: if external {
+                            target
+                        } else {
+                            resolve_target(part, &target)
+                        },
+                        external,
+                    },
+                );
+            }
+            Ok(Event::Eof) => {
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ExcelError::Xml {
+                    part: rels_part(part),
+                    position: reader.buffer_position(),
+                    error: e,
+                });
+            }
+        }
+        buf.clear();
+    }
+    return Ok(rels);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relationship_target() {
+        assert_eq!("xl/_rels/workbook.xml.rels", rels_part("xl/workbook.xml"));
+        assert_eq!(
+            "xl/worksheets/_rels/sheet1.xml.rels",
+            rels_part("xl/worksheets/sheet1.xml")
+        );
+        assert_eq!(
+            "xl/worksheets/sheet1.xml",
+            resolve_target("xl/workbook.xml", "worksheets/sheet1.xml")
+        );
+        assert_eq!(
+            "xl/worksheets/sheet1.xml",
+            resolve_target("xl/workbook.xml", "/xl/worksheets/sheet1.xml")
+        );
+        assert_eq!(
+            "xl/comments1.xml",
+            resolve_target("xl/worksheets/sheet1.xml", "../comments1.xml")
+        );
+
+        let rels = relationships(
+            "xl/workbook.xml",
+            r#"<Relationships>
+            <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet7.xml"/>
+            <Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="https://riscv.org" TargetMode="External"/>
+            </Relationships>"#,
+        )
+        .unwrap();
+        assert_eq!("worksheet", rels["rId2"].rel_type);
+        assert_eq!("xl/worksheets/sheet7.xml", rels["rId2"].target);
+        assert_eq!("https://riscv.org", rels["rId3"].target);
+        assert!(rels["rId3"].external);
+    }
+}
@@ -1,49 +1,40 @@
-use super::{CellPosition, CellRange};
-use quick_xml::{self, events::Event, Reader};
-use std::{cmp::Ordering, mem::replace, ops::Range, rc::Rc};
+use super::{CellPosition, CellRange, ExcelError, Font, Formula, FormulaError, Row, SheetRows};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::BufRead,
+    mem::take,
+    ops::Range,
+    rc::Rc,
+};
 
-use super::get_xml_attribute;
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellValue {
+    Text(Rc<String>),
+    Number(f64),
+    Bool(bool),
+}
 
-#[derive(Debug)]
-pub enum Cell {
-    None,
-    Primary {
-        content: Rc<String>,
-        size: CellPosition,
-    },
-    Merge {
-        offset: CellPosition,
-    },
+impl CellValue {
+    pub fn text(&self) -> Rc<String> {
+        return match self {
+            CellValue::Text(text) => text.clone(),
+            _ => Rc::new(self.to_string()),
+        };
+    }
 }
 
-#[allow(dead_code)]
-impl Cell {
-    pub fn merged_cell(&mut self, pos: CellPosition, range: &CellRange) {
-        assert!(pos <= range.end);
-        match pos.partial_cmp(&range.begin) {
-            Some(Ordering::Equal) => match self {
-                Cell::Primary { content: _, size } => {
-                    *size = range.size();
-                }
-                _ => {
-                    unreachable!()
-                }
-            },
-            Some(Ordering::Greater) => {
-                let _ = replace(
-                    self,
-                    Cell::Merge {
-                        offset: CellPosition {
-                            row: pos.row - range.begin.row,
-                            col: pos.col - range.begin.col,
-                        },
-                    },
-                );
-            }
-            _ => {
-                unreachable!("{} ? {}", &pos, &range.begin);
+impl std::fmt::Display for CellValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            CellValue::Text(text) => write!(f, "{}", text),
+            // integers are written without fraction, like excel shows them
+            CellValue::Number(n) if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 => {
+                write!(f, "{}", *n as i64)
             }
-        }
+            CellValue::Number(n) => write!(f, "{}", n),
+            CellValue::Bool(true) => write!(f, "TRUE"),
+            CellValue::Bool(false) => write!(f, "FALSE"),
+        };
     }
 }
 
@@ -54,172 +45,363 @@ pub struct MergedCell {
 }
 
 #[allow(dead_code)]
+#[derive(Default)]
 pub struct Sheet {
-    pub cells: Vec<Vec<Cell>>,
+    /// cells with a value by row, in column order, only rows that were read are kept
+    pub cells: BTreeMap<u32, Vec<(u16, CellValue)>>,
+    /// merged ranges, none overlaps another, laid over the cells instead of filling them
+    pub merges: Vec<CellRange>,
+    /// indexes into `merges` of the ranges covering a row
+    merged_rows: HashMap<u32, Vec<usize>>,
+    /// notes attached to cells
+    pub comments: HashMap<CellPosition, Rc<String>>,
+    /// hyperlink targets of cells, a url or a `#Sheet!A1` location
+    pub links: HashMap<CellPosition, Rc<String>>,
+    pub hidden_rows: HashSet<u32>,
+    pub hidden_cols: HashSet<u16>,
+    /// fonts of cells, cells with the default font are left out
+    pub fonts: HashMap<CellPosition, Font>,
+    /// values of the defined names the sheet sees, by `DefinedName::key`
+    pub names: HashMap<String, Vec<Rc<String>>>,
+    /// formula cells without a cached value that could not be evaluated, these cells are empty
+    pub unevaluated: Vec<(CellPosition, String, FormulaError)>,
+    /// (name, error) of the names into a sheet that could not be read, these names are left out
+    pub unresolved: Vec<(String, String)>,
 }
 
 #[allow(dead_code)]
 impl Sheet {
+    /// rows up to the last one read, merges do not add rows
+    pub fn rows(&self) -> usize {
+        return self
+            .cells
+            .keys()
+            .next_back()
+            .map(|row| *row as usize + 1)
+            .unwrap_or(0);
+    }
+
+    /// columns of a row up to its last cell or the end of a merge covering it
+    pub fn cols(&self, ridx: usize) -> usize {
+        let cells = match self.cells.get(&(ridx as u32)).and_then(|row| row.last()) {
+            Some((col, _)) => *col as usize + 1,
+            None => 0,
+        };
+        let merges = self
+            .row_merges(ridx as u32)
+            .map(|range| range.end.col as usize + 1)
+            .max()
+            .unwrap_or(0);
+        return cells.max(merges);
+    }
+
+    fn row_merges(&self, row: u32) -> impl Iterator<Item = &CellRange> {
+        return self
+            .merged_rows
+            .get(&row)
+            .into_iter()
+            .flatten()
+            .map(|idx| &self.merges[*idx]);
+    }
+
+    /// merge range covering a cell, a range of a single cell is no merge
+    fn merge_of(&self, ridx: usize, cidx: usize) -> Option<&CellRange> {
+        let col = u16::try_from(cidx).ok()?;
+        return self
+            .row_merges(ridx as u32)
+            .find(|range| range.cols().contains(&col))
+            .filter(|range| range.size() != CellPosition { row: 1, col: 1 });
+    }
+
+    /// value of a cell itself, not of the merge covering it
+    fn cell(&self, row: u32, col: u16) -> Option<&CellValue> {
+        let cells = self.cells.get(&row)?;
+        return match cells.binary_search_by_key(&col, |(c, _)| *c) {
+            Ok(idx) => Some(&cells[idx].1),
+            Err(_) => None,
+        };
+    }
+
+    /// typed value of a cell, merged cells give the value of their first cell
+    pub fn value(&self, ridx: usize, cidx: usize) -> Option<&CellValue> {
+        return match self.merge_of(ridx, cidx) {
+            Some(range) => self.cell(range.begin.row, range.begin.col),
+            None => self.cell(ridx as u32, u16::try_from(cidx).ok()?),
+        };
+    }
+
     pub fn content(&self, ridx: usize, cidx: usize) -> Option<(Rc<String>, Option<MergedCell>)> {
-        if ridx < self.cells.len() {
-            let row = &self.cells[ridx];
-            if cidx < row.len() {
-                let cell = &row[cidx];
-                match cell {
-                    Cell::Primary { content, size } => match size {
-                        CellPosition { row: 1, col: 1 } => {
-                            return Some((content.clone(), None));
-                        }
-                        _ => {
-                            return Some((
-                                content.clone(),
-                                Some(MergedCell {
-                                    offset: (0, 0).into(),
-                                    size: size.clone(),
-                                }),
-                            ));
-                        }
-                    },
-                    Cell::Merge { offset } => {
-                        match self
-                            .content(ridx - (offset.row as usize), cidx - (offset.col as usize))
-                        {
-                            Some((content, Some(mut merged_cell))) => {
-                                merged_cell.offset = offset.clone();
-                                return Some((content, Some(merged_cell)));
-                            }
-                            _ => {
-                                unreachable!()
-                            }
-                        }
-                    }
-                    Cell::None => {
-                        return None;
-                    }
+        return match self.merge_of(ridx, cidx) {
+            // merged into an empty cell gives nothing
+            Some(range) => self.cell(range.begin.row, range.begin.col).map(|value| {
+                let merged = MergedCell {
+                    offset: (ridx as u32 - range.begin.row, cidx as u16 - range.begin.col).into(),
+                    size: range.size(),
+                };
+                (value.text(), Some(merged))
+            }),
+            None => self
+                .cell(ridx as u32, u16::try_from(cidx).ok()?)
+                .map(|value| (value.text(), None)),
+        };
+    }
+
+    /// text of the cells of a range in row order, empty cells and cells covered by a merge are left out
+    pub fn range_text(&self, range: &CellRange) -> Vec<Rc<String>> {
+        let mut text = Vec::new();
+        for (_, cells) in self.cells.range(range.rows()) {
+            for (col, value) in cells {
+                if range.cols().contains(col) {
+                    text.push(value.text());
                 }
             }
         }
-        return None;
+        return text;
     }
 
     pub fn row(&self, ridx: usize) -> Range<usize> {
-        return 0..self.cells[ridx].len();
-    }
-
-    pub fn from_xml(xml: &str, shared_strings: &Vec<Rc<String>>) -> Sheet {
-        let mut cells: Vec<Vec<Cell>> = Vec::new();
-        let mut reader = Reader::from_str(xml);
-        reader.trim_text(true);
-        let mut buf = Vec::with_capacity(64);
-
-        let mut curr_row_cnt: Option<u16> = None;
-        let mut curr_pos: Option<CellPosition> = None;
-        let mut cell_value = false;
-
-        loop {
-            match reader.read_event(&mut buf) {
-                Ok(Event::Start(ref e)) => match e.name() {
-                    b"row" => {
-                        curr_row_cnt = get_xml_attribute(e, b"r").and_then(|a| unsafe {
-                            std::str::from_utf8_unchecked(&a).parse::<u16>().ok()
-                        });
-
-                        if let Some(row) = curr_row_cnt {
-                            for _ in cells.len()..(row as usize) {
-                                cells.push(Vec::with_capacity(16));
-                            }
-                        } else {
-                            unreachable!("invaild xml event of row in excel");
-                        }
-                    }
-                    b"c" => {
-                        curr_pos = get_xml_attribute(e, b"r").and_then(|a| unsafe {
-                            Some(CellPosition::from(std::str::from_utf8_unchecked(&a)))
-                        });
-
-                        match &curr_pos {
-                            Some(ref pos) => {
-                                assert!(pos.row + 1 == curr_row_cnt.unwrap());
-                                let current_row = &mut cells[(pos.row) as usize];
-                                for _ in current_row.len()..(pos.col as usize + 1) {
-                                    current_row.push(Cell::None);
-                                }
-                            }
-                            None => {
-                                unreachable!("invaild xml event col in excel");
-                            }
-                        }
-                    }
-                    b"v" => {
-                        cell_value = true;
-                    }
-
-                    _ => {}
-                },
-
-                Ok(Event::Text(ref e)) => {
-                    if cell_value == true {
-                        let idx = unsafe {
-                            let s = std::str::from_utf8_unchecked(&e);
-                            s.parse::<usize>().expect(s)
-                        };
-                        match &curr_pos {
-                            Some(ref pos) => {
-                                // println!("{} -> {}", pos, shared_strings[idx]);
-                                cells[pos.row as usize][pos.col as usize] = Cell::Primary {
-                                    content: shared_strings[idx].clone(),
-                                    size: CellPosition { row: 1, col: 1 },
-                                };
-                            }
-                            None => {
-                                unreachable!("ref {}", std::str::from_utf8(&e).unwrap());
-                            }
-                        }
-                    } else {
-                        unreachable!();
-                    }
-                }
+        return 0..self.cols(ridx);
+    }
 
-                Ok(Event::End(ref e)) => match e.name() {
-                    b"row" => curr_row_cnt = None,
-                    b"c" => curr_pos = None,
-                    b"v" => cell_value = false,
-                    _ => {}
-                },
-
-                Ok(Event::Empty(ref e)) => match e.name() {
-                    b"c" => {
-                        let current_row = &mut cells[curr_row_cnt.unwrap() as usize - 1];
-                        current_row.push(Cell::None);
-                    }
-                    b"mergeCell" => {
-                        let range = get_xml_attribute(e, b"ref")
-                            .and_then(|a| unsafe {
-                                let s = std::str::from_utf8_unchecked(&a);
-                                Some(CellRange::from(s))
-                            })
-                            .unwrap();
-
-                        for row in range.rows() {
-                            for col in range.cols() {
-                                let pos: CellPosition = (row, col).into();
-                                cells[row as usize][col as usize].merged_cell(pos, &range);
-                            }
-                        }
-                    }
-                    _ => {}
-                },
+    pub fn from_xml(
+        part: &str,
+        xml: &str,
+        shared_strings: &[Rc<String>],
+    ) -> Result<Sheet, ExcelError> {
+        return Sheet::from_rows(&mut SheetRows::new(
+            part,
+            xml.as_bytes(),
+            shared_strings,
+            &[],
+        ));
+    }
+
+    /// read every row, evaluate the formulas without a cached value, then lay the merge ranges over them
+    pub fn from_rows<B: BufRead>(rows: &mut SheetRows<B>) -> Result<Sheet, ExcelError> {
+        let mut sheet = Sheet::default();
+        let mut formulas = Vec::new();
+        for row in rows.by_ref() {
+            let mut row = row?;
+            for (col, formula) in take(&mut row.formulas) {
+                formulas.push((CellPosition::from_tuple((row.row, col)), formula));
+            }
+            sheet.push_row(row);
+        }
+        sheet.evaluate(formulas);
+        for cols in rows.hidden_cols() {
+            sheet.hidden_cols.extend(cols.clone());
+        }
+        for range in rows.merges() {
+            sheet.merge(range)?;
+        }
+        return Ok(sheet);
+    }
 
-                Ok(Event::Eof) => {
-                    break;
+    /// formulas are evaluated once the cells they refer to have a value,
+    /// a formula referring to itself through others is never evaluated
+    fn evaluate(&mut self, formulas: Vec<(CellPosition, String)>) {
+        let mut pending: Vec<(CellPosition, String, Option<Formula>)> = formulas
+            .into_iter()
+            .map(|(pos, text)| {
+                let formula = Formula::parse(&text);
+                (pos, text, formula)
+            })
+            .collect();
+        while !pending.is_empty() {
+            let waiting: HashSet<CellPosition> = pending.iter().map(|p| p.0.clone()).collect();
+            // with the cell each formula waits for
+            let mut next = Vec::new();
+            for (pos, text, formula) in take(&mut pending) {
+                let value = match &formula {
+                    Some(formula) => formula.evaluate(&|cell: &CellPosition| {
+                        if waiting.contains(cell) {
+                            return Err(FormulaError::Pending(cell.clone()));
+                        }
+                        return Ok(self.value(cell.row as usize, cell.col as usize).cloned());
+                    }),
+                    None => Err(FormulaError::Syntax),
+                };
+                match value {
+                    Ok(value) => self.push_row(Row::new(pos.row, vec![(pos.col, value)])),
+                    Err(e @ FormulaError::Pending(_)) => next.push((pos, text, formula, e)),
+                    Err(e) => self.unevaluated.push((pos, text, e)),
                 }
-                Ok(_) => {}
-                Err(e) => {
-                    panic!("{} {:?}", reader.buffer_position(), e);
+            }
+            if next.len() == waiting.len() {
+                for (pos, text, _, e) in next {
+                    self.unevaluated.push((pos, text, e));
                 }
+                break;
             }
+            pending = next
+                .into_iter()
+                .map(|(pos, text, formula, _)| (pos, text, formula))
+                .collect();
         }
+    }
+
+    /// place the cells of a row, a cell already placed takes the new value
+    pub fn push_row(&mut self, row: Row) {
+        if row.hidden {
+            self.hidden_rows.insert(row.row);
+        }
+        for (col, font) in row.fonts {
+            self.fonts.insert((row.row, col).into(), font);
+        }
+        let current_row = self.cells.entry(row.row).or_default();
+        for (col, value) in row.cells {
+            match current_row.binary_search_by_key(&col, |(c, _)| *c) {
+                Ok(idx) => current_row[idx].1 = value,
+                Err(idx) => current_row.insert(idx, (col, value)),
+            }
+        }
+    }
+
+    /// merge a range into its first cell once every row is placed, the range must not overlap
+    /// another one, rows past the last row of the sheet are left out
+    pub fn merge(&mut self, range: &CellRange) -> Result<(), ExcelError> {
+        if !(range.begin <= range.end) {
+            return Err(ExcelError::BadMergeRange(range.to_string()));
+        }
+        let rows = range.begin.row..(range.end.row + 1).min(self.rows() as u32);
+        for row in rows.clone() {
+            if self
+                .row_merges(row)
+                .any(|other| other.begin.col <= range.end.col && range.begin.col <= other.end.col)
+            {
+                return Err(ExcelError::BadMergeRange(range.to_string()));
+            }
+        }
+        let idx = self.merges.len();
+        self.merges.push(range.clone());
+        for row in rows {
+            self.merged_rows.entry(row).or_default().push(idx);
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared_strings() -> Vec<Rc<String>> {
+        return vec![Rc::new("#input".into()), Rc::new("[31:0]i_instr".into())];
+    }
+
+    #[test]
+    fn sheet_from_xml() {
+        let xml = r#"<worksheet><sheetData>
+            <row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c><c r="C1"/></row>
+            </sheetData><mergeCells><mergeCell ref="B1:C1"/></mergeCells></worksheet>"#;
+        let sheet = Sheet::from_xml("sheet1.xml", xml, &shared_strings()).unwrap();
+        assert_eq!("#input", sheet.content(0, 0).unwrap().0.as_str());
+        let (content, merged) = sheet.content(0, 2).unwrap();
+        assert_eq!("[31:0]i_instr", content.as_str());
+        assert_eq!(CellPosition { row: 0, col: 1 }, merged.unwrap().offset);
+    }
+
+    #[test]
+    fn sheet_typed_cells() {
+        let xml = r#"<sheetData><row r="1">
+            <c r="A1"><v>7</v></c>
+            <c r="B1" t="n"><v>2.5</v></c>
+            <c r="C1" t="b"><v>1</v></c>
+            <c r="D1" t="str"><f>"7'b"&amp;DEC2BIN(A1,7)</f><v>7'b0000111</v></c>
+            <c r="E1" t="inlineStr"><is><r><t>imm</t></r><r><t>[11:0]</t></r><rPh><t>x</t></rPh></is></c>
+            <c r="F1" t="s"><v>1</v></c>
+            </row></sheetData>"#;
+        let sheet = Sheet::from_xml("sheet1.xml", xml, &shared_strings()).unwrap();
+        assert_eq!(Some(&CellValue::Number(7.0)), sheet.value(0, 0));
+        assert_eq!("7", sheet.content(0, 0).unwrap().0.as_str());
+        assert_eq!("2.5", sheet.content(0, 1).unwrap().0.as_str());
+        assert_eq!(Some(&CellValue::Bool(true)), sheet.value(0, 2));
+        assert_eq!("7'b0000111", sheet.content(0, 3).unwrap().0.as_str());
+        assert_eq!("imm[11:0]", sheet.content(0, 4).unwrap().0.as_str());
+        assert_eq!("[31:0]i_instr", sheet.content(0, 5).unwrap().0.as_str());
+    }
+
+    #[test]
+    fn sheet_formulas() {
+        let xml = r#"<sheetData>
+            <row r="1"><c r="A1"><v>51</v></c><c r="B1" t="str"><f>"7'b"&amp;DEC2BIN(A1,7)</f></c>
+            <c r="C1" t="str"><f>CONCAT(B1,D1)</f><v>cached</v></c><c r="D1" t="str"><f>E1&amp;"!"</f></c>
+            <c r="E1" t="str"><f>B1</f><v></v></c></row>
+            <row r="2"><c r="A2"><f t="shared" ref="A2:A3" si="0">A1+1</f></c><c r="B2"><f>SUM(A1:A2)</f></c>
+            <c r="C2"><f>D2</f></c><c r="D2"><f>C2</f></c></row>
+            <row r="3"><c r="A3"><f t="shared" si="0"/></c><c r="B3"><f t="shared" si="7"/></c></row>
+            </sheetData><mergeCells><mergeCell ref="B1:B2"/></mergeCells>"#;
+        let sheet = Sheet::from_xml("sheet1.xml", xml, &shared_strings()).unwrap();
+        assert_eq!("7'b0110011", sheet.content(0, 1).unwrap().0.as_str());
+        assert_eq!("7'b0110011", sheet.content(1, 1).unwrap().0.as_str());
+        assert_eq!("cached", sheet.content(0, 2).unwrap().0.as_str());
+        assert_eq!("7'b0110011!", sheet.content(0, 3).unwrap().0.as_str());
+        assert_eq!(Some(&CellValue::Number(53.0)), sheet.value(2, 0));
+        let unevaluated: Vec<(String, &str)> = sheet
+            .unevaluated
+            .iter()
+            .map(|(pos, formula, _)| (pos.to_string(), formula.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("B2".to_string(), "SUM(A1:A2)"),
+                ("B3".to_string(), "shared formula 7"),
+                ("C2".to_string(), "D2"),
+                ("D2".to_string(), "C2"),
+            ],
+            unevaluated
+        );
+    }
+
+    #[test]
+    fn sheet_sparse_merges() {
+        let xml = r#"<sheetData><row r="2"><c r="A2" t="s"><v>0</v></c><c r="XFD2" t="s"><v>1</v></c></row>
+            </sheetData><mergeCells><mergeCell ref="A2:XFC1048576"/></mergeCells>"#;
+        let sheet = Sheet::from_xml("sheet1.xml", xml, &shared_strings()).unwrap();
+        // the merge is cut at the last row and takes no room for the cells it covers
+        assert_eq!(2, sheet.rows());
+        assert_eq!(16384, sheet.cols(1));
+        assert_eq!(0, sheet.cols(0));
+        let (content, merged) = sheet.content(1, 16382).unwrap();
+        assert_eq!("#input", content.as_str());
+        assert_eq!(CellPosition { row: 0, col: 16382 }, merged.unwrap().offset);
+        assert_eq!("[31:0]i_instr", sheet.content(1, 16383).unwrap().0.as_str());
+
+        let xml = r#"<sheetData><row r="1"><c r="A1" t="s"><v>0</v></c></row></sheetData>
+            <mergeCells><mergeCell ref="A1:C1"/><mergeCell ref="C1:D1"/></mergeCells>"#;
+        assert!(matches!(
+            Sheet::from_xml("sheet1.xml", xml, &shared_strings()),
+            Err(ExcelError::BadMergeRange(r)) if r == "C1:D1"
+        ));
+    }
 
-        return Sheet { cells };
+    #[test]
+    fn sheet_from_bad_xml() {
+        let ss = shared_strings();
+        let xml = r#"<sheetData><row r="1"><c r="A1" t="s"><v>7</v></c></row></sheetData>"#;
+        assert!(matches!(
+            Sheet::from_xml("sheet1.xml", xml, &ss),
+            Err(ExcelError::SharedStringOutOfRange { len: 2, .. })
+        ));
+        let xml = r#"<sheetData><row r="1"><c r="1A"><v>0</v></c></row></sheetData>"#;
+        assert!(matches!(
+            Sheet::from_xml("sheet1.xml", xml, &ss),
+            Err(ExcelError::BadCellReference(_))
+        ));
+        let xml = r#"<mergeCells><mergeCell ref="C3:A1"/></mergeCells>"#;
+        assert!(matches!(
+            Sheet::from_xml("sheet1.xml", xml, &ss),
+            Err(ExcelError::BadMergeRange(_))
+        ));
+        let xml = r#"<sheetData><row r="1"><c r="A1"><v>0x7</v></c></row></sheetData>"#;
+        assert!(matches!(
+            Sheet::from_xml("sheet1.xml", xml, &ss),
+            Err(ExcelError::BadCellValue(_))
+        ));
+        let xml = r#"<sheetData><row r="1"></sheetData>"#;
+        assert!(matches!(
+            Sheet::from_xml("sheet1.xml", xml, &ss),
+            Err(ExcelError::Xml { .. })
+        ));
     }
 }
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use super::{CellPosition, CellRange, DefinedName, Font, MergedCell, Sheet};
+
+/// a grid of text cells with merges, as the table logic reads it
+pub trait SheetSource {
+    fn rows(&self) -> usize;
+
+    /// columns of a row, up to its last cell
+    fn cols(&self, ridx: usize) -> usize;
+
+    /// text of a cell, cells of a merge give the text of its first cell and where they are in it
+    fn content(&self, ridx: usize, cidx: usize) -> Option<(Rc<String>, Option<MergedCell>)>;
+
+    /// where a cell is, for messages
+    fn location(&self, ridx: usize, cidx: usize) -> String {
+        return CellPosition::from_tuple((ridx as u32, cidx as u16)).to_string();
+    }
+
+    fn comment(&self, _ridx: usize, _cidx: usize) -> Option<Rc<String>> {
+        return None;
+    }
+
+    fn link(&self, _ridx: usize, _cidx: usize) -> Option<Rc<String>> {
+        return None;
+    }
+
+    /// comment and link of a cell, as documentation of what comes from it
+    fn note(&self, ridx: usize, cidx: usize) -> Option<String> {
+        return match (self.comment(ridx, cidx), self.link(ridx, cidx)) {
+            (Some(comment), Some(link)) => Some(format!("{}\n{}", comment, link)),
+            (Some(comment), None) => Some(comment.to_string()),
+            (None, Some(link)) => Some(link.to_string()),
+            (None, None) => None,
+        };
+    }
+
+    /// values of a defined name, in row order for a range
+    fn defined_name(&self, _name: &str) -> Option<Vec<Rc<String>>> {
+        return None;
+    }
+
+    fn row_hidden(&self, _ridx: usize) -> bool {
+        return false;
+    }
+
+    #[allow(dead_code)]
+    fn col_hidden(&self, _cidx: usize) -> bool {
+        return false;
+    }
+
+    fn font(&self, _ridx: usize, _cidx: usize) -> Font {
+        return Font::default();
+    }
+
+    /// a row switched off without deleting it, hidden or with every value of its own struck through
+    fn disabled(&self, ridx: usize) -> bool {
+        if self.row_hidden(ridx) {
+            return true;
+        }
+        // cells merged from a row above belong to that row
+        let mut cells = (0..self.cols(ridx))
+            .filter(|cidx| match self.content(ridx, *cidx) {
+                Some((_, Some(merged))) => merged.offset.row == 0 && merged.offset.col == 0,
+                Some((_, None)) => true,
+                None => false,
+            })
+            .peekable();
+        return cells.peek().is_some() && cells.all(|cidx| self.font(ridx, cidx).strike);
+    }
+}
+
+impl SheetSource for Sheet {
+    fn rows(&self) -> usize {
+        return Sheet::rows(self);
+    }
+
+    fn cols(&self, ridx: usize) -> usize {
+        return Sheet::cols(self, ridx);
+    }
+
+    fn content(&self, ridx: usize, cidx: usize) -> Option<(Rc<String>, Option<MergedCell>)> {
+        return Sheet::content(self, ridx, cidx);
+    }
+
+    fn comment(&self, ridx: usize, cidx: usize) -> Option<Rc<String>> {
+        let pos = CellPosition::from_tuple((ridx as u32, cidx as u16));
+        return self.comments.get(&pos).cloned();
+    }
+
+    fn link(&self, ridx: usize, cidx: usize) -> Option<Rc<String>> {
+        let pos = CellPosition::from_tuple((ridx as u32, cidx as u16));
+        return self.links.get(&pos).cloned();
+    }
+
+    fn defined_name(&self, name: &str) -> Option<Vec<Rc<String>>> {
+        return self.names.get(&DefinedName::key(name)).cloned();
+    }
+
+    fn row_hidden(&self, ridx: usize) -> bool {
+        return self.hidden_rows.contains(&(ridx as u32));
+    }
+
+    fn col_hidden(&self, cidx: usize) -> bool {
+        return self.hidden_cols.contains(&(cidx as u16));
+    }
+
+    fn font(&self, ridx: usize, cidx: usize) -> Font {
+        let pos = CellPosition::from_tuple((ridx as u32, cidx as u16));
+        return self.fonts.get(&pos).cloned().unwrap_or_default();
+    }
+}
+
+/// cells given as text in code, empty text is an empty cell
+pub struct MemorySheet {
+    cells: Vec<Vec<Rc<String>>>,
+    merges: Vec<CellRange>,
+    comments: HashMap<CellPosition, Rc<String>>,
+    hidden_rows: HashSet<usize>,
+    struck: HashSet<CellPosition>,
+    names: HashMap<String, Vec<Rc<String>>>,
+}
+
+#[allow(dead_code)]
+impl MemorySheet {
+    pub fn new(rows: &[&[&str]]) -> MemorySheet {
+        return MemorySheet {
+            cells: rows
+                .iter()
+                .map(|row| row.iter().map(|c| Rc::new(c.to_string())).collect())
+                .collect(),
+            merges: Vec::new(),
+            comments: HashMap::new(),
+            hidden_rows: HashSet::new(),
+            struck: HashSet::new(),
+            names: HashMap::new(),
+        };
+    }
+
+    /// merge a range like `B2:C3` into its first cell
+    pub fn merge(mut self, range: &str) -> MemorySheet {
+        self.merges.push(CellRange::new(range));
+        return self;
+    }
+
+    /// attach a comment to a cell like `A1`
+    pub fn comment(mut self, pos: &str, comment: &str) -> MemorySheet {
+        self.comments
+            .insert(CellPosition::new(pos), Rc::new(comment.into()));
+        return self;
+    }
+
+    /// define a name with its values
+    pub fn name(mut self, name: &str, values: &[&str]) -> MemorySheet {
+        self.names.insert(
+            DefinedName::key(name),
+            values.iter().map(|v| Rc::new(v.to_string())).collect(),
+        );
+        return self;
+    }
+
+    pub fn hide_row(mut self, ridx: usize) -> MemorySheet {
+        self.hidden_rows.insert(ridx);
+        return self;
+    }
+
+    /// strike the text of a cell like `A1` through
+    pub fn strike(mut self, pos: &str) -> MemorySheet {
+        self.struck.insert(CellPosition::new(pos));
+        return self;
+    }
+
+    fn text(&self, ridx: usize, cidx: usize) -> Option<Rc<String>> {
+        return self
+            .cells
+            .get(ridx)
+            .and_then(|row| row.get(cidx))
+            .filter(|text| !text.is_empty())
+            .cloned();
+    }
+}
+
+impl SheetSource for MemorySheet {
+    fn rows(&self) -> usize {
+        return self.cells.len();
+    }
+
+    fn cols(&self, ridx: usize) -> usize {
+        return self.cells.get(ridx).map(|row| row.len()).unwrap_or(0);
+    }
+
+    fn content(&self, ridx: usize, cidx: usize) -> Option<(Rc<String>, Option<MergedCell>)> {
+        let pos = CellPosition::from_tuple((ridx as u32, cidx as u16));
+        let range = self
+            .merges
+            .iter()
+            .find(|range| range.begin <= pos && pos <= range.end);
+        return match range {
+            Some(range) => self
+                .text(range.begin.row as usize, range.begin.col as usize)
+                .map(|text| {
+                    (
+                        text,
+                        Some(MergedCell {
+                            offset: (pos.row - range.begin.row, pos.col - range.begin.col).into(),
+                            size: range.size(),
+                        }),
+                    )
+                }),
+            None => self.text(ridx, cidx).map(|text| (text, None)),
+        };
+    }
+
+    fn comment(&self, ridx: usize, cidx: usize) -> Option<Rc<String>> {
+        let pos = CellPosition::from_tuple((ridx as u32, cidx as u16));
+        return self.comments.get(&pos).cloned();
+    }
+
+    fn defined_name(&self, name: &str) -> Option<Vec<Rc<String>>> {
+        return self.names.get(&DefinedName::key(name)).cloned();
+    }
+
+    fn row_hidden(&self, ridx: usize) -> bool {
+        return self.hidden_rows.contains(&ridx);
+    }
+
+    fn font(&self, ridx: usize, cidx: usize) -> Font {
+        let pos = CellPosition::from_tuple((ridx as u32, cidx as u16));
+        return Font {
+            strike: self.struck.contains(&pos),
+            ..Font::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_sheet() {
+        let sheet = MemorySheet::new(&[&["#match", "a"], &["7'b0", "", "x"]])
+            .merge("A2:B2")
+            .comment("B1", "field a");
+        assert_eq!(2, sheet.rows());
+        assert_eq!(3, sheet.cols(1));
+        assert_eq!("a", SheetSource::content(&sheet, 0, 1).unwrap().0.as_str());
+        let (text, merged) = SheetSource::content(&sheet, 1, 1).unwrap();
+        assert_eq!("7'b0", text.as_str());
+        assert_eq!(CellPosition { row: 0, col: 1 }, merged.unwrap().offset);
+        assert!(SheetSource::content(&sheet, 0, 2).is_none());
+        assert_eq!("B2", sheet.location(1, 1));
+        assert_eq!(Some("field a".to_string()), sheet.note(0, 1));
+        assert_eq!(None, sheet.note(0, 0));
+        assert!(!sheet.disabled(1));
+
+        let sheet = MemorySheet::new(&[
+            &["#match", "a", "b"],
+            &["0", "x", ""],
+            &["1", "", "y"],
+            &["", "", ""],
+        ])
+        .merge("B2:B3")
+        .strike("A2")
+        .strike("B2")
+        .strike("A3")
+        .hide_row(3);
+        assert!(sheet.disabled(1));
+        // B3 belongs to the merge of B2, C3 is not struck through
+        assert!(!sheet.disabled(2));
+        assert!(!sheet.disabled(0));
+        assert!(sheet.disabled(3));
+    }
+}
@@ -0,0 +1,583 @@
+use quick_xml::{
+    self,
+    events::{BytesStart, Event},
+    Reader,
+};
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::mem::take;
+use std::ops::Range;
+use std::rc::Rc;
+
+use super::{
+    get_xml_attribute, get_xml_attribute_string, CellPosition, CellRange, CellReference, CellValue,
+    ExcelError, Font, Formula,
+};
+
+/// `t` attribute of a `<c>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CellType {
+    SharedString,
+    Number,
+    Bool,
+    /// formula string, error and date cells hold their text in `<v>`
+    Str,
+    InlineString,
+}
+
+impl CellType {
+    fn from_attribute(t: Option<&str>) -> CellType {
+        return match t {
+            Some("s") => CellType::SharedString,
+            Some("b") => CellType::Bool,
+            Some("str") | Some("e") | Some("d") => CellType::Str,
+            Some("inlineStr") => CellType::InlineString,
+            _ => CellType::Number,
+        };
+    }
+}
+
+/// a row of a worksheet, only cells with a value are kept
+#[derive(Debug, PartialEq)]
+pub struct Row {
+    pub row: u32,
+    /// (col, value) in column order
+    pub cells: Vec<(u16, CellValue)>,
+    pub hidden: bool,
+    /// (col, font) of the cells formatted with something else than the default font
+    pub fonts: Vec<(u16, Font)>,
+    /// (col, formula) of the formula cells without a cached value
+    pub formulas: Vec<(u16, String)>,
+}
+
+impl Row {
+    pub fn new(row: u32, cells: Vec<(u16, CellValue)>) -> Row {
+        return Row {
+            row,
+            cells,
+            hidden: false,
+            fonts: Vec::new(),
+            formulas: Vec::new(),
+        };
+    }
+}
+
+/// `<hyperlink>` of a worksheet, external targets are in the sheet relationships
+#[derive(Debug, PartialEq)]
+pub struct Hyperlink {
+    pub range: CellRange,
+    pub id: Option<String>,
+    /// place inside the workbook, like `Sheet2!A1`
+    pub location: Option<String>,
+}
+
+/// rows of a worksheet read one at a time, merge ranges and hyperlinks are collected on the way
+pub struct SheetRows<'a, B: BufRead> {
+    part: String,
+    reader: Reader<B>,
+    buf: Vec<u8>,
+    shared_strings: &'a [Rc<String>],
+    /// font of each cell format, see `cell_fonts`
+    fonts: &'a [Font],
+    hidden_cols: Vec<Range<u16>>,
+    /// (first cell, formula) of the shared formulas by `si`, the other cells only refer to them
+    shared_formulas: HashMap<String, (CellPosition, String)>,
+    /// text of inline and formula string cells, shared between cells
+    interned: HashSet<Rc<String>>,
+    merges: Vec<CellRange>,
+    hyperlinks: Vec<Hyperlink>,
+    last_row: Option<u32>,
+    done: bool,
+}
+
+#[allow(dead_code)]
+impl<'a, B: BufRead> SheetRows<'a, B> {
+    pub fn new(
+        part: &str,
+        source: B,
+        shared_strings: &'a [Rc<String>],
+        fonts: &'a [Font],
+    ) -> SheetRows<'a, B> {
+        let mut reader = Reader::from_reader(source);
+        // spaces of inline and formula strings are part of their text
+        reader.trim_text(false);
+        return SheetRows {
+            part: part.into(),
+            reader,
+            buf: Vec::with_capacity(64),
+            shared_strings,
+            fonts,
+            hidden_cols: Vec::new(),
+            shared_formulas: HashMap::new(),
+            interned: HashSet::new(),
+            merges: Vec::new(),
+            hyperlinks: Vec::new(),
+            last_row: None,
+            done: false,
+        };
+    }
+
+    /// merge ranges read so far, complete once every row is read
+    pub fn merges(&self) -> &[CellRange] {
+        return &self.merges;
+    }
+
+    /// hyperlinks read so far, complete once every row is read
+    pub fn hyperlinks(&self) -> &[Hyperlink] {
+        return &self.hyperlinks;
+    }
+
+    /// hidden column ranges, `<cols>` comes before the rows
+    pub fn hidden_cols(&self) -> &[Range<u16>] {
+        return &self.hidden_cols;
+    }
+
+    fn xml_error(&self, error: quick_xml::Error) -> ExcelError {
+        return ExcelError::Xml {
+            part: self.part.clone(),
+            position: self.reader.buffer_position(),
+            error,
+        };
+    }
+
+    fn intern(&mut self, text: String) -> Rc<String> {
+        if let Some(text) = self.interned.get(&text) {
+            return text.clone();
+        }
+        let text = Rc::new(text);
+        self.interned.insert(text.clone());
+        return text;
+    }
+
+    /// rows without reference follow the previous one, rows must be in order
+    fn row_number(&mut self, e: &BytesStart) -> Result<u32, ExcelError> {
+        let row = match get_xml_attribute(e, b"r") {
+            Some(a) => {
+                let r = String::from_utf8_lossy(&a);
+                match r.parse::<u32>() {
+                    Ok(row)
+                        if row > 0 && !matches!(self.last_row, Some(last) if row <= last + 1) =>
+                    {
+                        row - 1
+                    }
+                    _ => return Err(ExcelError::BadCellReference(r.into())),
+                }
+            }
+            None => self.last_row.map_or(0, |last| last + 1),
+        };
+        self.last_row = Some(row);
+        return Ok(row);
+    }
+
+    fn start_row(&mut self, e: &BytesStart) -> Result<Row, ExcelError> {
+        let mut row = Row::new(self.row_number(e)?, Vec::new());
+        row.hidden = SheetRows::<B>::flag(e, b"hidden");
+        return Ok(row);
+    }
+
+    /// boolean attribute, off when it is missing
+    fn flag(e: &BytesStart, key: &[u8]) -> bool {
+        return matches!(
+            get_xml_attribute(e, key).as_deref(),
+            Some(b"1") | Some(b"true")
+        );
+    }
+
+    /// font of the format of a `<c>`, unknown formats have the default font
+    fn cell_font(&self, e: &BytesStart, row: Option<&mut Row>, col: u16) {
+        let font = get_xml_attribute_string(e, b"s")
+            .and_then(|s| s.parse::<usize>().ok())
+            .and_then(|s| self.fonts.get(s));
+        if let (Some(font), Some(row)) = (font, row) {
+            if !font.is_default() {
+                row.fonts.push((col, *font));
+            }
+        }
+    }
+
+    /// `<col min="1" max="3" hidden="1"/>`, columns are counted from 1
+    fn col(&mut self, e: &BytesStart) -> Result<(), ExcelError> {
+        if !SheetRows::<B>::flag(e, b"hidden") {
+            return Ok(());
+        }
+        let bound = |key: &[u8]| {
+            get_xml_attribute_string(e, key)
+                .and_then(|n| n.parse::<u32>().ok())
+                .filter(|n| *n > 0 && *n <= CellPosition::MAX_COLS)
+        };
+        return match (bound(b"min"), bound(b"max")) {
+            (Some(min), Some(max)) if min <= max => {
+                self.hidden_cols.push((min - 1) as u16..max as u16);
+                Ok(())
+            }
+            _ => Err(ExcelError::BadCellReference(format!(
+                "<col min=\"{}\" max=\"{}\">",
+                get_xml_attribute_string(e, b"min").unwrap_or_default(),
+                get_xml_attribute_string(e, b"max").unwrap_or_default()
+            ))),
+        };
+    }
+
+    /// `<f t="shared" si="0"/>` of a cell sharing the formula of the first cell of its group,
+    /// the formula moves along with the cell like a copied one
+    fn shared_formula(&self, si: &str, row: u32, col: u16) -> String {
+        let shifted = self.shared_formulas.get(si).and_then(|(first, text)| {
+            Formula::parse(text)?
+                .shifted(row as i64 - first.row as i64, col as i64 - first.col as i64)
+        });
+        return match shifted {
+            Some(formula) => formula.to_string(),
+            None => format!("shared formula {}", si),
+        };
+    }
+
+    /// column of a `<c>`, cells without reference follow the previous one
+    fn cell_column(e: &BytesStart, row: Option<&Row>, next_col: u16) -> Result<u16, ExcelError> {
+        let row = match row {
+            Some(row) => row.row,
+            None => return Err(ExcelError::BadCellReference("<c> outside <row>".into())),
+        };
+        return match get_xml_attribute(e, b"r") {
+            Some(a) => {
+                let r = String::from_utf8_lossy(&a);
+                match CellPosition::parse(&r) {
+                    Some(pos) if pos.row == row && pos.col >= next_col => Ok(pos.col),
+                    _ => Err(ExcelError::BadCellReference(r.into())),
+                }
+            }
+            None => Ok(next_col),
+        };
+    }
+
+    fn merge_cell(&mut self, e: &BytesStart) -> Result<(), ExcelError> {
+        let r = get_xml_attribute(e, b"ref")
+            .map(|a| String::from_utf8_lossy(&a).into_owned())
+            .unwrap_or_default();
+        return match CellRange::parse(&r) {
+            Some(range) if range.begin <= range.end => {
+                self.merges.push(range);
+                Ok(())
+            }
+            _ => Err(ExcelError::BadMergeRange(r)),
+        };
+    }
+
+    fn hyperlink(&mut self, e: &BytesStart) -> Result<(), ExcelError> {
+        let r = get_xml_attribute_string(e, b"ref").unwrap_or_default();
+        let range = match CellReference::parse(&r) {
+            Some(CellReference { sheet: None, range }) if range.begin <= range.end => range,
+            _ => return Err(ExcelError::BadCellReference(r)),
+        };
+        // the relationship namespace prefix is usually, but not always, `r`
+        let id = e
+            .attributes()
+            .flatten()
+            .find(|a| a.key == b"r:id" || a.key.ends_with(b":id"))
+            .map(|a| String::from_utf8_lossy(&a.value).into_owned());
+        self.hyperlinks.push(Hyperlink {
+            range,
+            id,
+            location: get_xml_attribute_string(e, b"location"),
+        });
+        return Ok(());
+    }
+
+    fn cell_value(&mut self, cell_type: CellType, text: String) -> Result<CellValue, ExcelError> {
+        return match cell_type {
+            CellType::SharedString => match text.trim().parse::<usize>() {
+                Ok(idx) if idx < self.shared_strings.len() => {
+                    Ok(CellValue::Text(self.shared_strings[idx].clone()))
+                }
+                _ => Err(ExcelError::SharedStringOutOfRange {
+                    idx: text,
+                    len: self.shared_strings.len(),
+                }),
+            },
+            CellType::Number => match text.trim().parse::<f64>() {
+                Ok(n) => Ok(CellValue::Number(n)),
+                Err(_) => Err(ExcelError::BadCellValue(text)),
+            },
+            CellType::Bool => match text.trim() {
+                "1" | "true" => Ok(CellValue::Bool(true)),
+                "0" | "false" => Ok(CellValue::Bool(false)),
+                _ => Err(ExcelError::BadCellValue(text)),
+            },
+            CellType::Str | CellType::InlineString => Ok(CellValue::Text(self.intern(text))),
+        };
+    }
+
+    /// read up to the end of the next `<row>`, `None` at the end of the part
+    fn next_row(&mut self) -> Result<Option<Row>, ExcelError> {
+        let mut buf = take(&mut self.buf);
+        let row = self.read_row(&mut buf);
+        buf.clear();
+        self.buf = buf;
+        return row;
+    }
+
+    fn read_row(&mut self, buf: &mut Vec<u8>) -> Result<Option<Row>, ExcelError> {
+        let mut row: Option<Row> = None;
+        let mut next_col: u16 = 0;
+        let mut curr_col: Option<u16> = None;
+        let mut curr_type = CellType::Number;
+        let mut cell_value = false;
+        // the current cell has a value, its formula if it has one, inside <f>
+        let mut valued = false;
+        let mut formula: Option<String> = None;
+        let mut formula_text = false;
+        let mut shared_si: Option<String> = None;
+        // inside <is>, inside <t> of <is>, inside phonetic <rPh>
+        let mut inline_string = false;
+        let mut inline_text = false;
+        let mut phonetic = false;
+        let mut inline_value = String::new();
+
+        loop {
+            match self.reader.read_event(buf) {
+                Ok(Event::Start(ref e)) => match e.name() {
+                    b"row" => {
+                        row = Some(self.start_row(e)?);
+                        next_col = 0;
+                    }
+                    b"c" => {
+                        let col = SheetRows::<B>::cell_column(e, row.as_ref(), next_col)?;
+                        self.cell_font(e, row.as_mut(), col);
+                        next_col = col + 1;
+                        curr_col = Some(col);
+                        valued = false;
+                        formula = None;
+                        curr_type = CellType::from_attribute(
+                            get_xml_attribute(e, b"t")
+                                .as_ref()
+                                .and_then(|t| std::str::from_utf8(t).ok()),
+                        );
+                    }
+                    b"v" => {
+                        cell_value = true;
+                    }
+                    b"f" => {
+                        formula_text = true;
+                        formula = Some(String::new());
+                        shared_si = match get_xml_attribute(e, b"t").as_deref() {
+                            Some(b"shared") => get_xml_attribute_string(e, b"si"),
+                            _ => None,
+                        };
+                    }
+                    b"is" => {
+                        inline_string = true;
+                        inline_value.clear();
+                    }
+                    b"t" => {
+                        inline_text = inline_string;
+                    }
+                    b"rPh" => {
+                        phonetic = true;
+                    }
+                    _ => {}
+                },
+
+                Ok(Event::Text(ref e)) => {
+                    let text = e
+                        .unescape_and_decode(&self.reader)
+                        .map_err(|e| self.xml_error(e))?;
+                    // an empty `<v></v>` leaves the cell without a cached value
+                    if cell_value && !text.is_empty() {
+                        let value = self.cell_value(curr_type, text)?;
+                        if let (Some(row), Some(col)) = (row.as_mut(), curr_col) {
+                            row.cells.push((col, value));
+                            valued = true;
+                        }
+                    } else if formula_text {
+                        if let Some(formula) = formula.as_mut() {
+                            formula.push_str(&text);
+                        }
+                    } else if inline_text && !phonetic {
+                        inline_value.push_str(&text);
+                    }
+                }
+
+                Ok(Event::End(ref e)) => match e.name() {
+                    b"row" => {
+                        return Ok(row);
+                    }
+                    b"c" => {
+                        if let (Some(row), Some(col), Some(formula)) =
+                            (row.as_mut(), curr_col, formula.take())
+                        {
+                            if !valued {
+                                row.formulas.push((col, formula));
+                            }
+                        }
+                        curr_col = None;
+                    }
+                    b"f" => {
+                        formula_text = false;
+                        // the first cell of a shared formula holds its text, the others none
+                        if let (Some(row), Some(col), Some(si)) =
+                            (row.as_ref(), curr_col, shared_si.take())
+                        {
+                            match formula.as_deref() {
+                                Some("") | None => {
+                                    formula = Some(self.shared_formula(&si, row.row, col));
+                                }
+                                Some(text) => {
+                                    let first = (row.row, col).into();
+                                    self.shared_formulas.insert(si, (first, text.into()));
+                                }
+                            }
+                        }
+                    }
+                    b"v" => cell_value = false,
+                    b"t" => inline_text = false,
+                    b"rPh" => phonetic = false,
+                    b"is" => {
+                        inline_string = false;
+                        let value = CellValue::Text(self.intern(take(&mut inline_value)));
+                        if let (Some(row), Some(col)) = (row.as_mut(), curr_col) {
+                            row.cells.push((col, value));
+                            valued = true;
+                        }
+                    }
+                    _ => {}
+                },
+
+                Ok(Event::Empty(ref e)) => match e.name() {
+                    b"row" => {
+                        return Ok(Some(self.start_row(e)?));
+                    }
+                    b"c" => {
+                        let col = SheetRows::<B>::cell_column(e, row.as_ref(), next_col)?;
+                        self.cell_font(e, row.as_mut(), col);
+                        next_col = col + 1;
+                    }
+                    b"col" => {
+                        self.col(e)?;
+                    }
+                    b"f" if get_xml_attribute(e, b"t").as_deref() == Some(b"shared") => {
+                        let si = get_xml_attribute_string(e, b"si").unwrap_or_default();
+                        if let (Some(row), Some(col)) = (row.as_ref(), curr_col) {
+                            formula = Some(self.shared_formula(&si, row.row, col));
+                        }
+                    }
+                    b"mergeCell" => {
+                        self.merge_cell(e)?;
+                    }
+                    b"hyperlink" => {
+                        self.hyperlink(e)?;
+                    }
+                    _ => {}
+                },
+
+                Ok(Event::Eof) => {
+                    return Ok(None);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(self.xml_error(e));
+                }
+            }
+            buf.clear();
+        }
+    }
+}
+
+/// stops after the last row or at the first error
+impl<'a, B: BufRead> Iterator for SheetRows<'a, B> {
+    type Item = Result<Row, ExcelError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let row = self.next_row();
+        if !matches!(row, Ok(Some(_))) {
+            self.done = true;
+        }
+        return row.transpose();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_rows() {
+        let ss = vec![Rc::new("#input".to_string())];
+        let fonts = [
+            Font::default(),
+            Font {
+                strike: true,
+                ..Font::default()
+            },
+        ];
+        let xml = r#"<worksheet><cols><col min="2" max="3" width="0" hidden="1"/><col min="4" max="4"/></cols><sheetData>
+            <row r="2"><c r="A2" t="s"><v>0</v></c><c r="B2" s="1"/><c r="AA2" t="str"><v>x</v></c></row>
+            <row r="70000" spans="1:3" hidden="1"><c s="0"><v>1</v></c><c t="str" s="1"><v>x</v></c></row>
+            <row/>
+            </sheetData><mergeCells><mergeCell ref="A2:B2"/></mergeCells>
+            <hyperlinks><hyperlink ref="A2" r:id="rId1"/><hyperlink ref="AA2" location="'RV32M'!A1" display="M"/></hyperlinks></worksheet>"#;
+        let mut rows = SheetRows::new("sheet1.xml", xml.as_bytes(), &ss, &fonts);
+        let first = rows.next().unwrap().unwrap();
+        assert_eq!(Some(&(1..3)), rows.hidden_cols().first());
+        assert_eq!(1, first.row);
+        assert!(!first.hidden);
+        assert_eq!(vec![(1, fonts[1])], first.fonts);
+        assert_eq!(
+            vec![0, 26],
+            first.cells.iter().map(|c| c.0).collect::<Vec<_>>()
+        );
+        let row = rows.next().unwrap().unwrap();
+        assert_eq!(69999, row.row);
+        assert!(row.hidden);
+        assert_eq!(vec![(1, fonts[1])], row.fonts);
+        assert_eq!((0, CellValue::Number(1.0)), row.cells[0]);
+        assert_eq!(1, row.cells[1].0);
+        match (&first.cells[1].1, &row.cells[1].1) {
+            (CellValue::Text(a), CellValue::Text(b)) => assert!(Rc::ptr_eq(a, b)),
+            _ => panic!("formula strings are text"),
+        }
+        assert_eq!(Row::new(70000, Vec::new()), rows.next().unwrap().unwrap());
+        assert!(rows.next().is_none());
+        assert_eq!(&[CellRange::new("A2:B2")], rows.merges());
+        assert_eq!(Some("rId1"), rows.hyperlinks()[0].id.as_deref());
+        assert_eq!(CellRange::new("AA2:AA2"), rows.hyperlinks()[1].range);
+        assert_eq!(Some("'RV32M'!A1"), rows.hyperlinks()[1].location.as_deref());
+
+        let xml = r#"<sheetData><row r="2"/><row r="1"/></sheetData>"#;
+        let mut rows = SheetRows::new("sheet1.xml", xml.as_bytes(), &ss, &fonts);
+        assert!(rows.next().unwrap().is_ok());
+        assert!(matches!(
+            rows.next(),
+            Some(Err(ExcelError::BadCellReference(_)))
+        ));
+        assert!(rows.next().is_none());
+    }
+
+    #[test]
+    fn untrimmed_text() {
+        let ss = vec![Rc::new(" #input ".to_string())];
+        let xml = r#"<worksheet>
+            <sheetData>
+            <row r="1">
+                <c t="s"><v> 0 </v></c>
+                <c t="str"><v> a b </v></c>
+                <c t="inlineStr"><is><t xml:space="preserve"> c </t></is></c>
+                <c><v> 2 </v></c>
+                <c t="b"><v>1</v></c>
+            </row>
+            </sheetData></worksheet>"#;
+        let mut rows = SheetRows::new("sheet1.xml", xml.as_bytes(), &ss, &[]);
+        let row = rows.next().unwrap().unwrap();
+        let text = |v: &CellValue| match v {
+            CellValue::Text(t) => t.to_string(),
+            v => panic!("{:?}", v),
+        };
+        assert_eq!(" #input ", text(&row.cells[0].1));
+        assert_eq!(" a b ", text(&row.cells[1].1));
+        assert_eq!(" c ", text(&row.cells[2].1));
+        assert_eq!((3, CellValue::Number(2.0)), row.cells[3]);
+        assert_eq!((4, CellValue::Bool(true)), row.cells[4]);
+        assert!(rows.next().is_none());
+    }
+}
@@ -0,0 +1,120 @@
+use quick_xml::{self, events::Event, Reader};
+
+use super::{get_xml_attribute_string, ExcelError};
+
+/// font attributes of a cell, the ones a table can mean something by
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Font {
+    pub bold: bool,
+    pub italic: bool,
+    pub strike: bool,
+}
+
+impl Font {
+    pub fn is_default(&self) -> bool {
+        return *self == Font::default();
+    }
+}
+
+/// font of every cell format of `xl/styles.xml`, indexed like the `s` attribute of `<c>`
+pub fn cell_fonts(part: &str, xml: &str) -> Result<Vec<Font>, ExcelError> {
+    let mut fonts: Vec<Font> = Vec::new();
+    let mut cell_fonts = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut buf = Vec::with_capacity(64);
+
+    // differential formats of conditional formatting have fonts too, only `<fonts>` counts
+    let mut in_fonts = false;
+    let mut in_cell_xfs = false;
+
+    loop {
+        let event = reader.read_event(&mut buf);
+        let empty = matches!(event, Ok(Event::Empty(_)));
+        match event {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => match e.name() {
+                b"fonts" => in_fonts = !empty,
+                b"cellXfs" => in_cell_xfs = !empty,
+                b"font" if in_fonts => fonts.push(Font::default()),
+                b"b" | b"i" | b"strike" if in_fonts => {
+                    // `<b val="0"/>` turns the attribute off
+                    let on = !matches!(
+                        get_xml_attribute_string(e, b"val").as_deref(),
+                        Some("0") | Some("false")
+                    );
+                    if let Some(font) = fonts.last_mut() {
+                        match e.name() {
+                            b"b" => font.bold = on,
+                            b"i" => font.italic = on,
+                            _ => font.strike = on,
+                        }
+                    }
+                }
+                b"xf" if in_cell_xfs => {
+                    let font = get_xml_attribute_string(e, b"fontId")
+                        .and_then(|id| id.parse::<usize>().ok())
+                        .and_then(|id| fonts.get(id))
+                        .cloned()
+                        .unwrap_or_default();
+                    cell_fonts.push(font);
+                }
+                _ => {}
+            },
+            Ok(Event::End(ref e)) => match e.name() {
+                b"fonts" => in_fonts = false,
+                b"cellXfs" => in_cell_xfs = false,
+                _ => {}
+            },
+            Ok(Event::Eof) => {
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ExcelError::Xml {
+                    part: part.into(),
+                    position: reader.buffer_position(),
+                    error: e,
+                });
+            }
+        }
+        buf.clear();
+    }
+    return Ok(cell_fonts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles_fonts() {
+        let xml = r#"<styleSheet><fonts count="3">
+<font><sz val="11"/><name val="Calibri"/></font>
+<font><b/><strike/><sz val="11"/></font>
+<font><i/><strike val="0"/></font>
+</fonts>
+<cellStyleXfs count="1"><xf numFmtId="0" fontId="0"/></cellStyleXfs>
+<cellXfs count="4"><xf fontId="0"/><xf fontId="1" applyFont="1"/><xf fontId="2"><alignment/></xf><xf fontId="9"/></cellXfs>
+<dxfs count="1"><dxf><font><strike/></font></dxf></dxfs></styleSheet>"#;
+        let fonts = cell_fonts("xl/styles.xml", xml).unwrap();
+        assert_eq!(4, fonts.len());
+        assert!(fonts[0].is_default());
+        assert_eq!(
+            Font {
+                bold: true,
+                italic: false,
+                strike: true
+            },
+            fonts[1]
+        );
+        assert_eq!(
+            Font {
+                bold: false,
+                italic: true,
+                strike: false
+            },
+            fonts[2]
+        );
+        assert!(fonts[3].is_default());
+    }
+}
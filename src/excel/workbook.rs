@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::rc::Rc;
+
+use super::{Csv, DefinedName, Excel, ExcelError, NameValue, Ods, Sheet};
+
+/// spreadsheet of any supported format
+pub enum Book {
+    Excel(Excel<BufReader<File>>),
+    Ods(Ods),
+    Csv(Csv),
+}
+
+/// workbook of any supported format, chosen by the file extension
+pub struct Workbook {
+    pub book: Book,
+    /// other sheets names point into, read once for every sheet that sees the names,
+    /// `None` for a sheet the workbook does not have
+    referenced: HashMap<String, Result<Option<Sheet>, String>>,
+}
+
+#[allow(dead_code)]
+impl Workbook {
+    pub fn open(path: &Path) -> Result<Workbook, ExcelError> {
+        let file = path.to_string_lossy();
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        let book = match ext.as_deref() {
+            Some("ods") | Some("fods") => Book::Ods(Ods::open(&file)?),
+            Some("csv") | Some("tsv") | Some("tab") => Book::Csv(Csv::open(&file)?),
+            _ => Book::Excel(Excel::open(&file)?),
+        };
+        return Ok(Workbook::new(book));
+    }
+
+    pub fn new(book: Book) -> Workbook {
+        return Workbook {
+            book,
+            referenced: HashMap::new(),
+        };
+    }
+
+    pub fn sheets(&self) -> Vec<String> {
+        return match &self.book {
+            Book::Excel(excel) => excel.sheets(),
+            Book::Ods(ods) => ods.sheets(),
+            Book::Csv(csv) => csv.sheets(),
+        };
+    }
+
+    /// names of the workbook and of its sheets, csv tables have none
+    pub fn names(&self) -> &[DefinedName] {
+        return match &self.book {
+            Book::Excel(excel) => excel.names(),
+            Book::Ods(ods) => ods.names(),
+            Book::Csv(_) => &[],
+        };
+    }
+
+    fn cells(&mut self, name: &str) -> Result<Sheet, ExcelError> {
+        return match &mut self.book {
+            Book::Excel(excel) => excel.sheet(name),
+            Book::Ods(ods) => ods.sheet(name),
+            Book::Csv(csv) => csv.sheet(name),
+        };
+    }
+
+    /// cells of a sheet with the values of the names it sees,
+    /// names of the sheet itself hide names of the workbook,
+    /// names into a sheet that can not be read are left out and kept in `Sheet::unresolved`
+    pub fn sheet(&mut self, name: &str) -> Result<Sheet, ExcelError> {
+        let mut sheet = self.cells(name)?;
+        let mut names: Vec<DefinedName> = self
+            .names()
+            .iter()
+            .filter(|n| match &n.scope {
+                Some(scope) => scope.eq_ignore_ascii_case(name),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        names.sort_by_key(|n| n.scope.is_some());
+
+        for defined in names {
+            let values = match &defined.value {
+                NameValue::Constant(text) => vec![Rc::new(text.clone())],
+                NameValue::Reference(r) => match &r.sheet {
+                    // sheet names differ from each other ignoring case
+                    Some(other) if !other.eq_ignore_ascii_case(name) => {
+                        if !self.referenced.contains_key(other) {
+                            let cells = match self.cells(other) {
+                                Ok(cells) => Ok(Some(cells)),
+                                // a stale name is only an error when it is used
+                                Err(ExcelError::UnknownSheet(_)) => Ok(None),
+                                Err(e) => Err(e.to_string()),
+                            };
+                            self.referenced.insert(other.clone(), cells);
+                        }
+                        match &self.referenced[other] {
+                            Ok(Some(cells)) => cells.range_text(&r.range),
+                            Ok(None) => continue,
+                            Err(e) => {
+                                sheet
+                                    .unresolved
+                                    .push((defined.name.clone(), format!("{}: {}", other, e)));
+                                continue;
+                            }
+                        }
+                    }
+                    _ => sheet.range_text(&r.range),
+                },
+            };
+            sheet.names.insert(DefinedName::key(&defined.name), values);
+        }
+        return Ok(sheet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::excel::SheetSource;
+
+    #[test]
+    fn workbook_names() {
+        let content = r#"<office:document-content><office:body><office:spreadsheet>
+<table:table table:name="Main"><table:table-row><table:table-cell office:value-type="string"><text:p>=OPS</text:p></table:table-cell></table:table-row></table:table>
+<table:table table:name="Shared"><table:table-row><table:table-cell office:value-type="string"><text:p>7'b0110011</text:p></table:table-cell><table:table-cell office:value-type="string"><text:p>7'b0010011</text:p></table:table-cell></table:table-row></table:table>
+<table:table table:name="Broken"><table:table-row><table:table-cell office:value-type="float" office:value="x"/></table:table-row></table:table>
+<table:named-expressions>
+<table:named-range table:name="OPS" table:cell-range-address="$Shared.$A$1:.$B$1"/>
+<table:named-range table:name="BAD" table:cell-range-address="$Broken.$A$1"/>
+<table:named-range table:name="STALE" table:cell-range-address="$Gone.$A$1"/>
+<table:named-range table:name="SELF" table:cell-range-address="$main.$A$1"/>
+</table:named-expressions>
+</office:spreadsheet></office:body></office:document-content>"#;
+        let ods = Ods::from_content("content.xml", content.into()).unwrap();
+        let mut workbook = Workbook::new(Book::Ods(ods));
+        // a broken sheet only leaves out the names into it
+        for _ in 0..2 {
+            let sheet = workbook.sheet("Main").unwrap();
+            let ops: Vec<String> = sheet
+                .defined_name("OPS")
+                .unwrap()
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            assert_eq!(vec!["7'b0110011", "7'b0010011"], ops);
+            assert_eq!(None, sheet.defined_name("BAD"));
+            assert_eq!(None, sheet.defined_name("STALE"));
+            let own: Vec<String> = sheet
+                .defined_name("SELF")
+                .unwrap()
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            assert_eq!(vec!["=OPS"], own);
+            assert_eq!(
+                vec![("BAD".to_string(), "Broken: bad cell value `x`".to_string())],
+                sheet.unresolved
+            );
+        }
+        assert_eq!(3, workbook.referenced.len());
+        assert!(workbook.sheet("Broken").is_err());
+    }
+}
@@ -0,0 +1,1046 @@
+use quick_xml::{
+    self,
+    escape::escape,
+    events::{BytesStart, Event},
+    Reader, Writer,
+};
+use std::collections::{btree_map, BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Read, Seek, Write};
+use std::iter::Peekable;
+use std::path::Path;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use super::{
+    get_xml_attribute_string, relationships, rels_part, CellPosition, CellValue, Excel, ExcelError,
+};
+
+const MAIN_NS: &str = "http://schemas.openxmlformats.org/spreadsheetml/2006/main";
+const REL_NS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const WORKSHEET_REL: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet";
+const WORKSHEET_CONTENT: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml";
+
+const CONTENT_TYPES_PART: &str = "[Content_Types].xml";
+const WORKBOOK_PART: &str = "xl/workbook.xml";
+const STYLES_PART: &str = "xl/styles.xml";
+/// formula cells in calculation order, excel rebuilds it when it is missing
+const CALC_CHAIN_PART: &str = "xl/calcChain.xml";
+
+/// light red excel fills bad cells with
+pub const HIGHLIGHT_RED: u32 = 0xFFC7CE;
+
+/// parts of a workbook without any sheet
+const EMPTY_WORKBOOK: [(&str, &str); 5] = [
+    (
+        CONTENT_TYPES_PART,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#,
+    ),
+    (
+        "_rels/.rels",
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#,
+    ),
+    (
+        WORKBOOK_PART,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets/></workbook>"#,
+    ),
+    (
+        "xl/_rels/workbook.xml.rels",
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#,
+    ),
+    (
+        STYLES_PART,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><fonts count="1"><font><sz val="11"/><name val="Calibri"/><family val="2"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/></cellXfs><cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles></styleSheet>"#,
+    ),
+];
+
+/// what to change in a cell
+#[derive(Debug, Clone, Default)]
+struct CellEdit {
+    value: Option<CellValue>,
+    /// rgb of a solid fill
+    fill: Option<u32>,
+}
+
+/// edits of a sheet by (row, col), in the order cells appear in a worksheet
+type SheetEdits = BTreeMap<(u32, u16), CellEdit>;
+
+/// copy of a workbook with cells set or filled and sheets added,
+/// parts without edits are copied as they are
+pub struct XlsxWriter<R: Read + Seek> {
+    excel: Excel<R>,
+    edits: HashMap<String, SheetEdits>,
+    /// sheets appended to the workbook, in order
+    added: Vec<String>,
+}
+
+#[allow(dead_code)]
+impl XlsxWriter<BufReader<File>> {
+    pub fn open(file: &str) -> Result<XlsxWriter<BufReader<File>>, ExcelError> {
+        return Ok(XlsxWriter::new(Excel::open(file)?));
+    }
+}
+
+impl XlsxWriter<Cursor<Vec<u8>>> {
+    /// a new workbook, it needs a sheet before excel opens it
+    pub fn empty() -> Result<XlsxWriter<Cursor<Vec<u8>>>, ExcelError> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (part, xml) in EMPTY_WORKBOOK {
+            zip.start_file(part, options)?;
+            zip.write_all(xml.as_bytes())?;
+        }
+        let package = zip.finish()?;
+        return Ok(XlsxWriter::new(Excel::from_reader(package)?));
+    }
+}
+
+#[allow(dead_code)]
+impl<R: Read + Seek> XlsxWriter<R> {
+    pub fn new(excel: Excel<R>) -> XlsxWriter<R> {
+        return XlsxWriter {
+            excel,
+            edits: HashMap::new(),
+            added: Vec::new(),
+        };
+    }
+
+    /// sheets of the workbook and the ones added, in workbook order
+    pub fn sheets(&self) -> Vec<String> {
+        let mut sheets = self.excel.sheets();
+        sheets.extend(self.added.iter().cloned());
+        return sheets;
+    }
+
+    /// append an empty sheet, excel takes up to 31 characters and no `[]:*?/\`
+    pub fn add_sheet(&mut self, name: &str) -> Result<(), ExcelError> {
+        let bad = name.is_empty()
+            || name.chars().count() > 31
+            || name.contains(&['[', ']', ':', '*', '?', '/', '\\'][..])
+            || name.starts_with('\'')
+            || name.ends_with('\'')
+            || self
+                .sheets()
+                .iter()
+                .any(|s| s.to_lowercase() == name.to_lowercase());
+        if bad {
+            return Err(ExcelError::BadSheetName(name.into()));
+        }
+        self.added.push(name.into());
+        return Ok(());
+    }
+
+    fn edit(&mut self, sheet: &str, pos: &CellPosition) -> Result<&mut CellEdit, ExcelError> {
+        if !self.sheets().iter().any(|s| s == sheet) {
+            return Err(ExcelError::UnknownSheet(sheet.into()));
+        }
+        return Ok(self
+            .edits
+            .entry(sheet.into())
+            .or_default()
+            .entry(pos.tuple())
+            .or_default());
+    }
+
+    /// replace the value of a cell, its format is kept
+    pub fn set_value(
+        &mut self,
+        sheet: &str,
+        pos: &CellPosition,
+        value: CellValue,
+    ) -> Result<(), ExcelError> {
+        self.edit(sheet, pos)?.value = Some(value);
+        return Ok(());
+    }
+
+    /// fill a cell with a solid color, like `HIGHLIGHT_RED`
+    pub fn set_fill(
+        &mut self,
+        sheet: &str,
+        pos: &CellPosition,
+        rgb: u32,
+    ) -> Result<(), ExcelError> {
+        self.edit(sheet, pos)?.fill = Some(rgb);
+        return Ok(());
+    }
+
+    /// the workbook is written next to `path` and renamed over it once complete,
+    /// so `path` may be the file the workbook is read from
+    pub fn save_as(&mut self, path: &Path) -> Result<(), ExcelError> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+        let saved = File::create(&temp)
+            .map_err(ExcelError::from)
+            .and_then(|file| self.save(file))
+            .and_then(|file| Ok(file.sync_all()?))
+            .and_then(|_| Ok(fs::rename(&temp, path)?));
+        if saved.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        return saved;
+    }
+
+    /// write the workbook with the edits to `out`, the calculation chain is left out
+    /// as replaced formula cells would make it stale
+    pub fn save<W: Write + Seek>(&mut self, out: W) -> Result<W, ExcelError> {
+        let archive = &mut self.excel.archive;
+        let styles_xml = match Excel::get_xml(archive, STYLES_PART) {
+            Ok(xml) => Some(xml),
+            Err(ExcelError::MissingPart(_)) => None,
+            Err(e) => return Err(e),
+        };
+        let mut styles = FillStyles::parse(STYLES_PART, styles_xml.as_deref().unwrap_or(""))?;
+        let no_edits = SheetEdits::new();
+
+        // new content of the parts, by part path
+        let mut parts: HashMap<String, Vec<u8>> = HashMap::new();
+        for (name, part) in &self.excel.sheets {
+            if let Some(edits) = self.edits.get(name) {
+                let xml = Excel::get_xml(archive, part)?;
+                parts.insert(part.clone(), edit_sheet(part, &xml, edits, &mut styles)?);
+            }
+        }
+
+        let rels_part = rels_part(WORKBOOK_PART);
+        let rels_xml = Excel::get_xml(archive, &rels_part)?;
+        let rels = relationships(WORKBOOK_PART, &rels_xml)?;
+        let files: HashSet<String> = archive.file_names().map(|f| f.to_string()).collect();
+        // (name, part, relationship id)
+        let mut added: Vec<(String, String, String)> = Vec::new();
+        let (mut n, mut id) = (1, 1);
+        for name in &self.added {
+            let part = loop {
+                let part = format!("xl/worksheets/sheet{}.xml", n);
+                n += 1;
+                if !files.contains(&part) {
+                    break part;
+                }
+            };
+            let rel = loop {
+                let rel = format!("rId{}", id);
+                id += 1;
+                if !rels.contains_key(&rel) {
+                    break rel;
+                }
+            };
+            let xml = format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<worksheet xmlns=\"{}\"><sheetData/></worksheet>",
+                MAIN_NS
+            );
+            let edits = self.edits.get(name).unwrap_or(&no_edits);
+            parts.insert(part.clone(), edit_sheet(&part, &xml, edits, &mut styles)?);
+            added.push((name.clone(), part, rel));
+        }
+
+        let workbook_xml = Excel::get_xml(archive, WORKBOOK_PART)?;
+        let (max_id, rel_prefix) = workbook_ids(&workbook_xml)?;
+        let sheets = |p: &str| {
+            let mut xml = String::new();
+            for (idx, (name, _, rel)) in added.iter().enumerate() {
+                let id = match &rel_prefix {
+                    Some(r) => format!("{}:id=\"{}\"", r, rel),
+                    None => format!("xmlns:r=\"{}\" r:id=\"{}\"", REL_NS, rel),
+                };
+                xml.push_str(&format!(
+                    "<{p}sheet name=\"{}\" sheetId=\"{}\" {}/>",
+                    String::from_utf8_lossy(&escape(name.as_bytes())),
+                    max_id + 1 + idx as u32,
+                    id,
+                    p = p
+                ));
+            }
+            return xml;
+        };
+        parts.insert(
+            WORKBOOK_PART.into(),
+            insert_part(WORKBOOK_PART, &workbook_xml, b"sheets", &sheets, &|_| false)?,
+        );
+
+        let worksheet_rels = |p: &str| {
+            let mut xml = String::new();
+            for (_, part, rel) in &added {
+                xml.push_str(&format!(
+                    "<{p}Relationship Id=\"{}\" Type=\"{}\" Target=\"{}\"/>",
+                    rel,
+                    WORKSHEET_REL,
+                    part.strip_prefix("xl/").unwrap_or(part),
+                    p = p
+                ));
+            }
+            return xml;
+        };
+        let calc_chain_rel = |e: &BytesStart| {
+            return e.local_name() == b"Relationship"
+                && get_xml_attribute_string(e, b"Type")
+                    .map(|t| t.ends_with("/calcChain"))
+                    .unwrap_or(false);
+        };
+        parts.insert(
+            rels_part.clone(),
+            insert_part(
+                &rels_part,
+                &rels_xml,
+                b"Relationships",
+                &worksheet_rels,
+                &calc_chain_rel,
+            )?,
+        );
+
+        let content_types_xml = Excel::get_xml(archive, CONTENT_TYPES_PART)?;
+        let overrides = |p: &str| {
+            let mut xml = String::new();
+            for (_, part, _) in &added {
+                xml.push_str(&format!(
+                    "<{p}Override PartName=\"/{}\" ContentType=\"{}\"/>",
+                    part,
+                    WORKSHEET_CONTENT,
+                    p = p
+                ));
+            }
+            return xml;
+        };
+        let calc_chain_type = |e: &BytesStart| {
+            return e.local_name() == b"Override"
+                && get_xml_attribute_string(e, b"PartName").as_deref()
+                    == Some(&format!("/{}", CALC_CHAIN_PART)[..]);
+        };
+        parts.insert(
+            CONTENT_TYPES_PART.into(),
+            insert_part(
+                CONTENT_TYPES_PART,
+                &content_types_xml,
+                b"Types",
+                &overrides,
+                &calc_chain_type,
+            )?,
+        );
+
+        if styles.changed() {
+            match &styles_xml {
+                Some(xml) => parts.insert(STYLES_PART.into(), styles.write(STYLES_PART, xml)?),
+                None => return Err(ExcelError::MissingPart(STYLES_PART.into())),
+            };
+        }
+
+        let mut zip = ZipWriter::new(out);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for idx in 0..archive.len() {
+            let file = archive.by_index_raw(idx)?;
+            let name = file.name().to_string();
+            if name == CALC_CHAIN_PART {
+                continue;
+            }
+            match parts.remove(&name) {
+                Some(content) => {
+                    drop(file);
+                    zip.start_file(name, options)?;
+                    zip.write_all(&content)?;
+                }
+                None => zip.raw_copy_file(file)?,
+            }
+        }
+        for (_, part, _) in &added {
+            zip.start_file(part.clone(), options)?;
+            zip.write_all(&parts.remove(part).unwrap_or_default())?;
+        }
+        return Ok(zip.finish()?);
+    }
+}
+
+fn xml_error(part: &str, position: usize, error: quick_xml::Error) -> ExcelError {
+    return ExcelError::Xml {
+        part: part.into(),
+        position,
+        error,
+    };
+}
+
+/// `x:` of `x:sheetData`, new elements take the prefix of their parent
+fn prefix(name: &[u8]) -> String {
+    return match name.iter().position(|c| *c == b':') {
+        Some(idx) => String::from_utf8_lossy(&name[..=idx]).into_owned(),
+        None => String::new(),
+    };
+}
+
+/// copy of an element with an attribute set, or removed when `value` is `None`
+fn with_attribute(e: &BytesStart, key: &[u8], value: Option<&str>) -> BytesStart<'static> {
+    let mut copy = BytesStart::owned_name(e.name().to_vec());
+    copy.extend_attributes(e.attributes().flatten().filter(|a| a.key != key));
+    if let Some(value) = value {
+        copy.push_attribute((key, value.as_bytes()));
+    }
+    return copy;
+}
+
+/// highest `sheetId` of a workbook, and the prefix it declares for relationship ids
+fn workbook_ids(xml: &str) -> Result<(u32, Option<String>), ExcelError> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::with_capacity(64);
+    let mut max_id = 0;
+    let mut rel_prefix = None;
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) => {
+                for attr in e.attributes().flatten() {
+                    if attr.key.starts_with(b"xmlns:") && &attr.value[..] == REL_NS.as_bytes() {
+                        rel_prefix = Some(String::from_utf8_lossy(&attr.key[6..]).into_owned());
+                    }
+                }
+                if e.local_name() == b"sheet" {
+                    let id = get_xml_attribute_string(e, b"sheetId")
+                        .and_then(|id| id.parse::<u32>().ok())
+                        .unwrap_or(0);
+                    max_id = max_id.max(id);
+                }
+            }
+            Ok(Event::Eof) => {
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => return Err(xml_error(WORKBOOK_PART, reader.buffer_position(), e)),
+        }
+        buf.clear();
+    }
+    return Ok((max_id, rel_prefix));
+}
+
+/// copy of a part with `extra` written at the end of the first `parent` element,
+/// elements `leave_out` matches are not copied
+fn insert_part(
+    part: &str,
+    xml: &str,
+    parent: &[u8],
+    extra: &dyn Fn(&str) -> String,
+    leave_out: &dyn Fn(&BytesStart) -> bool,
+) -> Result<Vec<u8>, ExcelError> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len() + 256));
+    let mut buf = Vec::with_capacity(64);
+    let mut inserted = false;
+    // depth inside an element left out
+    let mut skip = 0;
+    loop {
+        let event = reader
+            .read_event(&mut buf)
+            .map_err(|e| xml_error(part, reader.buffer_position(), e))?;
+        let position = reader.buffer_position();
+        if skip > 0 {
+            match event {
+                Event::Start(_) => skip += 1,
+                Event::End(_) => skip -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+            continue;
+        }
+        match event {
+            Event::Start(ref e) if leave_out(e) => skip = 1,
+            Event::Empty(ref e) if leave_out(e) => {}
+            Event::Empty(ref e) if !inserted && e.local_name() == parent => {
+                inserted = true;
+                let p = prefix(e.name());
+                writer
+                    .write_event(Event::Start(e.to_borrowed()))
+                    .and_then(|_| writer.write(extra(&p).as_bytes()))
+                    .and_then(|_| writer.write_event(Event::End(e.to_end())))
+                    .map_err(|e| xml_error(part, position, e))?;
+            }
+            Event::End(ref e) if !inserted && e.local_name() == parent => {
+                inserted = true;
+                writer
+                    .write(extra(&prefix(e.name())).as_bytes())
+                    .and_then(|_| writer.write_event(&event))
+                    .map_err(|e| xml_error(part, position, e))?;
+            }
+            Event::Eof => {
+                break;
+            }
+            _ => writer
+                .write_event(&event)
+                .map_err(|e| xml_error(part, position, e))?,
+        }
+        buf.clear();
+    }
+    return Ok(writer.into_inner());
+}
+
+/// fills and cell formats appended to `xl/styles.xml` for filled cells
+struct FillStyles {
+    /// `<fill>` in `<fills>`
+    fills: usize,
+    /// events of every `<xf>` in `<cellXfs>`, copied with another fill
+    formats: Vec<Vec<Event<'static>>>,
+    colors: Vec<u32>,
+    /// (format copied, color) of every format added
+    added: Vec<(usize, u32)>,
+}
+
+impl FillStyles {
+    fn parse(part: &str, xml: &str) -> Result<FillStyles, ExcelError> {
+        let mut styles = FillStyles {
+            fills: 0,
+            formats: Vec::new(),
+            colors: Vec::new(),
+            added: Vec::new(),
+        };
+        let mut reader = Reader::from_str(xml);
+        let mut buf = Vec::with_capacity(64);
+        let mut in_fills = false;
+        let mut in_cell_xfs = false;
+        // events of the `<xf>` being read
+        let mut format: Option<Vec<Event<'static>>> = None;
+        loop {
+            let event = reader
+                .read_event(&mut buf)
+                .map_err(|e| xml_error(part, reader.buffer_position(), e))?;
+            if let Some(events) = format.as_mut() {
+                events.push(event.clone().into_owned());
+                if matches!(&event, Event::End(e) if e.local_name() == b"xf") {
+                    styles.formats.extend(format.take());
+                }
+                buf.clear();
+                continue;
+            }
+            match event {
+                Event::Start(ref e) => match e.local_name() {
+                    b"fills" => in_fills = true,
+                    b"cellXfs" => in_cell_xfs = true,
+                    b"fill" if in_fills => styles.fills += 1,
+                    b"xf" if in_cell_xfs => format = Some(vec![event.clone().into_owned()]),
+                    _ => {}
+                },
+                Event::Empty(ref e) => match e.local_name() {
+                    b"fill" if in_fills => styles.fills += 1,
+                    b"xf" if in_cell_xfs => styles.formats.push(vec![event.clone().into_owned()]),
+                    _ => {}
+                },
+                Event::End(ref e) => match e.local_name() {
+                    b"fills" => in_fills = false,
+                    b"cellXfs" => in_cell_xfs = false,
+                    _ => {}
+                },
+                Event::Eof => {
+                    break;
+                }
+                _ => {}
+            }
+            buf.clear();
+        }
+        return Ok(styles);
+    }
+
+    fn changed(&self) -> bool {
+        return !self.added.is_empty();
+    }
+
+    /// `s` of a cell formatted like `base` and filled with `rgb`
+    fn style(&mut self, base: u32, rgb: u32) -> u32 {
+        let base = if (base as usize) < self.formats.len() {
+            base as usize
+        } else {
+            0
+        };
+        if !self.colors.contains(&rgb) {
+            self.colors.push(rgb);
+        }
+        let idx = match self.added.iter().position(|a| *a == (base, rgb)) {
+            Some(idx) => idx,
+            None => {
+                self.added.push((base, rgb));
+                self.added.len() - 1
+            }
+        };
+        return (self.formats.len() + idx) as u32;
+    }
+
+    fn fill_id(&self, rgb: u32) -> usize {
+        return self.fills + self.colors.iter().position(|c| *c == rgb).unwrap();
+    }
+
+    fn write(&self, part: &str, xml: &str) -> Result<Vec<u8>, ExcelError> {
+        let mut reader = Reader::from_str(xml);
+        let mut writer = Writer::new(Vec::with_capacity(xml.len() + 256));
+        let mut buf = Vec::with_capacity(64);
+        loop {
+            let event = reader
+                .read_event(&mut buf)
+                .map_err(|e| xml_error(part, reader.buffer_position(), e))?;
+            let position = reader.buffer_position();
+            let written = match event {
+                // counts are optional, they are kept right when they are there
+                Event::Start(ref e) if e.local_name() == b"fills" => {
+                    let count = (self.fills + self.colors.len()).to_string();
+                    writer.write_event(Event::Start(FillStyles::count(e, &count)))
+                }
+                Event::Start(ref e) if e.local_name() == b"cellXfs" => {
+                    let count = (self.formats.len() + self.added.len()).to_string();
+                    writer.write_event(Event::Start(FillStyles::count(e, &count)))
+                }
+                Event::End(ref e) if e.local_name() == b"fills" => {
+                    let p = prefix(e.name());
+                    for rgb in &self.colors {
+                        let fill = format!(
+                            "<{p}fill><{p}patternFill patternType=\"solid\"><{p}fgColor rgb=\"FF{:06X}\"/><{p}bgColor indexed=\"64\"/></{p}patternFill></{p}fill>",
+                            rgb,
+                            p = p
+                        );
+                        writer
+                            .write(fill.as_bytes())
+                            .map_err(|e| xml_error(part, position, e))?;
+                    }
+                    writer.write_event(&event)
+                }
+                Event::End(ref e) if e.local_name() == b"cellXfs" => {
+                    for (base, rgb) in &self.added {
+                        let fill_id = self.fill_id(*rgb).to_string();
+                        for (idx, format_event) in self.formats[*base].iter().enumerate() {
+                            let copy = match format_event {
+                                Event::Start(xf) if idx == 0 => {
+                                    Event::Start(FillStyles::filled(xf, &fill_id))
+                                }
+                                Event::Empty(xf) if idx == 0 => {
+                                    Event::Empty(FillStyles::filled(xf, &fill_id))
+                                }
+                                _ => format_event.clone(),
+                            };
+                            writer
+                                .write_event(copy)
+                                .map_err(|e| xml_error(part, position, e))?;
+                        }
+                    }
+                    writer.write_event(&event)
+                }
+                Event::Eof => {
+                    break;
+                }
+                _ => writer.write_event(&event),
+            };
+            written.map_err(|e| xml_error(part, position, e))?;
+            buf.clear();
+        }
+        return Ok(writer.into_inner());
+    }
+
+    fn count(e: &BytesStart, count: &str) -> BytesStart<'static> {
+        if get_xml_attribute_string(e, b"count").is_none() {
+            return e.to_owned();
+        }
+        return with_attribute(e, b"count", Some(count));
+    }
+
+    fn filled(xf: &BytesStart, fill_id: &str) -> BytesStart<'static> {
+        let xf = with_attribute(xf, b"fillId", Some(fill_id));
+        return with_attribute(&xf, b"applyFill", Some("1"));
+    }
+}
+
+/// writes the edits of a sheet into the worksheet being copied
+struct SheetEditor<'a, 'b> {
+    part: &'a str,
+    writer: Writer<Vec<u8>>,
+    /// namespace prefix of the worksheet elements
+    prefix: String,
+    pending: Peekable<btree_map::Iter<'a, (u32, u16), CellEdit>>,
+    styles: &'b mut FillStyles,
+}
+
+impl<'a, 'b> SheetEditor<'a, 'b> {
+    fn write(&mut self, xml: &str) -> Result<(), ExcelError> {
+        return self
+            .writer
+            .write(xml.as_bytes())
+            .map_err(|e| xml_error(self.part, 0, e));
+    }
+
+    fn write_event(&mut self, event: &Event) -> Result<(), ExcelError> {
+        return self
+            .writer
+            .write_event(event)
+            .map_err(|e| xml_error(self.part, 0, e));
+    }
+
+    fn pending_row(&mut self) -> Option<u32> {
+        return self.pending.peek().map(|((row, _), _)| *row);
+    }
+
+    /// `s` of an edited cell formatted with `base`
+    fn style(&mut self, base: Option<u32>, edit: &CellEdit) -> Option<u32> {
+        return match edit.fill {
+            Some(rgb) => Some(self.styles.style(base.unwrap_or(0), rgb)),
+            None => base,
+        };
+    }
+
+    /// new rows for the edits before `row`, or for all of them
+    fn flush_rows(&mut self, before: Option<u32>) -> Result<(), ExcelError> {
+        while let Some(row) = self.pending_row() {
+            if before.map(|b| row >= b).unwrap_or(false) {
+                break;
+            }
+            let xml = format!("<{}row r=\"{}\">", self.prefix, row + 1);
+            self.write(&xml)?;
+            self.flush_cells(row, None)?;
+            let xml = format!("</{}row>", self.prefix);
+            self.write(&xml)?;
+        }
+        return Ok(());
+    }
+
+    /// new cells for the edits of `row` before `col`, or for all of them
+    fn flush_cells(&mut self, row: u32, before: Option<u16>) -> Result<(), ExcelError> {
+        while let Some(((r, c), edit)) = self.pending.peek() {
+            if *r != row || before.map(|b| *c >= b).unwrap_or(false) {
+                break;
+            }
+            let (pos, edit) = ((*r, *c), *edit);
+            self.pending.next();
+            let style = self.style(None, edit);
+            self.write_cell(pos, style, edit.value.as_ref())?;
+        }
+        return Ok(());
+    }
+
+    fn take(&mut self, row: u32, col: u16) -> Option<&'a CellEdit> {
+        if let Some(((r, c), _)) = self.pending.peek() {
+            if (*r, *c) == (row, col) {
+                return self.pending.next().map(|(_, edit)| edit);
+            }
+        }
+        return None;
+    }
+
+    /// text is written inline, the shared strings are left as they are
+    fn write_cell(
+        &mut self,
+        pos: (u32, u16),
+        style: Option<u32>,
+        value: Option<&CellValue>,
+    ) -> Result<(), ExcelError> {
+        let p = &self.prefix;
+        let r = CellPosition::from_tuple(pos).to_a1();
+        let s = style.map(|s| format!(" s=\"{}\"", s)).unwrap_or_default();
+        let xml = match value {
+            None => format!("<{p}c r=\"{}\"{}/>", r, s, p = p),
+            Some(CellValue::Text(text)) => format!(
+                "<{p}c r=\"{}\"{} t=\"inlineStr\"><{p}is><{p}t xml:space=\"preserve\">{}</{p}t></{p}is></{p}c>",
+                r,
+                s,
+                String::from_utf8_lossy(&escape(text.as_bytes())),
+                p = p
+            ),
+            Some(CellValue::Number(n)) => {
+                format!("<{p}c r=\"{}\"{}><{p}v>{}</{p}v></{p}c>", r, s, n, p = p)
+            }
+            Some(CellValue::Bool(b)) => format!(
+                "<{p}c r=\"{}\"{} t=\"b\"><{p}v>{}</{p}v></{p}c>",
+                r,
+                s,
+                *b as u8,
+                p = p
+            ),
+        };
+        return self.write(&xml);
+    }
+}
+
+/// copy of a worksheet with the edits applied, rows and cells are added where they are missing
+fn edit_sheet(
+    part: &str,
+    xml: &str,
+    edits: &SheetEdits,
+    styles: &mut FillStyles,
+) -> Result<Vec<u8>, ExcelError> {
+    let mut reader = Reader::from_str(xml);
+    // whitespace of text cells is significant
+    reader.trim_text(false);
+    let mut editor = SheetEditor {
+        part,
+        writer: Writer::new(Vec::with_capacity(xml.len() + 64 * edits.len())),
+        prefix: String::new(),
+        pending: edits.iter().peekable(),
+        styles,
+    };
+    let mut buf = Vec::with_capacity(64);
+
+    let mut in_sheet_data = false;
+    let mut row: Option<u32> = None;
+    // rows and cells may leave out their reference
+    let mut next_row: u32 = 0;
+    let mut next_col: u16 = 0;
+    // depth inside a cell whose value is replaced
+    let mut skip = 0;
+
+    loop {
+        let event = reader
+            .read_event(&mut buf)
+            .map_err(|e| xml_error(part, reader.buffer_position(), e))?;
+        if skip > 0 {
+            match event {
+                Event::Start(_) => skip += 1,
+                Event::End(_) => skip -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+            continue;
+        }
+        let empty = matches!(event, Event::Empty(_));
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name() == b"sheetData" => {
+                editor.prefix = prefix(e.name());
+                editor.write_event(&Event::Start(e.to_borrowed()))?;
+                in_sheet_data = !empty;
+                if empty {
+                    editor.flush_rows(None)?;
+                    editor.write_event(&Event::End(e.to_end()))?;
+                }
+            }
+            Event::End(ref e) if e.local_name() == b"sheetData" => {
+                in_sheet_data = false;
+                editor.flush_rows(None)?;
+                editor.write_event(&event)?;
+            }
+            Event::Start(ref e) | Event::Empty(ref e)
+                if in_sheet_data && e.local_name() == b"row" =>
+            {
+                let r = match get_xml_attribute_string(e, b"r") {
+                    Some(r) => match r.parse::<u32>() {
+                        Ok(r) if r > 0 => r - 1,
+                        _ => return Err(ExcelError::BadCellReference(r)),
+                    },
+                    None => next_row,
+                };
+                editor.flush_rows(Some(r))?;
+                next_row = r + 1;
+                next_col = 0;
+                if editor.pending_row() == Some(r) {
+                    // the column span of the row is only a hint, and no longer right
+                    let e = with_attribute(e, b"spans", None);
+                    editor.write_event(&Event::Start(e.to_borrowed()))?;
+                    if empty {
+                        editor.flush_cells(r, None)?;
+                        editor.write_event(&Event::End(e.to_end()))?;
+                    }
+                } else {
+                    editor.write_event(&event)?;
+                }
+                if !empty {
+                    row = Some(r);
+                }
+            }
+            Event::End(ref e) if in_sheet_data && e.local_name() == b"row" => {
+                if let Some(r) = row.take() {
+                    editor.flush_cells(r, None)?;
+                }
+                editor.write_event(&event)?;
+            }
+            Event::Start(ref e) | Event::Empty(ref e)
+                if row.is_some() && e.local_name() == b"c" =>
+            {
+                let r = row.unwrap();
+                let col = match get_xml_attribute_string(e, b"r") {
+                    Some(pos) => match CellPosition::parse(&pos) {
+                        Some(pos) => pos.col,
+                        None => return Err(ExcelError::BadCellReference(pos)),
+                    },
+                    None => next_col,
+                };
+                editor.flush_cells(r, Some(col))?;
+                next_col = col + 1;
+                match editor.take(r, col) {
+                    Some(edit) => {
+                        let base = get_xml_attribute_string(e, b"s").and_then(|s| s.parse().ok());
+                        let style = editor.style(base, edit);
+                        match &edit.value {
+                            Some(value) => {
+                                editor.write_cell((r, col), style, Some(value))?;
+                                if !empty {
+                                    skip = 1;
+                                }
+                            }
+                            None => {
+                                let s = style.map(|s| s.to_string());
+                                let e = with_attribute(e, b"s", s.as_deref());
+                                editor.write_event(&match empty {
+                                    true => Event::Empty(e),
+                                    false => Event::Start(e),
+                                })?;
+                            }
+                        }
+                    }
+                    None => editor.write_event(&event)?,
+                }
+            }
+            Event::Eof => {
+                break;
+            }
+            _ => editor.write_event(&event)?,
+        }
+        buf.clear();
+    }
+    return Ok(editor.writer.into_inner());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn text(text: &str) -> CellValue {
+        return CellValue::Text(Rc::new(text.into()));
+    }
+
+    #[test]
+    fn write_new_workbook() {
+        let mut writer = XlsxWriter::empty().unwrap();
+        assert!(matches!(
+            writer.set_value("Report", &"A1".into(), text("x")),
+            Err(ExcelError::UnknownSheet(_))
+        ));
+        writer.add_sheet("Report").unwrap();
+        for name in ["report", "a:b", "", "'quoted'"] {
+            assert!(matches!(
+                writer.add_sheet(name),
+                Err(ExcelError::BadSheetName(_))
+            ));
+        }
+        writer
+            .set_value("Report", &"A1".into(), text("a < \"b\" & c"))
+            .unwrap();
+        writer
+            .set_value("Report", &"C1".into(), CellValue::Number(32.0))
+            .unwrap();
+        writer
+            .set_value("Report", &"B3".into(), CellValue::Bool(true))
+            .unwrap();
+        writer
+            .set_fill("Report", &"B3".into(), HIGHLIGHT_RED)
+            .unwrap();
+
+        let package = writer.save(Cursor::new(Vec::new())).unwrap();
+        let mut excel = Excel::from_reader(package).unwrap();
+        assert_eq!(vec!["Report".to_string()], excel.sheets());
+        let sheet = excel.sheet("Report").unwrap();
+        assert_eq!("a < \"b\" & c", sheet.content(0, 0).unwrap().0.as_str());
+        assert_eq!("32", sheet.content(0, 2).unwrap().0.as_str());
+        assert_eq!("TRUE", sheet.content(2, 1).unwrap().0.as_str());
+
+        let styles = Excel::get_xml(&mut excel.archive, STYLES_PART).unwrap();
+        assert!(styles.contains(r#"<fills count="3">"#));
+        assert!(styles.contains(r#"<fgColor rgb="FFFFC7CE"/>"#));
+        assert!(styles.contains(
+            r#"<xf numFmtId="0" fontId="0" borderId="0" xfId="0" fillId="2" applyFill="1"/>"#
+        ));
+    }
+
+    #[test]
+    fn annotate_copy() {
+        let mut writer = XlsxWriter::empty().unwrap();
+        writer.add_sheet("RV32I").unwrap();
+        writer
+            .set_value("RV32I", &"A1".into(), text("#match"))
+            .unwrap();
+        writer
+            .set_value("RV32I", &"B2".into(), text("lui"))
+            .unwrap();
+        writer
+            .set_value("RV32I", &"B4".into(), text("auipc"))
+            .unwrap();
+        let package = writer.save(Cursor::new(Vec::new())).unwrap();
+
+        let mut writer = XlsxWriter::new(Excel::from_reader(package).unwrap());
+        writer
+            .set_fill("RV32I", &"B4".into(), HIGHLIGHT_RED)
+            .unwrap();
+        writer.set_value("RV32I", &"A2".into(), text("ok")).unwrap();
+        writer
+            .set_value("RV32I", &"D2".into(), text("inst_lui"))
+            .unwrap();
+        writer
+            .set_value("RV32I", &"B2".into(), text("LUI"))
+            .unwrap();
+        writer
+            .set_value("RV32I", &"A3".into(), text("new row"))
+            .unwrap();
+        writer.add_sheet("Report").unwrap();
+        writer
+            .set_value("Report", &"A1".into(), text("conflict"))
+            .unwrap();
+        let package = writer.save(Cursor::new(Vec::new())).unwrap();
+
+        let mut excel = Excel::from_reader(package).unwrap();
+        assert_eq!(
+            vec!["RV32I".to_string(), "Report".to_string()],
+            excel.sheets()
+        );
+        let sheet = excel.sheet("RV32I").unwrap();
+        let cells: Vec<(usize, usize, String)> = (0..sheet.rows())
+            .flat_map(|r| (0..sheet.cols(r)).map(move |c| (r, c)))
+            .filter_map(|(r, c)| Some((r, c, sheet.content(r, c)?.0.to_string())))
+            .collect();
+        assert_eq!(
+            vec![
+                (0, 0, "#match".to_string()),
+                (1, 0, "ok".to_string()),
+                (1, 1, "LUI".to_string()),
+                (1, 3, "inst_lui".to_string()),
+                (2, 0, "new row".to_string()),
+                (3, 1, "auipc".to_string()),
+            ],
+            cells
+        );
+        let report = excel.sheet("Report").unwrap();
+        assert_eq!("conflict", report.content(0, 0).unwrap().0.as_str());
+
+        let part = excel.sheets[0].1.clone();
+        let xml = Excel::get_xml(&mut excel.archive, &part).unwrap();
+        assert!(xml.contains(r#"<c r="B4" t="inlineStr" s="1">"#));
+        let workbook = Excel::get_xml(&mut excel.archive, WORKBOOK_PART).unwrap();
+        assert!(workbook.contains(r#"<sheet name="Report" sheetId="2" r:id="rId3"/>"#));
+    }
+    #[test]
+    fn annotate_in_place() {
+        let path = std::env::temp_dir().join(format!("annotate_{}.xlsx", std::process::id()));
+        let mut writer = XlsxWriter::empty().unwrap();
+        writer.add_sheet("RV32I").unwrap();
+        writer
+            .set_value("RV32I", &"A1".into(), text("#match"))
+            .unwrap();
+        writer.save_as(&path).unwrap();
+
+        // the workbook being read is only replaced once the new one is complete
+        let mut writer = XlsxWriter::open(&path.to_string_lossy()).unwrap();
+        writer
+            .set_fill("RV32I", &"A1".into(), HIGHLIGHT_RED)
+            .unwrap();
+        writer.add_sheet("Report").unwrap();
+        writer
+            .set_value("Report", &"A1".into(), text("ok"))
+            .unwrap();
+        writer.save_as(&path).unwrap();
+
+        let mut excel = Excel::open(&path.to_string_lossy()).unwrap();
+        assert_eq!(
+            vec!["RV32I".to_string(), "Report".to_string()],
+            excel.sheets()
+        );
+        let sheet = excel.sheet("RV32I").unwrap();
+        assert_eq!("#match", sheet.content(0, 0).unwrap().0.as_str());
+        let report = excel.sheet("Report").unwrap();
+        assert_eq!("ok", report.content(0, 0).unwrap().0.as_str());
+        let leftover = fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .any(|e| e.file_name().to_string_lossy().starts_with(".annotate_"));
+        fs::remove_file(&path).unwrap();
+        assert!(!leftover);
+    }
+}
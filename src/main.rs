@@ -1,37 +1,160 @@
+#[allow(dead_code)]
+mod backend;
+mod cli;
 mod excel;
 #[allow(dead_code)]
 mod match_table;
 mod parser;
 #[allow(dead_code)]
 mod verilog_model;
-use excel::Sheet;
+use backend::VerilogPrinter;
+use excel::{CellValue, ExcelError, SheetSource, XlsxWriter};
 use match_table::*;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::{fs, ops::Range, process, rc::Rc};
 mod utils;
 
-fn create_model(sheet: &Sheet) {
+/// text of a cell, its note and its location
+type NotedCell = (Rc<String>, Option<String>, String);
+
+/// cells of a directive row, a `=Name` cell stands for every value of the name
+fn directive_cells(
+    sheet: &dyn SheetSource,
+    ridx: usize,
+    cols: Range<usize>,
+) -> Result<Vec<NotedCell>, verilog_model::Error> {
+    let mut cells = Vec::new();
+    for cidx in cols {
+        if let Some((text, _)) = sheet.content(ridx, cidx) {
+            let note = sheet.note(ridx, cidx);
+            let cell = sheet.location(ridx, cidx);
+            match name_values(sheet, &text)? {
+                Some(values) => {
+                    cells.extend(values.into_iter().map(|v| (v, note.clone(), cell.clone())))
+                }
+                None => cells.push((text, note, cell)),
+            }
+        }
+    }
+    return Ok(cells);
+}
+
+/// wraps an error into an error of the cell at `cell`
+fn in_cell(cell: &str) -> impl Fn(verilog_model::Error) -> verilog_model::Error + '_ {
+    return move |error| verilog_model::Error::InCell {
+        cell: cell.into(),
+        error: Box::new(error),
+    };
+}
+
+/// like: [31:0]name, [XLEN-1:0]name, (name, width), errors are of the cell at `cell`,
+/// a signal starts at bit 0
+fn declaration(
+    module: &verilog_model::Module,
+    text: &str,
+    cell: &str,
+) -> Result<(String, verilog_model::SignalWidth), verilog_model::Error> {
+    let bad_declaration = || verilog_model::Error::BadDeclaration {
+        cell: cell.into(),
+        text: text.into(),
+    };
+    let (name, h, l) = match parser::signal_def(text) {
+        Ok((_, ((h, l), name))) => (name, h, l),
+        Err(_) => match parser::signal_def_expr(text) {
+            Ok((_, ((h, l), name))) => (
+                name,
+                h.eval_index(module).map_err(in_cell(cell))?,
+                l.eval_index(module).map_err(in_cell(cell))?,
+            ),
+            Err(_) => return Err(bad_declaration()),
+        },
+    };
+    return match (h.checked_add(1), l) {
+        (Some(width), 0) => Ok((name.into(), width)),
+        _ => Err(bad_declaration()),
+    };
+}
+
+/// `#assign | [h:l]target | expression`, a target with a range declares a new signal
+fn assign_row(
+    module: &mut verilog_model::Module,
+    sheet: &dyn SheetSource,
+    ridx: usize,
+) -> Result<(), verilog_model::Error> {
+    let cell = |cidx| sheet.content(ridx, cidx).map(|(text, _)| text);
+    let bad_cell = |cidx| verilog_model::Error::BadExpression {
+        cell: sheet.location(ridx, cidx),
+        text: cell(cidx).map(|text| text.to_string()).unwrap_or_default(),
+    };
+    let target = cell(1).ok_or_else(|| bad_cell(1))?;
+    let key: verilog_model::SignalKey = match target.starts_with('[') {
+        true => {
+            let location = sheet.location(ridx, 1);
+            let (name, width) = declaration(module, &target, &location)?;
+            module.new_signal(name, width).map_err(in_cell(&location))?
+        }
+        false => match parser::identifier(&target) {
+            Ok(("", name)) if module.get_signals().contains_key(name) => name.into(),
+            Ok(("", name)) => {
+                return Err(verilog_model::Error::UndefinedSignal {
+                    signal: name.into(),
+                })
+            }
+            _ => return Err(bad_cell(1)),
+        },
+    };
+    let expr = match cell(2).and_then(|text| parser::expression_cell(&text)) {
+        Some(expr) => expr,
+        None => return Err(bad_cell(2)),
+    };
+    verbose!(1, "assign {} = {}", key.as_str(), expr);
+    let width = module.get_signals()[&key].length;
+    let logic = expr.logic(module, width)?;
+    module.drive(&key, verilog_model::SignalSource::Logic(logic))?;
+    for cidx in 1..3 {
+        if let Some(note) = sheet.note(ridx, cidx) {
+            module.document(&key, &note);
+        }
+    }
+    return Ok(());
+}
+
+/// tables look back at rows above them and merges are only known after the last row,
+/// so a model is built from a whole sheet, the sparse one gathered from its streamed rows
+fn create_model(
+    sheet: &dyn SheetSource,
+    include_disabled: bool,
+) -> Result<verilog_model::Module, verilog_model::Error> {
     let mut module = verilog_model::Module::new();
     let mut section: Section = Section::None;
-    let mut row_iter = sheet.cells.iter().enumerate();
-    while let Some((ridx, row)) = row_iter.next() {
-        let mut col_iter = 0..row.len();
+    for ridx in 0..sheet.rows() {
+        let mut col_iter = 0..sheet.cols(ridx);
         if let Some(cidx) = col_iter.next() {
             match sheet.content(ridx, cidx) {
                 Some((text, _offset)) => match text.as_str() {
                     "#input" => {
-                        for cidx in col_iter {
-                            if let Some((input, _)) = sheet.content(ridx, cidx) {
-                                // println!("input: {}", &input);
-                                let (_, signal) = parser::signal_def(&input).unwrap();
-                                println!("input [{}:{}]{}", signal.0 .0, signal.0 .1, signal.1);
-                                module.new_input(signal.1.into(), signal.0 .0 + 1);
+                        for (input, note, cell) in directive_cells(sheet, ridx, col_iter)? {
+                            let (name, width) = declaration(&module, &input, &cell)?;
+                            verbose!(1, "input [{}:0]{}", width - 1, name);
+                            module
+                                .new_input(name.clone(), width)
+                                .map_err(in_cell(&cell))?;
+                            if let Some(note) = note {
+                                module.document(&name, &note);
                             }
                         }
                     }
 
                     "#output" => {
-                        for cidx in col_iter {
-                            if let Some((output, _)) = sheet.content(ridx, cidx) {
-                                println!("output: {}", &output);
+                        for (output, note, cell) in directive_cells(sheet, ridx, col_iter)? {
+                            let (name, width) = declaration(&module, &output, &cell)?;
+                            verbose!(1, "output [{}:0]{}", width - 1, name);
+                            module
+                                .new_output(name.clone(), width)
+                                .map_err(in_cell(&cell))?;
+                            if let Some(note) = note {
+                                module.document(&name, &note);
                             }
                         }
                     }
@@ -42,35 +165,370 @@ fn create_model(sheet: &Sheet) {
 
                     "#end" => match section {
                         Section::Match(begin) => {
-                            MatchTable::parse(&mut module, sheet, begin, ridx);
+                            MatchTable::parse(&mut module, sheet, begin, ridx, include_disabled)?;
+                            section = Section::None;
                         }
                         Section::None => {
-                            unreachable!()
+                            return Err(verilog_model::Error::UnmatchedEnd {
+                                cell: sheet.location(ridx, cidx),
+                            })
                         }
                     },
 
                     "#wire" => {
-                        for cidx in col_iter {
-                            if let Some((input, _)) = sheet.content(ridx, cidx) {
-                                let (_, signal) = parser::signal_def(&input).unwrap();
-                                println!("wire [{}:{}]{}", signal.0 .0, signal.0 .1, signal.1);
-                                module.new_signal(signal.1.into(), signal.0 .0 + 1);
+                        for (input, note, cell) in directive_cells(sheet, ridx, col_iter)? {
+                            let (name, width) = declaration(&module, &input, &cell)?;
+                            verbose!(1, "wire [{}:0]{}", width - 1, name);
+                            module
+                                .new_signal(name.clone(), width)
+                                .map_err(in_cell(&cell))?;
+                            if let Some(note) = note {
+                                module.document(&name, &note);
                             }
                         }
                     }
 
+                    "#param" => {
+                        for (param, _, cell) in directive_cells(sheet, ridx, col_iter)? {
+                            let (name, expr) = match parser::param_def(&param) {
+                                Ok((_, def)) => def,
+                                Err(_) => {
+                                    return Err(verilog_model::Error::BadDeclaration {
+                                        cell,
+                                        text: param.to_string(),
+                                    })
+                                }
+                            };
+                            let value = expr.eval(&module).map_err(in_cell(&cell))?;
+                            verbose!(1, "param {} = {}", name, value);
+                            module
+                                .new_param(name.into(), value)
+                                .map_err(in_cell(&cell))?;
+                        }
+                    }
+
+                    "#assign" => {
+                        assign_row(&mut module, sheet, ridx)?;
+                    }
+
                     _ => {}
                 },
                 _ => {}
             }
         }
     }
+    module.check_outputs()?;
+    return Ok(module);
+}
+
+/// line of the report sheet of an annotated workbook
+enum ReportRow {
+    Signal {
+        sheet: String,
+        signal: String,
+        width: verilog_model::SignalWidth,
+        kind: &'static str,
+        note: String,
+    },
+    Failed {
+        sheet: String,
+        error: String,
+    },
+}
+
+/// ports first, in declaration order, then the other signals by name
+fn signal_report(sheet: &str, module: &verilog_model::Module) -> Vec<ReportRow> {
+    let signals = module.get_signals();
+    let mut wires: Vec<_> = signals
+        .keys()
+        .filter(|key| !module.get_inputs().contains(key) && !module.get_outputs().contains(key))
+        .collect();
+    wires.sort_by_key(|key| key.as_str());
+    let inputs = module.get_inputs().iter().map(|key| (key, "input"));
+    let outputs = module.get_outputs().iter().map(|key| (key, "output"));
+    return inputs
+        .chain(outputs)
+        .chain(wires.into_iter().map(|key| (key, "wire")))
+        .map(|(key, kind)| ReportRow::Signal {
+            sheet: sheet.into(),
+            signal: key.as_str().into(),
+            width: signals[key].length,
+            kind,
+            note: signals[key].doc.join("; "),
+        })
+        .collect();
+}
+
+/// copy of the workbook with a report sheet, failed sheets are highlighted
+fn write_annotated<R: Read + Seek>(
+    mut writer: XlsxWriter<R>,
+    report: &[ReportRow],
+    path: &Path,
+) -> Result<(), ExcelError> {
+    let mut name = String::from("Report");
+    let mut n = 1;
+    while writer.add_sheet(&name).is_err() {
+        n += 1;
+        name = format!("Report {}", n);
+    }
+    let text = |text: &str| CellValue::Text(Rc::new(text.into()));
+    for (col, title) in ["Sheet", "Signal", "Width", "Kind", "Note"]
+        .iter()
+        .enumerate()
+    {
+        writer.set_value(&name, &(0, col as u16).into(), text(title))?;
+    }
+    for (idx, line) in report.iter().enumerate() {
+        let row = idx as u32 + 1;
+        match line {
+            ReportRow::Signal {
+                sheet,
+                signal,
+                width,
+                kind,
+                note,
+            } => {
+                writer.set_value(&name, &(row, 0).into(), text(sheet))?;
+                writer.set_value(&name, &(row, 1).into(), text(signal))?;
+                writer.set_value(&name, &(row, 2).into(), CellValue::Number(*width as f64))?;
+                writer.set_value(&name, &(row, 3).into(), text(kind))?;
+                if !note.is_empty() {
+                    writer.set_value(&name, &(row, 4).into(), text(note))?;
+                }
+            }
+            ReportRow::Failed { sheet, error } => {
+                writer.set_value(&name, &(row, 0).into(), text(sheet))?;
+                writer.set_value(&name, &(row, 3).into(), text("error"))?;
+                writer.set_value(&name, &(row, 4).into(), text(error))?;
+                for col in 0..5 {
+                    writer.set_fill(&name, &(row, col).into(), excel::HIGHLIGHT_RED)?;
+                }
+            }
+        }
+    }
+    return writer.save_as(path);
 }
 
 fn main() {
-    let mut doc = excel::Excel::open("rv32_decode.xlsx");
-    for s in doc.sheets() {
-        let s = doc.sheet(&s);
-        create_model(&s);
+    let opts = cli::parse();
+    utils::set_verbosity(opts.verbose);
+
+    let input = opts.input.display();
+    let mut doc = match excel::Workbook::open(&opts.input) {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("{}: {}", input, e);
+            process::exit(1);
+        }
+    };
+    let names = doc.sheets();
+    let sheets: Vec<String> = if opts.sheets.is_empty() {
+        names
+    } else {
+        let mut sheets = Vec::new();
+        for sheet in &opts.sheets {
+            let name = names.iter().find(|n| *n == sheet).or_else(|| {
+                names
+                    .iter()
+                    .find(|n| n.to_lowercase() == sheet.to_lowercase())
+            });
+            match name {
+                Some(name) => sheets.push(name.clone()),
+                None => {
+                    eprintln!("{}: no sheet named `{}`", input, sheet);
+                    process::exit(1);
+                }
+            }
+        }
+        sheets
+    };
+
+    let output_dir = opts.output.is_dir();
+    if !output_dir && sheets.len() != 1 {
+        eprintln!(
+            "{}: not a directory, select a single sheet to write a file",
+            opts.output.display()
+        );
+        process::exit(1);
+    }
+
+    let mut failed = false;
+    let mut report = Vec::new();
+    for s in sheets {
+        let sheet = match doc.sheet(&s) {
+            Ok(sheet) => sheet,
+            Err(e) => {
+                eprintln!("{}: {}: {}", input, s, e);
+                report.push(ReportRow::Failed {
+                    sheet: s.clone(),
+                    error: e.to_string(),
+                });
+                failed = true;
+                continue;
+            }
+        };
+        for (pos, formula, e) in &sheet.unevaluated {
+            eprintln!(
+                "{}: {}!{}: formula `{}` has no cached value and can not be evaluated: {}",
+                input, s, pos, formula, e
+            );
+        }
+        for (name, e) in &sheet.unresolved {
+            eprintln!("{}: {}: name `{}` is left out: {}", input, s, name, e);
+        }
+        let module = match create_model(&sheet, opts.include_disabled) {
+            Ok(module) => module,
+            Err(e) => {
+                eprintln!("{}: {}: {}", input, s, e);
+                report.push(ReportRow::Failed {
+                    sheet: s.clone(),
+                    error: e.to_string(),
+                });
+                failed = true;
+                continue;
+            }
+        };
+        report.extend(signal_report(&s, &module));
+        let path = if output_dir {
+            opts.output
+                .join(format!("{}.{}", s, opts.target.extension()))
+        } else {
+            opts.output.clone()
+        };
+        let name = backend::module_name(&s);
+        let verilog = VerilogPrinter::new(&module, &name, opts.target);
+        if let Err(e) = fs::write(&path, format!("{}", verilog)) {
+            eprintln!("{}: {}", path.display(), e);
+            failed = true;
+            continue;
+        }
+        verbose!(1, "{} -> {}", s, path.display());
+    }
+
+    if let Some(path) = &opts.annotate {
+        // only an xlsx workbook can be copied, others get a new workbook with the report
+        let written = match doc.book {
+            excel::Book::Excel(excel) => write_annotated(XlsxWriter::new(excel), &report, path),
+            _ => XlsxWriter::empty().and_then(|writer| write_annotated(writer, &report, path)),
+        };
+        match written {
+            Ok(()) => verbose!(1, "report -> {}", path.display()),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use excel::MemorySheet;
+
+    #[test]
+    fn create_model_errors() {
+        let sheet = MemorySheet::new(&[&["#input", "[7:0]op"], &["#end"]]);
+        let error = create_model(&sheet, false).err().unwrap();
+        assert_eq!("A2: `#end` without `#match`", error.to_string());
+
+        let sheet = MemorySheet::new(&[
+            &["#input", "[7:0]op"],
+            &["#match", "op"],
+            &["[7:0]", "#primary(p)"],
+            &["8'h1", "a", "b"],
+            &["#end"],
+        ]);
+        let error = create_model(&sheet, false).err().unwrap();
+        assert_eq!("C4: bad match cell `b`", error.to_string());
+
+        let sheet = MemorySheet::new(&[&["#output", "[7:0]op"]]);
+        let error = create_model(&sheet, false).err().unwrap();
+        assert_eq!("output `op` is not driven", error.to_string());
+    }
+
+    #[test]
+    fn create_model_sample() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("rv32_decode.xlsx");
+        let mut doc = excel::Workbook::open(&path).unwrap();
+        let sheet = doc.sheet("Sheet1").unwrap();
+        let module = create_model(&sheet, false).unwrap();
+        let outputs: Vec<&str> = module.get_outputs().iter().map(|k| k.as_str()).collect();
+        assert_eq!(vec!["imm"], outputs);
+        assert!(module.get_signals().contains_key("inst_lui"));
+
+        // slots mapped by the same rows share a select
+        let verilog = VerilogPrinter::new(&module, "m", backend::Dialect::Verilog).to_string();
+        let mut selects: Vec<&str> = verilog
+            .lines()
+            .filter(|l| l.starts_with("assign imm_sel_"))
+            .map(|l| l.split_once(" = ").unwrap().1)
+            .collect();
+        let count = selects.len();
+        selects.sort();
+        selects.dedup();
+        assert_eq!(count, selects.len(), "{}", verilog);
+    }
+
+    #[test]
+    fn create_model_assign() {
+        // a new `[h:l]` target and an existing one
+        let sheet = MemorySheet::new(&[
+            &["#input", "[7:0]op"],
+            &["#output", "[3:0]hi"],
+            &["#assign", "[3:0]lo", "op[3:0]"],
+            &["#assign", "hi", "op[7:4] ^ lo"],
+        ]);
+        let module = create_model(&sheet, false).unwrap();
+        let verilog = VerilogPrinter::new(&module, "m", backend::Dialect::Verilog).to_string();
+        assert!(verilog.contains("wire [3:0] lo;"), "{}", verilog);
+        assert!(verilog.contains("assign lo = op[3:0];"), "{}", verilog);
+        assert!(verilog.contains("assign hi = op[7:4] ^ lo;"), "{}", verilog);
+
+        let sheet =
+            MemorySheet::new(&[&["#input", "[7:0]op"], &["#assign", "[3:0]lo", "op[3:0] +"]]);
+        let error = create_model(&sheet, false).err().unwrap();
+        assert_eq!("C2: bad expression `op[3:0] +`", error.to_string());
+    }
+
+    #[test]
+    fn create_model_declarations() {
+        let sheet = MemorySheet::new(&[
+            &["#param", "XLEN = 8", "OP_IMM = 7'h13"],
+            &["#input", "[XLEN-1:0]op", "[6:0]funct"],
+            &["#wire", "[XLEN/2-1:0]half"],
+        ]);
+        let module = create_model(&sheet, false).unwrap();
+        let params: Vec<String> = module
+            .get_params()
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        assert_eq!(vec!["XLEN = 8", "OP_IMM = 7'b0010011"], params);
+        assert_eq!(8, module.get_signals()["op"].length);
+        assert_eq!(4, module.get_signals()["half"].length);
+
+        let tables: [(&[&[&str]], &str); 6] = [
+            (&[&["#input", "[7:0]op", "d"]], "C1: bad declaration `d`"),
+            (
+                &[&["#input", "[65535:0]x"]],
+                "B1: bad declaration `[65535:0]x`",
+            ),
+            (&[&["#wire", "[7:4]x"]], "B1: bad declaration `[7:4]x`"),
+            (&[&["#input", "[W-1:0]d"]], "B1: undefined name `W`"),
+            (&[&["#param", "X 1"]], "B1: bad declaration `X 1`"),
+            (
+                &[&["#param", "X = 1"], &["#wire", "[3:0]X"]],
+                "B2: `X` is defined more than once",
+            ),
+        ];
+        for (rows, error) in tables {
+            let sheet = MemorySheet::new(rows);
+            let found = create_model(&sheet, false).err().unwrap();
+            assert_eq!(error, found.to_string(), "{:?}", rows);
+        }
     }
 }
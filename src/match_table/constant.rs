@@ -1,8 +1,8 @@
 use super::MatchTableColumn;
 use std::collections::HashMap;
 
-// number, case index
-pub struct SegConstantCase(pub HashMap<u128, Vec<usize>>);
+// (number, care mask), case index
+pub struct SegConstantCase(pub HashMap<(u128, u128), Vec<usize>>);
 
 pub struct SegsConstantCase {
     // segment index, case
@@ -29,7 +29,8 @@ impl<'a> std::fmt::Display for ConstantCasePrinter<'a> {
                 MatchTableColumn::Segment(signal) => signal,
                 _ => unreachable!(),
             };
-            let mut constants: Vec<(&u128, &Vec<usize>)> = self.cc.segs_set[k].0.iter().collect();
+            let mut constants: Vec<(&(u128, u128), &Vec<usize>)> =
+                self.cc.segs_set[k].0.iter().collect();
             constants.sort_by(|a, b| a.0.cmp(b.0));
             writeln!(f, "{}: {:?}", signal.as_str(), constants)?;
         }
@@ -44,13 +45,16 @@ impl SegsConstantCase {
         };
     }
 
-    pub fn insert(&mut self, case_idx: usize, seg_idx: usize, constant: u128) {
+    /// bits of `mask` that are clear are don't care
+    pub fn insert(&mut self, case_idx: usize, seg_idx: usize, constant: u128, mask: u128) {
         if !self.segs_set.contains_key(&seg_idx) {
             self.segs_set
                 .insert(seg_idx, SegConstantCase(HashMap::new()));
         }
 
         let case = self.segs_set.get_mut(&seg_idx).unwrap();
+        // don't care bits of the value do not make another case
+        let constant = (constant & mask, mask);
         match case.0.get_mut(&constant) {
             Some(v) => {
                 v.push(case_idx);
@@ -1,16 +1,31 @@
 mod constant;
 mod signal_map;
 
-use super::excel::Sheet;
-use super::parser::{self, match_cmd, match_content};
-use super::utils::binary_format;
+use super::excel::SheetSource;
+use super::parser::{self, match_cmd, match_content, Literal, WireRef};
+use super::utils::{pattern_format, width_mask};
 use super::verilog_model::{
-    LogicElem, LogicTree, Module, Signal, SignalKey, SignalSource, Wire, WireIndex,
+    Error, LogicElem, LogicTree, Module, Signal, SignalKey, SignalSource, Wire,
 };
+use crate::verbose;
 use constant::*;
 use signal_map::*;
 use std::collections::HashMap;
 use std::ops::{Range, Shr};
+use std::rc::Rc;
+
+/// values of a `=Name` cell, `None` for any other cell
+pub fn name_values(sheet: &dyn SheetSource, text: &str) -> Result<Option<Vec<Rc<String>>>, Error> {
+    return match text.strip_prefix('=') {
+        Some(name) => match sheet.defined_name(name.trim()) {
+            Some(values) => Ok(Some(values)),
+            None => Err(Error::UndefinedName {
+                name: name.trim().into(),
+            }),
+        },
+        None => Ok(None),
+    };
+}
 
 pub enum Section {
     None,
@@ -19,8 +34,10 @@ pub enum Section {
 
 #[derive(Debug)]
 pub enum MatchTableContent<'a> {
-    Constant(u16, u128),
+    Constant(Literal),
     Signal(&'a str, Option<Vec<(u16, u16)>>),
+    /// a mapping cell of several signals or an extended signal
+    Compose(WireRef),
 }
 
 #[derive(Debug)]
@@ -36,6 +53,8 @@ pub struct MatchTable {
     header: Vec<MatchTableColumn>,
     signal_case: SignalMapCase,
     constant_case: SegsConstantCase,
+    // (cidx, (number, care mask)), the param a constant is named by
+    params: HashMap<(usize, (u128, u128)), String>,
     // (cidx, <signal-key, ridx>)
     flags: Vec<HashMap<String, Vec<usize>>>,
     // <ridx, signal-key>
@@ -44,29 +63,48 @@ pub struct MatchTable {
 
 #[allow(dead_code)]
 impl MatchTable {
-    fn gen_constant_condition(&mut self, model: &mut Module) -> HashMap<usize, Vec<SignalKey>> {
+    fn gen_constant_condition(
+        &mut self,
+        model: &mut Module,
+    ) -> Result<HashMap<usize, Vec<SignalKey>>, Error> {
         let mut constant_condition_map: HashMap<usize, Vec<SignalKey>> = HashMap::new();
         let segs_set = &self.constant_case.segs_set;
-        for (cidx, seg_case) in segs_set {
+        let mut cidxs: Vec<&usize> = segs_set.keys().collect();
+        cidxs.sort();
+        for cidx in cidxs {
+            let seg_case = &segs_set[cidx];
             let column = &self.header[*cidx];
             if let MatchTableColumn::Segment(signal_key) = column {
                 let signal = model.get_signals()[signal_key].clone();
                 let signal_len = signal.length;
                 let signal_unit: LogicTree = LogicElem::Unit(signal.into()).into();
-                let mut constants: Vec<u128> = seg_case.0.iter().map(|a| *a.0).collect();
+                let mut constants: Vec<(u128, u128)> = seg_case.0.iter().map(|a| *a.0).collect();
                 constants.sort();
                 for constant in constants {
-                    let constant_unit: LogicTree =
-                        LogicElem::Unit(Wire::bit(signal_len, constant)).into();
-                    let constant_consdition_statement = signal_unit.clone().equal(constant_unit);
+                    let (value, mask) = constant;
+                    // a segment of don't care bits matches every row
+                    if mask == 0 {
+                        continue;
+                    }
+                    let constant_unit: LogicTree = match self.params.get(&(*cidx, constant)) {
+                        Some(name) => LogicElem::Param(name.clone()).into(),
+                        None => LogicElem::Unit(Wire::bit(signal_len, value)).into(),
+                    };
+                    let constant_consdition_statement = if mask == width_mask(signal_len) {
+                        signal_unit.clone().equal(constant_unit)
+                    } else {
+                        let mask_unit: LogicTree =
+                            LogicElem::Unit(Wire::bit(signal_len, mask)).into();
+                        (signal_unit.clone() & mask_unit).equal(constant_unit)
+                    };
                     let constant_consdition_key: SignalKey = format!(
                         "{}_is_{}",
                         signal_key.as_str(),
-                        binary_format(signal_len, constant)
+                        pattern_format(signal_len, value, mask)
                     )
                     .into();
                     let ridxs = &seg_case.0[&constant];
-                    println!("{} {:?}", constant_consdition_key.as_str(), ridxs);
+                    verbose!(2, "{} {:?}", constant_consdition_key.as_str(), ridxs);
                     ridxs
                         .iter()
                         .for_each(|ridx| match constant_condition_map.get_mut(ridx) {
@@ -81,92 +119,423 @@ impl MatchTable {
                         1,
                         SignalSource::Logic(constant_consdition_statement),
                     );
-                    model.add_signal(constant_consdition_signal);
+                    model.add_signal(constant_consdition_signal)?;
                 }
             } else {
                 unreachable!("{} -> {:?}", *cidx, column);
             }
         }
-        return constant_condition_map;
+        return Ok(constant_condition_map);
+    }
+
+    /// a row is matched by the AND of all its segment conditions
+    fn row_condition(
+        &self,
+        model: &Module,
+        constant_condition_map: &HashMap<usize, Vec<SignalKey>>,
+        ridx: usize,
+    ) -> LogicTree {
+        let conditions = match constant_condition_map.get(&ridx) {
+            Some(conditions) => conditions.as_slice(),
+            None => &[],
+        };
+        let mut conditions = conditions.iter();
+        return match conditions.next() {
+            Some(consition_signal_key) => {
+                let signals = model.get_signals();
+                let statement: LogicTree =
+                    LogicElem::Unit(signals[consition_signal_key].clone().into()).into();
+                conditions.fold(statement, |s, cond| {
+                    s.logic_and(LogicElem::Unit(signals[cond].clone().into()).into())
+                })
+            }
+            None => LogicElem::Unit(Wire::bit(1, 1)).into(),
+        };
+    }
+
+    /// OR of the rows, primary signals are reused as row conditions
+    fn rows_selected(
+        &self,
+        model: &Module,
+        constant_condition_map: &HashMap<usize, Vec<SignalKey>>,
+        ridxs: &[usize],
+    ) -> LogicTree {
+        return ridxs
+            .iter()
+            .map(|ridx| match self.primary.get(ridx) {
+                Some(primary_signal) => {
+                    let primary = &model.get_signals()[primary_signal.as_str()];
+                    LogicElem::Unit(primary.clone().into()).into()
+                }
+                None => self.row_condition(model, constant_condition_map, *ridx),
+            })
+            .reduce(|s, cond: LogicTree| s.logic_or(cond))
+            .unwrap_or_else(|| LogicElem::Unit(Wire::bit(1, 0)).into());
     }
 
     fn gen_primary(
         &self,
         model: &mut Module,
-        constant_condition_map: HashMap<usize, Vec<SignalKey>>,
-    ) {
+        constant_condition_map: &HashMap<usize, Vec<SignalKey>>,
+    ) -> Result<(), Error> {
         let mut primary_ridx: Vec<usize> = self.primary.iter().map(|p| *p.0).collect();
         primary_ridx.sort();
         for ridx in primary_ridx {
             let primary_signal = &self.primary[&ridx];
             let primary_signal_key: SignalKey = primary_signal.to_owned().into();
-            let conditions = constant_condition_map.get(&ridx).unwrap();
-            println!("{} {} -> {:?}", ridx, primary_signal, conditions);
-            let mut conditions = conditions.iter().rev();
-            let primary_signal_statement: LogicTree = match conditions.next() {
-                Some(consition_signal_key) => {
-                    let signals = model.get_signals();
-                    let statement: LogicTree =
-                        LogicElem::Unit(signals[consition_signal_key].clone().into()).into();
-                    conditions.fold(statement, |s, cond| {
-                        s.logic_and(
-                            LogicElem::Unit(signals[consition_signal_key].clone().into()).into(),
-                        )
-                    })
-                }
-                None => LogicElem::Unit(Wire::bit(1, 1)).into(),
-            };
+            verbose!(
+                2,
+                "{} {} -> {:?}",
+                ridx,
+                primary_signal,
+                constant_condition_map.get(&ridx)
+            );
+            let primary_signal_statement = self.row_condition(model, constant_condition_map, ridx);
+            model.add_signal(Signal::new(
+                primary_signal_key,
+                1,
+                SignalSource::Logic(primary_signal_statement),
+            ))?;
         }
+        return Ok(());
     }
 
-    fn gen_flags(&self, model: &Module) {
+    /// each flag is the OR of the rows carrying it
+    fn gen_flags(
+        &self,
+        model: &mut Module,
+        constant_condition_map: &HashMap<usize, Vec<SignalKey>>,
+    ) -> Result<(), Error> {
         for flag in &self.flags {
-            for (signal, ridxs) in flag {
+            let mut signals: Vec<&String> = flag.keys().collect();
+            signals.sort();
+            for signal in signals {
+                let ridxs = &flag[signal];
                 let signal_key: SignalKey = signal.to_owned().into();
-                println!("{} -> {:?}", signal_key.as_str(), ridxs);
+                verbose!(2, "{} -> {:?}", signal_key.as_str(), ridxs);
+                let flag_signal_statement =
+                    self.rows_selected(model, constant_condition_map, ridxs);
+                model.add_signal(Signal::new(
+                    signal_key,
+                    1,
+                    SignalSource::Logic(flag_signal_statement),
+                ))?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// source bits of segment columns, from MSB to LSB
+    fn segs_bits(&self, model: &Module, segs: &Range<usize>) -> Result<Vec<Wire>, Error> {
+        let mut bits = Vec::new();
+        for cidx in segs.clone() {
+            match &self.header[cidx] {
+                MatchTableColumn::Segment(signal_key) => {
+                    let signal = &model.get_signals()[signal_key];
+                    for idx in (0..signal.length).rev() {
+                        bits.push(signal.single(idx)?);
+                    }
+                }
+                column => unreachable!("{} -> {:?}", cidx, column),
+            }
+        }
+        return Ok(bits);
+    }
+
+    /// every mapped signal is driven by a per-bit AND-OR mux,
+    /// each slot is selected by the rows that map it
+    fn gen_signal_map(
+        &self,
+        model: &mut Module,
+        constant_condition_map: &HashMap<usize, Vec<SignalKey>>,
+    ) -> Result<(), Error> {
+        let mut signal_keys: Vec<&SignalKey> = self.signal_case.0.keys().collect();
+        signal_keys.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for signal_key in signal_keys {
+            let map_slots = &self.signal_case.0[signal_key];
+            let signal_len = model.get_signals()[signal_key].length;
+            // target bit index, [(slot select, source bit)]
+            let mut bit_sources: Vec<Vec<(LogicTree, Wire)>> =
+                vec![Vec::new(); signal_len as usize];
+            // slots mapped by the same rows share one select
+            let mut selects: HashMap<Vec<usize>, LogicTree> = HashMap::new();
+
+            for (slot_idx, slot) in map_slots.slots.iter().enumerate() {
+                let mut ridxs: Vec<usize> = map_slots
+                    .slot_case
+                    .iter()
+                    .filter(|(_, slots)| slots.contains(&slot_idx))
+                    .map(|(ridx, _)| *ridx)
+                    .collect();
+                ridxs.sort();
+                verbose!(
+                    2,
+                    "{}[{:?}] <- {:?} {:?}",
+                    signal_key.as_str(),
+                    slot.ranges,
+                    slot.segs,
+                    ridxs
+                );
+
+                let select = match selects.get(&ridxs) {
+                    Some(select) => select.clone(),
+                    None => {
+                        let select_key: SignalKey =
+                            format!("{}_sel_{}", signal_key.as_str(), selects.len()).into();
+                        let select_statement =
+                            self.rows_selected(model, constant_condition_map, &ridxs);
+                        let select_signal =
+                            Signal::new(select_key, 1, SignalSource::Logic(select_statement));
+                        let select: LogicTree =
+                            LogicElem::Unit(select_signal.clone().into()).into();
+                        model.add_signal(select_signal)?;
+                        selects.insert(ridxs, select.clone());
+                        select
+                    }
+                };
+
+                let mut source_bits =
+                    self.segs_bits(model, &slot.segs)?[slot.bits.clone()].to_vec();
+                let mut target_bits: Vec<u16> = slot
+                    .ranges
+                    .iter()
+                    .flat_map(|&(h, l)| (l..h + 1).rev())
+                    .collect();
+                if source_bits.len() != target_bits.len() {
+                    return Err(Error::SignalWidthMismatch {
+                        signal: signal_key.clone(),
+                        expect: source_bits.len() as u16,
+                        found: target_bits.len() as u16,
+                    });
+                }
+                // bits above the ranges take the MSB of a sign extension, zeros are left unmapped
+                if let Some((width, signed)) = slot.extend {
+                    let top = target_bits.iter().max().map(|h| h + 1).unwrap_or(0);
+                    if width < top {
+                        return Err(Error::SignalWidthMismatch {
+                            signal: signal_key.clone(),
+                            expect: width,
+                            found: top,
+                        });
+                    }
+                    if signed {
+                        let msb = source_bits[0].clone();
+                        target_bits.splice(0..0, (top..width).rev());
+                        source_bits.splice(0..0, vec![msb; (width - top) as usize]);
+                    } else if width > signal_len {
+                        return Err(Error::SignalIndexOutOfRange {
+                            len: signal_len,
+                            idx: width - 1,
+                        });
+                    }
+                }
+                for (idx, source) in target_bits.into_iter().zip(source_bits) {
+                    if idx >= signal_len {
+                        return Err(Error::SignalIndexOutOfRange {
+                            len: signal_len,
+                            idx,
+                        });
+                    }
+                    bit_sources[idx as usize].push((select.clone(), source));
+                }
+            }
+
+            let bits: Vec<LogicTree> = bit_sources
+                .into_iter()
+                .rev()
+                .map(|sources| {
+                    sources
+                        .into_iter()
+                        .map(|(select, source)| select & LogicElem::Unit(source).into())
+                        .reduce(|s, term| s | term)
+                        .unwrap_or_else(|| LogicElem::Unit(Wire::bit(1, 0)).into())
+                })
+                .collect();
+            model.drive(
+                signal_key,
+                SignalSource::Logic(LogicElem::Combine(bits).into()),
+            )?;
+        }
+        return Ok(());
+    }
+
+    /// parts of a mapping cell, `false` for a cell that names no bits to drive, like a replication
+    fn map_targets(
+        model: &Module,
+        target: &WireRef,
+        targets: &mut Vec<MapTarget>,
+    ) -> Result<bool, Error> {
+        match target {
+            WireRef::Signal(name, ranges) => {
+                let signal = match model.get_signals().get(name.as_str()) {
+                    Some(signal) => signal,
+                    None => {
+                        return Err(Error::UndefinedSignal {
+                            signal: name.clone(),
+                        })
+                    }
+                };
+                let ranges = ranges.clone().unwrap_or(vec![(signal.length - 1, 0)]);
+                if let Some(&(h, _)) = ranges.iter().find(|(h, l)| h < l) {
+                    return Err(Error::SignalIndexOutOfRange {
+                        len: signal.length,
+                        idx: h,
+                    });
+                }
+                targets.push(MapTarget {
+                    signal: Some(signal.key.clone()),
+                    width: ranges.iter().map(|(h, l)| h - l + 1).sum(),
+                    ranges,
+                    extend: None,
+                });
+            }
+            WireRef::Constant(c) => targets.push(MapTarget {
+                signal: None,
+                ranges: Vec::new(),
+                width: c.bits(),
+                extend: None,
+            }),
+            WireRef::Concat(parts) => {
+                for part in parts {
+                    if !MatchTable::map_targets(model, part, targets)? {
+                        return Ok(false);
+                    }
+                }
             }
+            WireRef::Extend {
+                signed,
+                width,
+                wire,
+            } => {
+                // the extended bits all belong to one signal
+                let mut parts = Vec::new();
+                if !MatchTable::map_targets(model, wire, &mut parts)? {
+                    return Ok(false);
+                }
+                let signal = match parts.first() {
+                    Some(MapTarget {
+                        signal: Some(signal),
+                        ..
+                    }) => signal.clone(),
+                    _ => return Ok(false),
+                };
+                if parts
+                    .iter()
+                    .any(|p| p.signal.as_ref() != Some(&signal) || p.extend.is_some())
+                {
+                    return Ok(false);
+                }
+                targets.push(MapTarget {
+                    signal: Some(signal),
+                    ranges: parts.iter().flat_map(|p| p.ranges.clone()).collect(),
+                    width: parts.iter().map(|p| p.width).sum(),
+                    extend: Some((*width, *signed)),
+                });
+            }
+            WireRef::Replicate(..) => return Ok(false),
+        }
+        return Ok(true);
+    }
+
+    /// text of a match cell, a param gives its value, `=NAME` or a bare `NAME` that is not a signal
+    /// gives the value of the name
+    fn named_constant(
+        model: &Module,
+        sheet: &dyn SheetSource,
+        content: Rc<String>,
+    ) -> Result<Rc<String>, Error> {
+        if let Some(value) = model.param(content.trim()) {
+            return Ok(Rc::new(value.to_string()));
         }
+        let (name, values) = match name_values(sheet, &content)? {
+            Some(values) => (content[1..].trim(), values),
+            None if !model.get_signals().contains_key(content.as_str()) => {
+                match sheet.defined_name(&content) {
+                    Some(values) => (content.as_str(), values),
+                    None => return Ok(content),
+                }
+            }
+            None => return Ok(content),
+        };
+        return match values.len() {
+            1 => Ok(values[0].clone()),
+            n => Err(Error::NameNotSingle {
+                name: name.into(),
+                values: n,
+            }),
+        };
     }
 
-    pub fn parse(model: &mut Module, sheet: &Sheet, begin: usize, end: usize) {
+    /// disabled rows of the table are left out unless `include_disabled` is set
+    pub fn parse(
+        model: &mut Module,
+        sheet: &dyn SheetSource,
+        begin: usize,
+        end: usize,
+        include_disabled: bool,
+    ) -> Result<(), Error> {
         let mut row = begin..end;
-        let ridx = row.next().unwrap();
-
-        let target_signal_str = sheet.content(ridx, 1).and_then(|(c, _)| Some(c)).unwrap();
-        let (_, (signal_name, ranges)) =
-            parser::sginal_ref(&target_signal_str).expect(&target_signal_str);
-        let target_signal = model.get_signals().get(signal_name).unwrap().clone();
-
-        let target = match ranges {
-            Some(ranges) => Wire::Multiple {
-                signal: target_signal.key.clone(),
-                idxs: ranges.iter().map(|&(h, l)| WireIndex::new(h, l)).collect(),
-            },
-            None => Wire::Independent {
-                signal: target_signal.key.clone(),
-                idx: (0..target_signal.length).into(),
-            },
+        let ridx = begin;
+        row.next();
+        let bad_cell = |ridx: usize, cidx: usize, text: &str| Error::BadMatchCell {
+            cell: sheet.location(ridx, cidx),
+            text: text.into(),
+        };
+        let in_cell = |ridx: usize, cidx: usize| {
+            move |error: Error| Error::InCell {
+                cell: sheet.location(ridx, cidx),
+                error: Box::new(error),
+            }
+        };
+
+        let target_signal_str = match sheet.content(ridx, 1) {
+            Some((c, _)) => c,
+            None => return Err(bad_cell(ridx, 1, "")),
         };
+        let target = match parser::wire_ref(&target_signal_str) {
+            Ok(("", target)) => target,
+            _ => return Err(bad_cell(ridx, 1, &target_signal_str)),
+        };
+        // segments are named after the matched signal, or after the match of a composition
+        let target_name = match &target {
+            WireRef::Signal(name, _) => Some(name.clone()),
+            _ => None,
+        };
+        let target = target.wire(model).map_err(in_cell(ridx, 1))?;
 
         let match_signal = Signal::new(
             format!("match_{}", target).into(),
             target.len(),
             SignalSource::Wire(target),
         );
-        model.add_signal(match_signal.clone());
+        model.add_signal(match_signal.clone())?;
+        for cidx in 0..2 {
+            if let Some(note) = sheet.note(ridx, cidx) {
+                model.document(&match_signal.key, &note);
+            }
+        }
 
         let mut match_header: Vec<MatchTableColumn> = Vec::new();
         // header index, <ridx, signal-key>
         let mut match_primary: Option<(usize, HashMap<usize, String>)> = None;
         // [(header index, <signal-key, [ridx]>)]
         let mut match_flags: Vec<(usize, HashMap<String, Vec<usize>>)> = Vec::new();
-        let ridx = row.next().unwrap();
-        for cidx in sheet.row(ridx) {
-            let signals = model.get_signals_mut();
+        let ridx = match row.next() {
+            Some(ridx) => ridx,
+            None => {
+                return Err(Error::MissingPrimary {
+                    cell: sheet.location(begin, 0),
+                })
+            }
+        };
+        for cidx in 0..sheet.cols(ridx) {
             match sheet.content(ridx, cidx) {
                 Some((c, None)) => {
                     if c.starts_with("#") {
-                        let (_, colum_cmd) = match_cmd(c.as_str()).unwrap();
+                        let colum_cmd = match match_cmd(c.as_str()) {
+                            Ok((_, colum_cmd)) => colum_cmd,
+                            Err(_) => return Err(bad_cell(ridx, cidx, &c)),
+                        };
                         match &colum_cmd {
                             MatchTableColumn::Flag(_) => {
                                 match_flags.push((cidx, HashMap::new()));
@@ -178,100 +547,208 @@ impl MatchTable {
                         }
                         match_header.push(colum_cmd);
                     } else {
-                        let (_, ((h, l), alias)) = parser::range_alias(&c).unwrap();
-                        let seg_wire = match_signal.range(l..h + 1).unwrap();
+                        let ((h, l), alias) = match parser::range_alias(&c) {
+                            Ok((_, range_alias)) => range_alias,
+                            Err(_) => return Err(bad_cell(ridx, cidx, &c)),
+                        };
+                        let seg_wire = match_signal
+                            .range(l..h.saturating_add(1))
+                            .map_err(in_cell(ridx, cidx))?;
                         let seg_key: SignalKey = alias
-                            .unwrap_or(format!("{}_{}to{}", target_signal.key.as_str(), h, l))
+                            .unwrap_or(format!(
+                                "{}_{}to{}",
+                                target_name.as_deref().unwrap_or(match_signal.key.as_str()),
+                                h,
+                                l
+                            ))
                             .into();
                         let seg_signal = Signal::new(
                             seg_key.clone(),
                             seg_wire.len(),
                             SignalSource::Wire(seg_wire),
                         );
-                        signals.insert(seg_key.clone(), seg_signal.clone());
+                        model.add_signal(seg_signal).map_err(in_cell(ridx, cidx))?;
+                        if let Some(note) = sheet.note(ridx, cidx) {
+                            model.document(&seg_key, &note);
+                        }
                         match_header.push(MatchTableColumn::Segment(seg_key));
                     }
                 }
-                _ => {}
+                // keeps the header in line with the columns
+                _ => match_header.push(MatchTableColumn::None),
             }
         }
 
-        println!(
+        let header_ridx = ridx;
+        verbose!(
+            1,
             "header: {:?}, primary: {:?} flags: {:?}",
-            match_header, match_primary, match_flags
+            match_header,
+            match_primary,
+            match_flags
         );
 
         let mut constant_case = SegsConstantCase::new();
+        let mut params = HashMap::new();
         let mut signal_case = SignalMapCase::new();
+        // notes of a row document its primary signal, notes of a flag cell its flag signal
+        let mut row_notes: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut flag_notes: Vec<(String, String)> = Vec::new();
 
         for ridx in row {
-            let mut row_iter = sheet.row(ridx);
+            if !include_disabled && sheet.disabled(ridx) {
+                verbose!(1, "{}: disabled row skipped", sheet.location(ridx, 0));
+                continue;
+            }
+            let mut row_iter = 0..sheet.cols(ridx);
             while let Some(cidx) = row_iter.next() {
-                // let cidx = *cidx;
+                let note = sheet.note(ridx, cidx);
+                if let Some(note) = &note {
+                    if !matches!(match_header.get(cidx), Some(MatchTableColumn::Flag(_))) {
+                        row_notes
+                            .entry(ridx - begin)
+                            .or_default()
+                            .push(note.clone());
+                    }
+                }
                 match sheet.content(ridx, cidx) {
                     Some((content, merged)) => {
-                        let (_, content) = match_content(content.as_str()).expect(content.as_str());
+                        // a cell past the last column of the header belongs to no column
+                        if cidx >= match_header.len() {
+                            return Err(bad_cell(ridx, cidx, &content));
+                        }
+                        let param = match match_header.get(cidx) {
+                            Some(MatchTableColumn::Segment(_)) => model
+                                .param(content.trim())
+                                .map(|_| content.trim().to_string()),
+                            _ => None,
+                        };
+                        // names are constants of segments, primary and flag cells name signals
+                        let content = match match_header.get(cidx) {
+                            Some(MatchTableColumn::Segment(_)) => {
+                                MatchTable::named_constant(model, sheet, content)
+                                    .map_err(in_cell(ridx, cidx))?
+                            }
+                            _ => content,
+                        };
+                        let text = content.clone();
+                        let content = match match_content(content.as_str()) {
+                            Ok((_, content)) => content,
+                            Err(_) => {
+                                return Err(bad_cell(ridx, cidx, &text));
+                            }
+                        };
+                        // constants and signals merged over columns take segment columns only
+                        let segment = |idx: usize| match match_header.get(idx) {
+                            Some(MatchTableColumn::Segment(signal)) => Ok(signal),
+                            _ => Err(bad_cell(ridx, cidx, &text)),
+                        };
+                        let seg_len = |idx: usize| {
+                            segment(idx).map(|signal| model.get_signals()[signal].length)
+                        };
                         match content {
-                            MatchTableContent::Constant(constant_width, constant) => {
-                                // println!("@ [{}:{}]", ridx, cidx,);
-                                let constant = match merged.and_then(|merged| {
-                                    if merged.size.col == 1 {
-                                        None
-                                    } else {
-                                        Some(merged)
-                                    }
-                                }) {
+                            MatchTableContent::Constant(literal) => {
+                                // a constant merged over segments is split over them
+                                let cols = match merged.filter(|merged| merged.size.col != 1) {
                                     Some(merged) => {
-                                        let merged_col =
-                                            (cidx - merged.offset.col as usize)..cidx + 1;
-                                        let offset =
-                                            merged_col.fold(0_u16, |sum, idx| match &match_header
-                                                [idx]
-                                            {
-                                                MatchTableColumn::Segment(signal) => {
-                                                    sum + model.get_signals()[signal].length
-                                                }
-                                                _ => {
-                                                    unreachable!();
-                                                }
-                                            });
-                                        if merged.offset.col == merged.size.col {
-                                            assert_eq!(offset, constant_width);
-                                        }
-
-                                        constant.shr((constant_width - offset) as usize) as u128
+                                        let first = cidx - merged.offset.col as usize;
+                                        first..first + merged.size.col as usize
                                     }
+                                    None => cidx..cidx + 1,
+                                };
+                                let width = cols.clone().map(seg_len).sum::<Result<u16, _>>()?;
+                                let (constant, mask) = match literal.fit(width) {
+                                    Some(fit) => fit,
                                     None => {
-                                        match &match_header[cidx] {
-                                            MatchTableColumn::Segment(signal) => {
-                                                assert_eq!(
-                                                    model.get_signals()[signal].length,
-                                                    constant_width
-                                                );
-                                            }
-                                            _ => unreachable!(),
-                                        }
-                                        constant
+                                        return Err(Error::SignalWidthMismatch {
+                                            signal: segment(cidx)?.clone(),
+                                            expect: width,
+                                            found: literal.bits(),
+                                        })
                                     }
                                 };
-                                constant_case.insert(ridx - begin, cidx, constant);
+                                // bits of the columns on the right are shifted out,
+                                // bits of the columns on the left masked out
+                                let shift =
+                                    (cidx + 1..cols.end).map(seg_len).sum::<Result<u16, _>>()?;
+                                let seg_mask = width_mask(seg_len(cidx)?);
+                                constant_case.insert(
+                                    ridx - begin,
+                                    cidx,
+                                    constant.shr(shift) & seg_mask,
+                                    mask.shr(shift) & seg_mask,
+                                );
+                                // a param of the width of its segment is compared by its name
+                                if let Some(name) = param.filter(|_| {
+                                    cols.len() == 1
+                                        && literal.width == Some(width)
+                                        && literal.known()
+                                }) {
+                                    params.entry((cidx, (constant, mask))).or_insert(name);
+                                }
+                            }
+                            MatchTableContent::Compose(target) => {
+                                let mut targets = Vec::new();
+                                let is_target = match &match_header[cidx] {
+                                    MatchTableColumn::Segment(_) => {
+                                        MatchTable::map_targets(model, &target, &mut targets)
+                                            .map_err(in_cell(ridx, cidx))?
+                                    }
+                                    _ => false,
+                                };
+                                if !is_target {
+                                    return Err(bad_cell(ridx, cidx, &target.to_string()));
+                                }
+                                let merged_cols =
+                                    merged.and_then(|m| Some(m.size.col as usize)).unwrap_or(1);
+                                for _ in 0..merged_cols - 1 {
+                                    row_iter.next();
+                                }
+
+                                // bits of the segments are given out from the MSB
+                                let segs = cidx..cidx + merged_cols;
+                                let width = segs.clone().map(seg_len).sum::<Result<u16, _>>()?;
+                                let found: u16 = targets.iter().map(|t| t.width).sum();
+                                if found != width {
+                                    return Err(Error::SignalWidthMismatch {
+                                        signal: segment(cidx)?.clone(),
+                                        expect: width,
+                                        found,
+                                    });
+                                }
+                                let mut offset = 0;
+                                for target in targets {
+                                    let bits = offset..offset + target.width as usize;
+                                    offset = bits.end;
+                                    if let Some(signal) = &target.signal {
+                                        signal_case.insert(
+                                            signal,
+                                            ridx - begin,
+                                            SignalMapSlot {
+                                                ranges: target.ranges,
+                                                segs: segs.clone(),
+                                                bits,
+                                                extend: target.extend,
+                                            },
+                                        );
+                                    }
+                                }
                             }
                             MatchTableContent::Signal(signal, ranges) => {
                                 match &match_header[cidx] {
                                     MatchTableColumn::Segment(_) => {
-                                        let ranges = ranges.unwrap_or_else(|| {
-                                            vec![(
-                                                model
-                                                    .get_signals()
-                                                    .get(signal)
-                                                    .expect(signal)
-                                                    .length
-                                                    - 1,
-                                                0,
-                                            )]
-                                        });
-                                        let signal =
-                                            model.get_signals().get(signal).expect(signal).clone();
+                                        let signal = match model.get_signals().get(signal) {
+                                            Some(signal) => signal.clone(),
+                                            None => {
+                                                return Err(in_cell(ridx, cidx)(
+                                                    Error::UndefinedSignal {
+                                                        signal: signal.into(),
+                                                    },
+                                                ))
+                                            }
+                                        };
+                                        let ranges =
+                                            ranges.unwrap_or_else(|| vec![(signal.length - 1, 0)]);
                                         let merged_cols = merged
                                             .and_then(|m| Some(m.size.col as usize))
                                             .unwrap_or(1);
@@ -279,11 +756,18 @@ impl MatchTable {
                                             row_iter.next();
                                         }
 
+                                        let segs = cidx..cidx + merged_cols;
+                                        let width =
+                                            segs.clone().map(seg_len).sum::<Result<u16, _>>()?;
                                         signal_case.insert(
                                             &signal.key,
                                             ridx - begin,
-                                            ranges,
-                                            cidx..cidx + merged_cols,
+                                            SignalMapSlot {
+                                                ranges,
+                                                segs,
+                                                bits: 0..width as usize,
+                                                extend: None,
+                                            },
                                         );
                                     }
                                     MatchTableColumn::Primary(prefix) => {
@@ -297,7 +781,11 @@ impl MatchTable {
                                         }
                                     }
 
-                                    MatchTableColumn::Flag(_) => {
+                                    MatchTableColumn::Flag(prefix) => {
+                                        if let Some(note) = note {
+                                            flag_notes
+                                                .push((format!("{}_{}", prefix, signal), note));
+                                        }
                                         let flags_idx = match_flags
                                             .iter()
                                             .position(|v| v.0 == cidx)
@@ -335,18 +823,394 @@ impl MatchTable {
                 unreachable!();
             }
         }
-        let primary = match_primary.unwrap().1;
+        let primary = match match_primary {
+            Some((_, primary)) => primary,
+            None => {
+                return Err(Error::MissingPrimary {
+                    cell: sheet.location(header_ridx, 0),
+                })
+            }
+        };
 
         let mut match_table = MatchTable {
             header: match_header,
             signal_case,
             constant_case,
+            params,
             flags,
             primary,
         };
 
-        let condition_map = match_table.gen_constant_condition(model);
-        match_table.gen_primary(model, condition_map);
-        // match_table.gen_flags(model);
+        let condition_map = match_table.gen_constant_condition(model)?;
+        match_table.gen_primary(model, &condition_map)?;
+        match_table.gen_flags(model, &condition_map)?;
+        match_table.gen_signal_map(model, &condition_map)?;
+        for (ridx, notes) in &row_notes {
+            if let Some(primary) = match_table.primary.get(ridx) {
+                for note in notes {
+                    model.document(primary, note);
+                }
+            }
+        }
+        for (flag, note) in &flag_notes {
+            model.document(flag, note);
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Dialect, VerilogPrinter};
+    use crate::excel::MemorySheet;
+
+    /// the verilog of the table of `sheet` over an `op` input of `width` bits,
+    /// `declare` adds the other signals and params of the module
+    fn decode(
+        sheet: &MemorySheet,
+        width: u16,
+        declare: impl FnOnce(&mut Module),
+        include_disabled: bool,
+    ) -> Result<String, Error> {
+        let mut model = Module::new();
+        model.new_input("op".into(), width).unwrap();
+        declare(&mut model);
+        MatchTable::parse(&mut model, sheet, 0, sheet.rows(), include_disabled)?;
+        return Ok(VerilogPrinter::new(&model, "t", Dialect::Verilog).to_string());
+    }
+
+    #[test]
+    fn match_table_from_memory() {
+        let sheet = MemorySheet::new(&[
+            &["#match", "op"],
+            &["[6:4]", "[3:0]", "#primary(inst)", "#flag(T)"],
+            &["3'b000", "4'b0001", "a", "X"],
+            &["=OP_B", "LOW", "b", "X"],
+            &["7'b1000000", "", "c", "Y"],
+            &["3'b010", "x", "d", "Y"],
+            &["3'b011", "4'b0001", "e", "X"],
+        ])
+        .merge("A5:B5")
+        .comment("B2", "low bits")
+        .comment("A3", "spec 2.4")
+        .comment("C3", "add immediate")
+        .comment("D6", "type Y")
+        .hide_row(6)
+        .name("op_b", &["3'b001"])
+        .name("LOW", &["4'b0001"])
+        .name("x", &["4'b0010"]);
+        let x = |model: &mut Module| {
+            model.new_signal("x".into(), 4).unwrap();
+        };
+        let verilog = decode(&sheet, 7, x, false).unwrap();
+        for line in [
+            "// low bits\nwire [3:0] op_3to0;",
+            "// spec 2.4\n// add immediate\nwire inst_a;",
+            "wire inst_d;",
+            "// type Y\nwire T_Y;",
+            "assign x = ",
+            "assign T_X = inst_a || inst_b;",
+            // the merged constant is split over its columns
+            "assign inst_c = op_6to4_is_100 && op_3to0_is_0000;",
+        ] {
+            assert!(verilog.contains(line), "{}\n{}", line, verilog);
+        }
+        assert!(!verilog.contains("inst_e"), "{}", verilog);
+
+        let verilog = decode(&sheet, 7, x, true).unwrap();
+        assert!(
+            verilog.contains("assign T_X = (inst_a || inst_b) || inst_e;"),
+            "{}",
+            verilog
+        );
+
+        let sheet = MemorySheet::new(&[
+            &["#match", "op"],
+            &["[6:0]", "#primary(inst)"],
+            &["=OPS", "a"],
+        ])
+        .name("OPS", &["7'b0000000", "7'b0000001"]);
+        match decode(&sheet, 7, |_| (), false) {
+            Err(Error::InCell { cell, error }) => {
+                assert_eq!("A3", cell);
+                assert!(matches!(*error, Error::NameNotSingle { values: 2, .. }));
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn match_table_dont_care() {
+        let sheet = MemorySheet::new(&[
+            &["#match", "op"],
+            &["[6:4]", "[3:0]", "#primary(inst)"],
+            &["3'b1?0", "4'b0001", "a"],
+            &["3'b1x0", "4'b0010", "b"],
+            &["7'b01?????", "", "c"],
+            &["3'b111", "4'h?", "d"],
+        ])
+        .merge("A5:B5");
+        let verilog = decode(&sheet, 7, |_| (), false).unwrap();
+        for line in [
+            "assign op_6to4_is_1x0 = (op_6to4 & 3'b101) == 3'b100;",
+            "assign inst_a = op_6to4_is_1x0 && op_3to0_is_0001;",
+            "assign inst_b = op_6to4_is_1x0 && op_3to0_is_0010;",
+            "assign op_6to4_is_01x = (op_6to4 & 3'b110) == 3'b010;",
+            // a segment of don't care bits leaves only the other segments
+            "assign inst_c = op_6to4_is_01x;",
+            "assign inst_d = op_6to4_is_111;",
+        ] {
+            assert!(verilog.contains(line), "{}\n{}", line, verilog);
+        }
+        assert!(!verilog.contains("op_3to0_is_xxxx"), "{}", verilog);
+    }
+
+    #[test]
+    fn match_table_literals() {
+        let sheet = MemorySheet::new(&[
+            &["#match", "op"],
+            &["[6:4]", "[3:0]", "#primary(inst)"],
+            &["'h5", "4'B00_01", "a"],
+            &["7'h4_2", "", "b"],
+            &["-1", "'d0", "c"],
+        ])
+        .merge("A4:B4");
+        let verilog = decode(&sheet, 7, |_| (), false).unwrap();
+        for line in [
+            "assign inst_a = op_6to4_is_101 && op_3to0_is_0001;",
+            "assign inst_b = op_6to4_is_100 && op_3to0_is_0010;",
+            "assign inst_c = op_6to4_is_111 && op_3to0_is_0000;",
+        ] {
+            assert!(verilog.contains(line), "{}\n{}", line, verilog);
+        }
+
+        for (cell, expect) in [("4'h5", 3), ("'h8", 3)] {
+            let sheet = MemorySheet::new(&[
+                &["#match", "op"],
+                &["[6:4]", "[3:0]", "#primary(inst)"],
+                &[cell, "4'h1", "a"],
+            ]);
+            assert!(
+                matches!(
+                    decode(&sheet, 7, |_| (), false),
+                    Err(Error::SignalWidthMismatch { expect: e, .. }) if e == expect
+                ),
+                "{}",
+                cell
+            );
+        }
+        let sheet = MemorySheet::new(&[
+            &["#match", "op"],
+            &["[6:4]", "[3:0]", "#primary(inst)"],
+            &["3'h9", "4'h1", "a"],
+        ]);
+        assert!(matches!(
+            decode(&sheet, 7, |_| (), false),
+            Err(Error::BadMatchCell { cell, .. }) if cell == "A3"
+        ));
+    }
+
+    #[test]
+    fn match_table_malformed() {
+        let tables: [(&[&[&str]], &str); 9] = [
+            (
+                &[&["#match"], &["[7:0]", "#primary(p)"]],
+                "BadMatchCell { cell: \"B1\"",
+            ),
+            (
+                &[&["#match", "op +"], &["[7:0]", "#primary(p)"]],
+                "BadMatchCell { cell: \"B1\"",
+            ),
+            (&[&["#match", "op"]], "MissingPrimary { cell: \"A1\""),
+            (
+                &[&["#match", "op"], &["[7:0]"], &["8'h1"]],
+                "MissingPrimary { cell: \"A2\"",
+            ),
+            (
+                &[&["#match", "op"], &["[7:0]", "#prime(p)"]],
+                "BadMatchCell { cell: \"B2\"",
+            ),
+            (
+                &[&["#match", "op"], &["[9:0]", "#primary(p)"]],
+                "InCell { cell: \"A2\"",
+            ),
+            (
+                &[
+                    &["#match", "op"],
+                    &["[7:0]", "#primary(p)"],
+                    &["8'h1", "a", "x"],
+                ],
+                "BadMatchCell { cell: \"C3\"",
+            ),
+            (
+                &[&["#match", "op"], &["[7:0]", "#primary(p)"], &["imm", "a"]],
+                "InCell { cell: \"A3\", error: UndefinedSignal",
+            ),
+            (
+                &[
+                    &["#match", "op"],
+                    &["[7:0]", "#primary(p)"],
+                    &["8'h1", "8'h2"],
+                ],
+                "BadMatchCell { cell: \"B3\"",
+            ),
+        ];
+        for (rows, error) in tables {
+            let result = decode(&MemorySheet::new(rows), 8, |_| (), false);
+            assert!(
+                format!("{:?}", result).starts_with(&format!("Err({}", error)),
+                "{:?} {:?}",
+                rows,
+                result
+            );
+        }
+
+        // a constant merged past its segments
+        let sheet =
+            MemorySheet::new(&[&["#match", "op"], &["[7:0]", "#primary(p)"], &["16'h1", ""]])
+                .merge("A3:B3");
+        let result = decode(&sheet, 8, |_| (), false);
+        assert!(
+            format!("{:?}", result).starts_with("Err(BadMatchCell { cell: \"A3\""),
+            "{:?}",
+            result
+        );
+        // an empty header cell still takes its column
+        let sheet = MemorySheet::new(&[
+            &["#match", "op"],
+            &["[7:4]", "", "[3:0]", "#primary(p)"],
+            &["4'h1", "", "4'h2", "a"],
+        ]);
+        decode(&sheet, 8, |_| (), false).unwrap();
+    }
+
+    #[test]
+    fn match_table_wide_segment() {
+        // constants hold at most 128 bits
+        for (cell, error) in [
+            ("200'h1", "BadMatchCell { cell: \"A3\""),
+            ("1", "SignalWidthMismatch"),
+        ] {
+            let rows: &[&[&str]] = &[&["#match", "op"], &["[199:0]", "#primary(p)"], &[cell, "a"]];
+            let result = decode(&MemorySheet::new(rows), 200, |_| (), false);
+            assert!(
+                format!("{:?}", result).starts_with(&format!("Err({}", error)),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn match_table_compose() {
+        let sheet = MemorySheet::new(&[
+            &["#match", "{op[7:4], op[3:0]}"],
+            &["[7:4]", "[3:0]", "#primary(inst)"],
+            &["4'h1", "sext8(imm[3:0])", "a"],
+            &["4'h2", "{rd, rs}", "b"],
+            &["4'h3", "{rd, 2'b00}", "c"],
+            &["4'h4", "zext8(imm[3:0])", "d"],
+        ]);
+        let outputs = |model: &mut Module| {
+            model.new_output("imm".into(), 8).unwrap();
+            model.new_output("rd".into(), 2).unwrap();
+            model.new_output("rs".into(), 2).unwrap();
+        };
+        let verilog = decode(&sheet, 8, outputs, false).unwrap();
+        let seg = "match_wires_op_7to4_op_3to0_3to0";
+        let sign = format!("imm_sel_0 & {}[3]", seg);
+        assert!(
+            verilog.contains(&format!(
+                "assign imm = {{{}, {}, {}, {}, ({}) | (imm_sel_1 & {}[3]),",
+                sign, sign, sign, sign, sign, seg
+            )),
+            "{}",
+            verilog
+        );
+        assert!(
+            verilog.contains(&format!(
+                "assign rd = {{rd_sel_0 & {}[3], rd_sel_0 & {}[2]}};",
+                seg, seg
+            )),
+            "{}",
+            verilog
+        );
+        assert!(
+            verilog.contains("assign rd_sel_0 = inst_b || inst_c;"),
+            "{}",
+            verilog
+        );
+        assert!(
+            verilog.contains(&format!(
+                "assign rs = {{rs_sel_0 & {}[1], rs_sel_0 & {}[0]}};",
+                seg, seg
+            )),
+            "{}",
+            verilog
+        );
+
+        for (cell, error) in [
+            ("{rd, rs, rd}", "SignalWidthMismatch"),
+            ("{rd, rs1}", "UndefinedSignal"),
+            ("{rd*2}", "BadMatchCell"),
+            ("sext2(imm[3:0])", "SignalWidthMismatch"),
+            ("zext9(imm[3:0])", "SignalIndexOutOfRange"),
+        ] {
+            let sheet = MemorySheet::new(&[
+                &["#match", "op"],
+                &["[7:4]", "[3:0]", "#primary(inst)"],
+                &["4'h1", cell, "a"],
+            ]);
+            let signals = |model: &mut Module| {
+                model.new_signal("imm".into(), 8).unwrap();
+                model.new_signal("rd".into(), 2).unwrap();
+                model.new_signal("rs".into(), 2).unwrap();
+            };
+            // errors of the signals of a cell carry its location
+            let result = match decode(&sheet, 8, signals, false) {
+                Err(Error::InCell { cell, error }) if cell == "B3" => Err(*error),
+                result => result,
+            };
+            assert!(
+                format!("{:?}", result).starts_with(&format!("Err({}", error)),
+                "{} {:?}",
+                cell,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn match_table_params() {
+        let sheet = MemorySheet::new(&[
+            &["#match", "op"],
+            &["[6:4]", "[3:0]", "#primary(inst)"],
+            &["OP_HI", "OP_LO", "a"],
+            &["OP", "", "b"],
+        ])
+        .merge("A4:B4");
+        let params = |model: &mut Module| {
+            for (name, value) in [("OP_HI", "3'b101"), ("OP_LO", "4'h?"), ("OP", "7'h13")] {
+                model
+                    .new_param(name.into(), parser::constant(value).unwrap().1)
+                    .unwrap();
+            }
+        };
+        let verilog = decode(&sheet, 7, params, false).unwrap();
+        assert!(
+            verilog.contains(");\n\nlocalparam OP_HI = 3'b101;\nlocalparam OP = 7'b0010011;\n\n"),
+            "{}",
+            verilog
+        );
+        for line in [
+            "assign inst_a = op_6to4_is_101;",
+            // a param filling its segment is compared by name, one split over segments by value
+            "assign op_6to4_is_101 = op_6to4 == OP_HI;",
+            "assign op_6to4_is_001 = op_6to4 == 3'b001;",
+            "assign inst_b = op_6to4_is_001 && op_3to0_is_0011;",
+        ] {
+            assert!(verilog.contains(line), "{}\n{}", line, verilog);
+        }
     }
 }
@@ -3,10 +3,23 @@ use std::collections::HashMap;
 use std::ops::Range;
 use std::vec::Vec;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct SignalMapSlot {
     pub ranges: Vec<(u16, u16)>,
     pub segs: Range<usize>,
+    /// bits of the segments the slot takes, counted from the MSB
+    pub bits: Range<usize>,
+    /// (width, signed), the signal is extended above its ranges by the MSB or by zeros
+    pub extend: Option<(u16, bool)>,
+}
+
+/// a part of a mapping cell, bits of a signal, or bits left unmapped without a signal
+#[derive(Debug)]
+pub struct MapTarget {
+    pub signal: Option<SignalKey>,
+    pub ranges: Vec<(u16, u16)>,
+    pub width: u16,
+    pub extend: Option<(u16, bool)>,
 }
 
 #[derive(Debug)]
@@ -25,19 +38,12 @@ impl SignalMapSlots {
         };
     }
 
-    pub fn insert(&mut self, case_idx: usize, ranges: Vec<(u16, u16)>, segs: Range<usize>) {
-        let slot_idx = match self
-            .slots
-            .iter()
-            .position(|s| s.ranges == ranges && s.segs == segs)
-        {
+    pub fn insert(&mut self, case_idx: usize, slot: SignalMapSlot) {
+        let slot_idx = match self.slots.iter().position(|s| *s == slot) {
             Some(slot_idx) => slot_idx,
             None => {
                 let slot_idx = self.slots.len();
-                self.slots.push(SignalMapSlot {
-                    ranges: ranges,
-                    segs: segs,
-                });
+                self.slots.push(slot);
                 slot_idx
             }
         };
@@ -62,19 +68,10 @@ impl SignalMapCase {
         return SignalMapCase(HashMap::new());
     }
 
-    pub fn insert(
-        &mut self,
-        signal_key: &SignalKey,
-        case_idx: usize,
-        ranges: Vec<(u16, u16)>,
-        segs: Range<usize>,
-    ) {
+    pub fn insert(&mut self, signal_key: &SignalKey, case_idx: usize, slot: SignalMapSlot) {
         if !self.0.contains_key(signal_key) {
             self.0.insert(signal_key.clone(), SignalMapSlots::new());
         }
-        self.0
-            .get_mut(signal_key)
-            .unwrap()
-            .insert(case_idx, ranges, segs);
+        self.0.get_mut(signal_key).unwrap().insert(case_idx, slot);
     }
 }
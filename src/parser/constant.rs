@@ -1,67 +1,351 @@
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while1},
-    character::complete::{hex_digit1, u128 as uint128, u16 as uint16},
-    combinator::map_res,
-    sequence::{pair},
-    IResult,
+    bytes::complete::take_while,
+    character::complete::{char, one_of, satisfy, space0},
+    combinator::{map, opt, peek, recognize},
+    error::{Error, ErrorKind},
+    sequence::{pair, terminated},
+    Err, IResult,
 };
 
-fn from_hex(input: &str) -> Result<u128, std::num::ParseIntError> {
-    u128::from_str_radix(input, 16)
+use crate::utils::{pattern_format, width_mask};
+use std::fmt;
+
+/// `x`, `z` and `?` digits match any bit
+fn is_dont_care(c: char) -> bool {
+    return matches!(c, 'x' | 'X' | 'z' | 'Z' | '?');
+}
+
+fn is_digit(c: char, radix: u32) -> bool {
+    return c.is_digit(radix) || (radix != 10 && is_dont_care(c));
+}
+
+/// a verilog number literal, the bits of `mask` that are clear are don't care
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Literal {
+    /// `None` for an unsized number, like `'h1F` or `42`
+    pub width: Option<u16>,
+    /// two's complement, a negative unsized number has every bit above its digits set
+    pub value: u128,
+    pub mask: u128,
+}
+
+impl Literal {
+    fn negative(&self) -> bool {
+        return self.width.is_none() && self.value >> 127 == 1;
+    }
+
+    /// bits the literal takes, its width or the fewest bits an unsized number fits in
+    pub fn bits(&self) -> u16 {
+        if let Some(width) = self.width {
+            return width;
+        }
+        return match self.negative() {
+            true => bit_width(!self.value) + 1,
+            false => bit_width(self.value & self.mask).max(1),
+        };
+    }
+
+    /// every bit of the literal is known, none is don't care
+    pub fn known(&self) -> bool {
+        let bits = width_mask(self.bits());
+        return self.mask & bits == bits;
+    }
+
+    /// (value, mask) of the literal in `width` bits, an unsized number fits any width it needs
+    /// no more bits than, a sized one only its own width, neither fits more than 128 bits
+    pub fn fit(&self, width: u16) -> Option<(u128, u128)> {
+        let fits = match self.width {
+            Some(w) => w == width,
+            None => self.bits() <= width && width <= 128,
+        };
+        if !fits {
+            return None;
+        }
+        let mask = width_mask(width);
+        return Some((self.value & mask, self.mask & mask));
+    }
 }
 
-fn from_bin(input: &str) -> Result<u128, std::num::ParseIntError> {
-    u128::from_str_radix(input, 2)
+impl fmt::Display for Literal {
+    /// a literal that parses back to itself, like `5'b0x011`, `42` or `-3`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bits = self.bits();
+        return match self.width {
+            Some(w) => write!(f, "{}'b{}", w, pattern_format(w, self.value, self.mask)),
+            None if self.mask & width_mask(bits) != width_mask(bits) => {
+                write!(f, "'b{}", pattern_format(bits, self.value, self.mask))
+            }
+            None => write!(f, "{}", self.value as i128),
+        };
+    }
 }
 
-fn is_bin_digit(c: char) -> bool {
-    c.is_digit(2)
+/// digits of a number, the bits of `mask` that are clear are don't care
+struct Digits {
+    value: u128,
+    mask: u128,
+    bits: usize,
+    /// the leftmost digit is don't care, so are the bits above the digits
+    open: bool,
 }
 
-fn constant_hex(input: &str) -> IResult<&str, u128> {
-    let (s, (_, num)) = pair(tag("'h"), map_res(hex_digit1, from_hex))(input)?;
-    return Ok((s, num));
+/// `None` when the value takes more than 128 bits
+fn masked_digits(digits: &str, radix: u32) -> Option<Digits> {
+    let digits: Vec<char> = digits.chars().filter(|c| *c != '_').collect();
+    let open = digits.first().map(|c| is_dont_care(*c)).unwrap_or(false);
+    if radix == 10 {
+        // a decimal number is either a value or a single don't care digit
+        if open {
+            return Some(Digits {
+                value: 0,
+                mask: 0,
+                bits: 0,
+                open,
+            });
+        }
+        let mut value: u128 = 0;
+        for c in &digits {
+            value = value
+                .checked_mul(10)?
+                .checked_add(c.to_digit(10)? as u128)?;
+        }
+        return Some(Digits {
+            value,
+            mask: u128::MAX,
+            bits: 128,
+            open,
+        });
+    }
+
+    let bits = match radix {
+        2 => 1,
+        8 => 3,
+        _ => 4,
+    };
+    let mut value: u128 = 0;
+    let mut mask: u128 = 0;
+    for c in &digits {
+        if value.leading_zeros() < bits {
+            return None;
+        }
+        value <<= bits;
+        mask <<= bits;
+        if !is_dont_care(*c) {
+            value |= c.to_digit(radix)? as u128;
+            mask |= (1 << bits) - 1;
+        }
+    }
+    return Some(Digits {
+        value,
+        mask,
+        bits: digits.len() * bits as usize,
+        open,
+    });
 }
 
-fn constant_dec(input: &str) -> IResult<&str, u128> {
-    let (s, (_, num)) = pair(tag("'d"), uint128)(input)?;
-    return Ok((s, num));
+/// like: 42, 1_000
+fn unsigned_number(input: &str) -> IResult<&str, &str> {
+    return recognize(pair(
+        satisfy(|c| c.is_ascii_digit()),
+        take_while(|c: char| c.is_ascii_digit() || c == '_'),
+    ))(input);
 }
 
-fn constant_bin(input: &str) -> IResult<&str, u128> {
-    let (s, (_, num)) = pair(tag("'b"), map_res(take_while1(is_bin_digit), from_bin))(input)?;
-    return Ok((s, num));
+/// width of a sized number, not zero and at most 128 bits, the digits before a base
+fn size(input: &str) -> IResult<&str, u16> {
+    let (rest, digits) = terminated(
+        recognize(pair(
+            one_of("123456789"),
+            take_while(|c: char| c.is_ascii_digit() || c == '_'),
+        )),
+        peek(pair(space0, char('\''))),
+    )(input)?;
+    return match digits.replace('_', "").parse::<u16>() {
+        Ok(width) if width <= 128 => Ok((rest, width)),
+        _ => Err(Err::Failure(Error::new(input, ErrorKind::TooLarge))),
+    };
+}
+
+/// like: 'h, 'sd, 'B, the radix
+fn base(input: &str) -> IResult<&str, u32> {
+    let (input, _) = pair(char('\''), opt(one_of("sS")))(input)?;
+    return map(one_of("bBoOdDhH"), |c| match c.to_ascii_lowercase() {
+        'b' => 2,
+        'o' => 8,
+        'd' => 10,
+        _ => 16,
+    })(input);
+}
+
+/// like: 'h3a4b, 'b1?0x?, 'dx, (radix, digits)
+fn based_number(input: &str) -> IResult<&str, (u32, &str)> {
+    let (input, radix) = terminated(base, space0)(input)?;
+    let (input, digits) = match radix {
+        10 => alt((
+            unsigned_number,
+            recognize(pair(satisfy(is_dont_care), take_while(|c| c == '_'))),
+        ))(input)?,
+        _ => recognize(pair(
+            satisfy(|c| is_digit(c, radix)),
+            take_while(|c| is_digit(c, radix) || c == '_'),
+        ))(input)?,
+    };
+    return Ok((input, (radix, digits)));
 }
 
 fn bit_width(mut num: u128) -> u16 {
     let mut w = 0;
     while num > 0 {
-        num = num >> 1;
-        w = w + 1;
+        num >>= 1;
+        w += 1;
     }
-    w
+    return w;
 }
 
-pub fn constant(input: &str) -> IResult<&str, (u16, u128)> {
-    let (s, (w, n)) = pair(uint16, alt((constant_hex, constant_dec, constant_bin)))(input)?;
-    assert!(w >= bit_width(n));
-    Ok((s, (w, n)))
+/// a number of IEEE 1364, like: 16'h3a4b, 32'h0000_0013, 5'b1?0x?, 4'sb1010, 'o17, -4'd3, 42,
+/// digits that do not fit the width are an error
+pub fn constant(input: &str) -> IResult<&str, Literal> {
+    let (rest, negative) = map(opt(terminated(char('-'), space0)), |n| n.is_some())(input)?;
+    let (rest, width) = opt(terminated(size, space0))(rest)?;
+    let (rest, (radix, digits)) = match width {
+        Some(_) => based_number(rest)?,
+        None => alt((based_number, map(unsigned_number, |d| (10, d))))(rest)?,
+    };
+    // a size of zero, or one starting with zero
+    if width.is_none() && radix == 10 && rest.trim_start().starts_with('\'') {
+        return Err(Err::Failure(Error::new(input, ErrorKind::Verify)));
+    }
+    let too_large: IResult<&str, Literal> =
+        Err(Err::Failure(Error::new(input, ErrorKind::TooLarge)));
+    let d = match masked_digits(digits, radix) {
+        Some(d) => d,
+        None => return too_large,
+    };
+    let mask = match d.open {
+        true => d.mask,
+        // bits above the digits are zeros
+        false => d.mask | !width_mask(d.bits.min(128) as u16),
+    };
+    let literal = match width {
+        Some(w) => {
+            if bit_width(d.value) > w {
+                return too_large;
+            }
+            let value = match negative {
+                true => d.value.wrapping_neg() & width_mask(w),
+                false => d.value,
+            };
+            Literal {
+                width: Some(w),
+                value,
+                mask: mask & width_mask(w),
+            }
+        }
+        None => Literal {
+            width: None,
+            value: match negative {
+                true => d.value.wrapping_neg(),
+                false => d.value,
+            },
+            mask,
+        },
+    };
+    return Ok((rest, literal));
 }
 
 #[cfg(test)]
 mod tests {
-    use super::constant;
+    use super::*;
+
+    fn sized(width: u16, value: u128, mask: u128) -> Literal {
+        return Literal {
+            width: Some(width),
+            value,
+            mask,
+        };
+    }
 
     #[test]
     fn constant_hex_test() {
         let (_, r) = constant("16'h3a4b").unwrap();
-        assert_eq!((16, 0x3a4b), r);
+        assert_eq!(sized(16, 0x3a4b, 0xffff), r);
         let (_, r) = constant("16'd2344").unwrap();
-        assert_eq!((16, 2344), r);
+        assert_eq!(sized(16, 2344, 0xffff), r);
         let (_, r) = constant("32'hfabcd444").unwrap();
-        assert_eq!((32, 0xfabc_d444), r);
+        assert_eq!(sized(32, 0xfabc_d444, 0xffff_ffff), r);
         let (_, r) = constant("8'b11100000").unwrap();
-        assert_eq!((8, 0b11100000), r);
+        assert_eq!(sized(8, 0b11100000, 0xff), r);
+    }
+
+    #[test]
+    fn constant_dont_care() {
+        let (_, r) = constant("5'b1?0x?").unwrap();
+        assert_eq!(sized(5, 0b10000, 0b10100), r);
+        let (_, r) = constant("8'h3?").unwrap();
+        assert_eq!(sized(8, 0x30, 0xf0), r);
+        let (_, r) = constant("8'hZ3").unwrap();
+        assert_eq!(sized(8, 0x03, 0x0f), r);
+        // bits above the digits are zeros, unless the leftmost digit is don't care
+        let (_, r) = constant("6'bx1").unwrap();
+        assert_eq!(sized(6, 0b01, 0b01), r);
+        let (_, r) = constant("6'b1x").unwrap();
+        assert_eq!(sized(6, 0b10, 0b111110), r);
+        let (_, r) = constant("4'dx").unwrap();
+        assert_eq!(sized(4, 0, 0), r);
+    }
+
+    #[test]
+    fn constant_literal_syntax() {
+        let (_, r) = constant("32'h0000_0013").unwrap();
+        assert_eq!(sized(32, 0x13, 0xffff_ffff), r);
+        let (_, r) = constant("8'HfF").unwrap();
+        assert_eq!(sized(8, 0xff, 0xff), r);
+        let (_, r) = constant("6'o7_1").unwrap();
+        assert_eq!(sized(6, 0o71, 0x3f), r);
+        let (_, r) = constant("4'sb1010").unwrap();
+        assert_eq!(sized(4, 0b1010, 0xf), r);
+        let (_, r) = constant("8 'd 5").unwrap();
+        assert_eq!(sized(8, 5, 0xff), r);
+        let (_, r) = constant("-4'd3").unwrap();
+        assert_eq!(sized(4, 0b1101, 0xf), r);
+
+        let (_, r) = constant("'h1F").unwrap();
+        assert_eq!((None, 0x1f), (r.width, r.value));
+        assert_eq!(5, r.bits());
+        assert_eq!(Some((0x1f, 0x7f)), r.fit(7));
+        assert_eq!(None, r.fit(4));
+        let (_, r) = constant("42").unwrap();
+        assert_eq!(Some((42, 0xff)), r.fit(8));
+        let (_, r) = constant("-3").unwrap();
+        assert_eq!(3, r.bits());
+        assert_eq!(Some((0b101, 0b111)), r.fit(3));
+        assert_eq!(None, r.fit(2));
+        let (_, r) = constant("'bx1").unwrap();
+        assert_eq!(Some((0b01, 0b01)), r.fit(4));
+        assert_eq!(None, constant("8'h1F").unwrap().1.fit(7));
+    }
+
+    #[test]
+    fn constant_errors() {
+        for bad in ["4'h1F", "3'd8", "'d340282366920938463463374607431768211456"] {
+            assert!(matches!(constant(bad), Err(Err::Failure(_))), "{}", bad);
+        }
+        assert!(matches!(constant("8'h1F").map(|r| r.0), Ok("")));
+        assert!(matches!(constant("0'h0"), Err(Err::Failure(_))));
+        assert!(matches!(constant("8'b2"), Err(Err::Error(_))));
+        assert!(matches!(constant("'h_1"), Err(Err::Error(_))));
+        assert!(constant("99999'h0").is_err());
+        assert!(matches!(constant("128'h1"), Ok(("", _))));
+        // a plain number is no width
+        assert_eq!(
+            Some((65536, u128::MAX)),
+            constant("65536").ok().map(|r| (r.1.value, r.1.mask))
+        );
+        match constant("200'h1") {
+            Err(Err::Failure(e)) => assert_eq!(ErrorKind::TooLarge, e.code),
+            r => panic!("{:?}", r),
+        }
+        assert_eq!(None, constant("1").unwrap().1.fit(200));
     }
 }
@@ -0,0 +1,744 @@
+use super::{constant, identifier, ranges_format, sginal_ref, signal_wire, Literal};
+use crate::utils::width_mask;
+use crate::verilog_model::{Error, LogicElem, LogicTree, Module, SignalWidth, Wire};
+use nom::{
+    branch::alt,
+    character::complete::{char, space0},
+    combinator::{all_consuming, map, opt},
+    multi::separated_list1,
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+    IResult,
+};
+use std::fmt;
+
+/// an expression of a cell, like `rs1 == 5'd0 ? {20{imm[11]}} : 20'h0`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    /// signal and the ranges it selects, from MSB to LSB
+    Signal(String, Option<Vec<(u16, u16)>>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// operands from MSB to LSB
+    Concat(Vec<Expr>),
+    /// a constant count, like `XLEN-12`, and what it repeats
+    Replicate(Box<Expr>, Box<Expr>),
+}
+
+/// binary operators from the lowest precedence to the highest, like verilog
+const LEVELS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+fn ws<'a, O, F>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+{
+    return delimited(space0, f, space0);
+}
+
+/// an operator of `ops`, not the start of a longer one like `|` of `||`
+fn operator(ops: &'static [&'static str]) -> impl Fn(&str) -> IResult<&str, &'static str> {
+    return move |input: &str| {
+        let input = input.trim_start();
+        for op in ops {
+            if let Some(rest) = input.strip_prefix(op) {
+                let longer = match *op {
+                    "|" => rest.starts_with('|'),
+                    "&" => rest.starts_with('&'),
+                    "<" => rest.starts_with(['<', '=']),
+                    ">" => rest.starts_with(['>', '=']),
+                    _ => false,
+                };
+                if !longer {
+                    return Ok((rest.trim_start(), *op));
+                }
+            }
+        }
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Tag,
+        )));
+    };
+}
+
+/// like: {rs1, 2'b00}, {4{sign}}, {XLEN-12{imm[11]}}
+fn concat(input: &str) -> IResult<&str, Expr> {
+    let replicate = map(
+        pair(
+            ws(|input| binary(input, 0)),
+            delimited(
+                ws(char('{')),
+                separated_list1(ws(char(',')), expression),
+                char('}'),
+            ),
+        ),
+        |(count, mut exprs)| {
+            let expr = match exprs.len() {
+                1 => exprs.pop().unwrap(),
+                _ => Expr::Concat(exprs),
+            };
+            (count, expr)
+        },
+    );
+    return alt((
+        delimited(
+            char('{'),
+            map(replicate, |(count, expr)| {
+                Expr::Replicate(Box::new(count), Box::new(expr))
+            }),
+            ws(char('}')),
+        ),
+        map(
+            delimited(
+                char('{'),
+                separated_list1(ws(char(',')), expression),
+                ws(char('}')),
+            ),
+            Expr::Concat,
+        ),
+    ))(input);
+}
+
+fn primary(input: &str) -> IResult<&str, Expr> {
+    return ws(alt((
+        delimited(char('('), expression, char(')')),
+        concat,
+        map(constant, Expr::Literal),
+        map(sginal_ref, |(name, ranges)| {
+            Expr::Signal(name.into(), ranges)
+        }),
+    )))(input);
+}
+
+fn unary(input: &str) -> IResult<&str, Expr> {
+    let op = ws(alt((
+        map(char('~'), |_| "~"),
+        map(
+            terminated(char('!'), nom::combinator::not(char('='))),
+            |_| "!",
+        ),
+    )));
+    return alt((
+        map(pair(op, unary), |(op, e)| Expr::Unary(op, Box::new(e))),
+        primary,
+    ))(input);
+}
+
+/// left associative operators of `LEVELS[level]` and above
+fn binary(input: &str, level: usize) -> IResult<&str, Expr> {
+    if level == LEVELS.len() {
+        return unary(input);
+    }
+    let (mut input, mut expr) = binary(input, level + 1)?;
+    while let Ok((rest, op)) = operator(LEVELS[level])(input) {
+        let (rest, rhs) = binary(rest, level + 1)?;
+        expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        input = rest;
+    }
+    return Ok((input, expr));
+}
+
+/// like: a & ~b, op == 7'b0110011 ? rs2 : {27'd0, shamt}
+pub fn expression(input: &str) -> IResult<&str, Expr> {
+    let (input, cond) = binary(input, 0)?;
+    let (input, branches) = opt(tuple((
+        preceded(ws(char('?')), expression),
+        preceded(ws(char(':')), expression),
+    )))(input)?;
+    return Ok((
+        input,
+        match branches {
+            Some((t, e)) => Expr::Ternary(Box::new(cond), Box::new(t), Box::new(e)),
+            None => cond,
+        },
+    ));
+}
+
+/// like: [XLEN-1:0]i_instr, bounds are constant expressions
+pub fn signal_def_expr(input: &str) -> IResult<&str, ((Expr, Expr), &str)> {
+    return pair(
+        delimited(
+            char('['),
+            separated_pair(ws(expression), char(':'), ws(expression)),
+            char(']'),
+        ),
+        identifier,
+    )(input);
+}
+
+/// like: OP_LOAD = 5'b00000, XLEN = 32
+pub fn param_def(input: &str) -> IResult<&str, (&str, Expr)> {
+    return all_consuming(separated_pair(ws(identifier), char('='), ws(expression)))(input);
+}
+
+/// the whole text of a cell as an expression
+pub fn expression_cell(input: &str) -> Option<Expr> {
+    return all_consuming(ws(expression))(input).ok().map(|(_, e)| e);
+}
+
+/// a built operand, unsized literals take the width of what they are used with
+enum Operand {
+    Sized(LogicTree, SignalWidth),
+    Unsized(Literal),
+}
+
+fn unit(wire: Wire) -> LogicTree {
+    return LogicElem::Unit(wire).into();
+}
+
+#[allow(dead_code)]
+impl Expr {
+    /// logic of the expression driving a signal of `width` bits
+    pub fn logic(&self, model: &Module, width: SignalWidth) -> Result<LogicTree, Error> {
+        return self.sized(model.build(self)?, width);
+    }
+
+    /// value of a constant expression of numbers and params
+    pub fn eval(&self, model: &Module) -> Result<Literal, Error> {
+        let not_constant = || Error::NotConstant {
+            expr: self.to_string(),
+        };
+        // every bit known, sized results are cut to their width
+        let known = |l: Literal| match l.mask & width_mask(l.bits()) == width_mask(l.bits()) {
+            true => Ok(l),
+            false => Err(not_constant()),
+        };
+        let literal = |width: Option<SignalWidth>, value: u128| Literal {
+            width,
+            value: value & width.map(width_mask).unwrap_or(u128::MAX),
+            mask: width.map(width_mask).unwrap_or(u128::MAX),
+        };
+        return match self {
+            Expr::Literal(l) => Ok(*l),
+            Expr::Signal(name, None) => match model.param(name) {
+                Some(value) => Ok(*value),
+                None => Err(Error::UndefinedName { name: name.clone() }),
+            },
+            Expr::Unary(op, e) => {
+                let e = known(e.eval(model)?)?;
+                Ok(match *op {
+                    "~" => literal(e.width, !e.value),
+                    _ => literal(Some(1), (e.value == 0) as u128),
+                })
+            }
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (known(a.eval(model)?)?, known(b.eval(model)?)?);
+                let width = match (a.width, b.width) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    (a, b) => a.or(b),
+                };
+                let (x, y) = (a.value, b.value);
+                Ok(match *op {
+                    "+" => literal(width, x.wrapping_add(y)),
+                    "-" => literal(width, x.wrapping_sub(y)),
+                    "*" => literal(width, x.wrapping_mul(y)),
+                    "/" if y == 0 => return Err(not_constant()),
+                    "/" => literal(width, x / y),
+                    "&" => literal(width, x & y),
+                    "|" => literal(width, x | y),
+                    "^" => literal(width, x ^ y),
+                    "<<" => literal(a.width, x.checked_shl(y as u32).unwrap_or(0)),
+                    ">>" => literal(a.width, x.checked_shr(y as u32).unwrap_or(0)),
+                    "==" => literal(Some(1), (x == y) as u128),
+                    "!=" => literal(Some(1), (x != y) as u128),
+                    "<" => literal(Some(1), (x < y) as u128),
+                    "<=" => literal(Some(1), (x <= y) as u128),
+                    ">" => literal(Some(1), (x > y) as u128),
+                    ">=" => literal(Some(1), (x >= y) as u128),
+                    "&&" => literal(Some(1), (x != 0 && y != 0) as u128),
+                    _ => literal(Some(1), (x != 0 || y != 0) as u128),
+                })
+            }
+            Expr::Ternary(c, t, e) => match known(c.eval(model)?)?.value {
+                0 => e.eval(model),
+                _ => t.eval(model),
+            },
+            _ => Err(not_constant()),
+        };
+    }
+
+    /// a bit index of a declaration, like the `XLEN-1` of `[XLEN-1:0]`
+    pub fn eval_index(&self, model: &Module) -> Result<SignalWidth, Error> {
+        let value = self.eval(model)?;
+        let bits = width_mask(value.bits());
+        // a negative number is too large as well
+        if value.mask & bits != bits || value.value > SignalWidth::MAX as u128 {
+            return Err(Error::NotConstant {
+                expr: self.to_string(),
+            });
+        }
+        return Ok(value.value as SignalWidth);
+    }
+
+    fn mismatch(&self, left: SignalWidth, right: SignalWidth) -> Error {
+        return Error::OperandWidthMismatch {
+            expr: self.to_string(),
+            left,
+            right,
+        };
+    }
+
+    fn sized(&self, operand: Operand, width: SignalWidth) -> Result<LogicTree, Error> {
+        return match operand {
+            Operand::Sized(tree, w) if w == width => Ok(tree),
+            Operand::Sized(_, w) => Err(self.mismatch(width, w)),
+            Operand::Unsized(literal) => match literal.fit(width) {
+                Some((value, _)) => Ok(unit(Wire::bit(width, value))),
+                None => Err(self.mismatch(width, literal.bits())),
+            },
+        };
+    }
+
+    /// an unsized literal in the fewest bits it takes
+    fn self_sized(operand: Operand) -> (LogicTree, SignalWidth) {
+        return match operand {
+            Operand::Sized(tree, w) => (tree, w),
+            Operand::Unsized(literal) => {
+                let w = literal.bits();
+                (unit(Wire::bit(w, literal.value & width_mask(w))), w)
+            }
+        };
+    }
+
+    /// both operands in the same width
+    fn same_width(
+        &self,
+        a: Operand,
+        b: Operand,
+    ) -> Result<(LogicTree, LogicTree, SignalWidth), Error> {
+        let width = match (&a, &b) {
+            (Operand::Sized(_, w), _) | (Operand::Unsized(_), Operand::Sized(_, w)) => *w,
+            (Operand::Unsized(a), Operand::Unsized(b)) => a.bits().max(b.bits()),
+        };
+        return Ok((self.sized(a, width)?, self.sized(b, width)?, width));
+    }
+
+    /// a constant amount or count, like the shift of `a << 2`
+    fn constant(&self) -> Result<u16, Error> {
+        return match self {
+            Expr::Literal(l) if l.value <= u16::MAX as u128 => match l.fit(l.bits()) {
+                Some((value, mask)) if mask == width_mask(l.bits()) => Ok(value as u16),
+                _ => Err(Error::NotConstant {
+                    expr: self.to_string(),
+                }),
+            },
+            _ => Err(Error::NotConstant {
+                expr: self.to_string(),
+            }),
+        };
+    }
+}
+
+trait Build {
+    fn build(&self, expr: &Expr) -> Result<Operand, Error>;
+}
+
+impl Build for Module {
+    fn build(&self, expr: &Expr) -> Result<Operand, Error> {
+        return match expr {
+            Expr::Literal(literal) => match literal.width {
+                Some(w) if literal.mask != width_mask(w) => Err(Error::NotConstant {
+                    expr: expr.to_string(),
+                }),
+                Some(w) => Ok(Operand::Sized(unit(Wire::bit(w, literal.value)), w)),
+                None => Ok(Operand::Unsized(*literal)),
+            },
+            Expr::Signal(name, None) if self.param(name).is_some() => {
+                let value = expr.eval(self)?;
+                match value.width {
+                    // a sized param is referred to by its name
+                    Some(w) if value.known() => {
+                        Ok(Operand::Sized(LogicElem::Param(name.clone()).into(), w))
+                    }
+                    _ => self.build(&Expr::Literal(value)),
+                }
+            }
+            Expr::Signal(name, ranges) => {
+                let wire = signal_wire(self, name, ranges)?;
+                let width = wire.len();
+                Ok(Operand::Sized(unit(wire), width))
+            }
+            Expr::Unary(op, e) => {
+                let (tree, w) = Expr::self_sized(self.build(e)?);
+                Ok(match *op {
+                    "~" => Operand::Sized(!tree, w),
+                    _ => Operand::Sized(tree.logic_not(), 1),
+                })
+            }
+            Expr::Binary(op @ ("==" | "!="), a, b) => {
+                // a literal with don't care bits compares the bits it cares about
+                let literal = |e: &Expr| match e {
+                    Expr::Literal(l) => Some(*l),
+                    Expr::Signal(name, None) => self.param(name).copied(),
+                    _ => None,
+                };
+                let partial = |l: &Literal| l.width.map(|w| l.mask != width_mask(w)) == Some(true);
+                let (other, literal) = match (literal(a), literal(b)) {
+                    (_, Some(l)) if partial(&l) => (a, l),
+                    (Some(l), _) if partial(&l) => (b, l),
+                    _ => {
+                        let (a, b, _) = expr.same_width(self.build(a)?, self.build(b)?)?;
+                        return Ok(Operand::Sized(
+                            match *op {
+                                "==" => a.equal(b),
+                                _ => a.not_equal(b),
+                            },
+                            1,
+                        ));
+                    }
+                };
+                let (tree, w) = Expr::self_sized(self.build(other)?);
+                let (value, mask) = match literal.fit(w) {
+                    Some(fit) => fit,
+                    None => return Err(expr.mismatch(w, literal.bits())),
+                };
+                let masked = tree & unit(Wire::bit(w, mask));
+                let value = unit(Wire::bit(w, value & mask));
+                Ok(Operand::Sized(
+                    match *op {
+                        "==" => masked.equal(value),
+                        _ => masked.not_equal(value),
+                    },
+                    1,
+                ))
+            }
+            Expr::Binary(op @ ("&&" | "||"), a, b) => {
+                let (a, _) = Expr::self_sized(self.build(a)?);
+                let (b, _) = Expr::self_sized(self.build(b)?);
+                Ok(Operand::Sized(
+                    match *op {
+                        "&&" => a.logic_and(b),
+                        _ => a.logic_or(b),
+                    },
+                    1,
+                ))
+            }
+            Expr::Binary(op @ ("<<" | ">>"), a, b) => {
+                let amount = b.constant()?;
+                let (tree, w) = Expr::self_sized(self.build(a)?);
+                Ok(Operand::Sized(
+                    match *op {
+                        "<<" => tree << amount,
+                        _ => tree >> amount,
+                    },
+                    w,
+                ))
+            }
+            Expr::Binary(op, a, b) => {
+                let (a, b, w) = expr.same_width(self.build(a)?, self.build(b)?)?;
+                Ok(match *op {
+                    "<" => Operand::Sized(a.less_than(b), 1),
+                    "<=" => Operand::Sized(a.less_than_equal(b), 1),
+                    ">" => Operand::Sized(a.greate_than(b), 1),
+                    ">=" => Operand::Sized(a.greate_than_equal(b), 1),
+                    "&" => Operand::Sized(a & b, w),
+                    "|" => Operand::Sized(a | b, w),
+                    "^" => Operand::Sized(a ^ b, w),
+                    "+" => Operand::Sized(a + b, w),
+                    "-" => Operand::Sized(a - b, w),
+                    "*" => Operand::Sized(a * b, w),
+                    _ => Operand::Sized(a / b, w),
+                })
+            }
+            Expr::Ternary(c, t, e) => {
+                let (cond, w) = Expr::self_sized(self.build(c)?);
+                if w != 1 {
+                    return Err(c.mismatch(1, w));
+                }
+                let (t, e, w) = expr.same_width(self.build(t)?, self.build(e)?)?;
+                Ok(Operand::Sized(cond.ternary(t, e), w))
+            }
+            Expr::Concat(exprs) => {
+                let mut trees = Vec::new();
+                let mut width = 0;
+                for e in exprs {
+                    match self.build(e)? {
+                        Operand::Sized(tree, w) => {
+                            trees.push(tree);
+                            width += w;
+                        }
+                        // an unsized number has no width to take in a concatenation
+                        Operand::Unsized(_) => {
+                            return Err(Error::NotConstant {
+                                expr: e.to_string(),
+                            })
+                        }
+                    }
+                }
+                Ok(Operand::Sized(LogicElem::Combine(trees).into(), width))
+            }
+            Expr::Replicate(count, e) => {
+                // a count is fully known, not negative and fits 16 bits
+                let count = count.eval_index(self)?;
+                let (tree, w) = match self.build(e)? {
+                    Operand::Sized(tree, w) if count > 0 && w.checked_mul(count).is_some() => {
+                        (tree, w)
+                    }
+                    _ => {
+                        return Err(Error::NotConstant {
+                            expr: expr.to_string(),
+                        })
+                    }
+                };
+                Ok(Operand::Sized(
+                    LogicElem::Combine(vec![tree; count as usize]).into(),
+                    w * count,
+                ))
+            }
+        };
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // operators of operands are always parenthesized
+        let operand = |f: &mut fmt::Formatter<'_>, e: &Expr| match e {
+            Expr::Binary(..) | Expr::Ternary(..) => write!(f, "({})", e),
+            _ => write!(f, "{}", e),
+        };
+        return match self {
+            Expr::Literal(l) => write!(f, "{}", l),
+            Expr::Signal(name, None) => write!(f, "{}", name),
+            Expr::Signal(name, Some(ranges)) => write!(f, "{}{}", name, ranges_format(ranges)),
+            Expr::Unary(op, e) => {
+                write!(f, "{}", op)?;
+                operand(f, e)
+            }
+            Expr::Binary(op, a, b) => {
+                operand(f, a)?;
+                write!(f, " {} ", op)?;
+                operand(f, b)
+            }
+            Expr::Ternary(c, t, e) => {
+                operand(f, c)?;
+                write!(f, " ? ")?;
+                operand(f, t)?;
+                write!(f, " : ")?;
+                operand(f, e)
+            }
+            Expr::Concat(exprs) => {
+                let exprs: Vec<String> = exprs.iter().map(|e| e.to_string()).collect();
+                write!(f, "{{{}}}", exprs.join(", "))
+            }
+            Expr::Replicate(count, e) => {
+                write!(f, "{{")?;
+                operand(f, count)?;
+                match &**e {
+                    Expr::Concat(_) => write!(f, "{}}}", e),
+                    _ => write!(f, "{{{}}}}}", e),
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Dialect, VerilogPrinter};
+    use crate::parser::assign_line;
+    use crate::verilog_model::{Signal, SignalSource};
+
+    #[test]
+    fn expression_precedence() {
+        let text = |input: &str| expression_cell(input).unwrap().to_string();
+        assert_eq!("(a | (b & c)) || d", text("a | b & c || d"));
+        assert_eq!("((a + b) << 2) == c", text("a+b<<2 == c"));
+        assert_eq!("~a & !b", text("~a & !b"));
+        assert_eq!("(a < b) != (c <= d)", text("a<b != c<=d"));
+        assert_eq!("((a - b) - c) ^ d", text("a - b - c ^ d"));
+        assert_eq!("s ? a : (t ? b : c)", text("s ? a : t ? b : c"));
+        assert_eq!(
+            "{a[3:0], 2'b0x, {4{b[7]}}}",
+            text("{a[3:0], 2'b0?, {4{b[7]}}}")
+        );
+        assert_eq!("{2{a, b}}", text("{ 2 { a , b } }"));
+        assert_eq!("imm[20|10:1] + -4", text("imm[20|10:1] + -4"));
+        assert_eq!(None, expression_cell("a +"));
+        assert_eq!(None, expression_cell("(a"));
+        assert_eq!(None, expression_cell("a ? b"));
+    }
+
+    #[test]
+    fn expression_logic() {
+        let mut model = Module::new();
+        model.new_input("op".into(), 7).unwrap();
+        model.new_input("rs1".into(), 5).unwrap();
+        model.new_input("imm".into(), 12).unwrap();
+        let build = |model: &Module, text: &str, width| {
+            let logic = expression_cell(text).unwrap().logic(model, width)?;
+            let inputs = [("op", 7), ("rs1", 5), ("imm", 12)];
+            assign_line(&inputs, width, SignalSource::Logic(logic))
+        };
+
+        assert_eq!(
+            "assign t = (op == 7'b0010011) && (rs1 != 5'b00000);",
+            build(&model, "op == 7'h13 && rs1 != 0", 1).unwrap()
+        );
+        assert_eq!(
+            "assign t = (op & 7'b1011111) == 7'b0000011;",
+            build(&model, "op == 7'b0?00011", 1).unwrap()
+        );
+        assert_eq!(
+            "assign t = {{imm[11], imm[11], imm[11], imm[11]}, imm};",
+            build(&model, "{{4{imm[11]}}, imm}", 16).unwrap()
+        );
+        assert_eq!(
+            "assign t = rs1[0] ? (imm << 1) : (~imm);",
+            build(&model, "rs1[0] ? imm << 1 : ~imm", 12).unwrap()
+        );
+        assert_eq!(
+            "assign t = rs1 + 5'b00001;",
+            build(&model, "rs1 + 1", 5).unwrap()
+        );
+        model
+            .new_param("XLEN".into(), constant("16").unwrap().1)
+            .unwrap();
+        assert_eq!(
+            "assign t = {{imm[11], imm[11], imm[11], imm[11]}, imm};",
+            build(&model, "{{XLEN-12{imm[11]}}, imm}", 16).unwrap()
+        );
+        assert_eq!(
+            "{{(XLEN - 12){imm[11]}}, imm}",
+            expression_cell("{{XLEN-12{imm[11]}}, imm}")
+                .unwrap()
+                .to_string()
+        );
+
+        assert!(matches!(
+            build(&model, "op & rs1", 7),
+            Err(Error::OperandWidthMismatch {
+                left: 7,
+                right: 5,
+                ..
+            })
+        ));
+        assert!(matches!(
+            build(&model, "rs1", 4),
+            Err(Error::OperandWidthMismatch {
+                left: 4,
+                right: 5,
+                ..
+            })
+        ));
+        assert!(matches!(
+            build(&model, "rs1 ? op : op", 7),
+            Err(Error::OperandWidthMismatch {
+                left: 1,
+                right: 5,
+                ..
+            })
+        ));
+        assert!(matches!(
+            build(&model, "rd", 1),
+            Err(Error::UndefinedSignal { .. })
+        ));
+        assert!(matches!(
+            build(&model, "op << rs1", 7),
+            Err(Error::NotConstant { .. })
+        ));
+        for count in ["-1", "2'bx1", "0", "65536"] {
+            assert!(
+                matches!(
+                    build(&model, &format!("{{{}{{op}}}}", count), 7),
+                    Err(Error::NotConstant { .. })
+                ),
+                "{}",
+                count
+            );
+        }
+        assert!(build(&model, "{rs1{op}}", 7).is_err());
+        assert!(matches!(
+            build(&model, "{rs1, 1}", 6),
+            Err(Error::NotConstant { .. })
+        ));
+        assert!(matches!(
+            build(&model, "imm[12:0]", 13),
+            Err(Error::SignalIndexOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn expression_params() {
+        let mut model = Module::new();
+        for def in [
+            "XLEN = 32",
+            "OP_LOAD = 7'b0000011",
+            "OP_BR = 7'b11?0011",
+            "HALF = XLEN / 2",
+        ] {
+            let (_, (name, expr)) = param_def(def).unwrap();
+            let value = expr.eval(&model).unwrap();
+            model.new_param(name.into(), value).unwrap();
+        }
+        let param = |name| model.param(name).unwrap().to_string();
+        assert_eq!("32", param("XLEN"));
+        assert_eq!("16", param("HALF"));
+        assert_eq!("7'b11x0011", param("OP_BR"));
+        let eval = |text| {
+            expression_cell(text)
+                .unwrap()
+                .eval(&model)
+                .map(|l| l.to_string())
+        };
+        assert_eq!("4'b1110", eval("~4'b0001").unwrap());
+        assert_eq!("8'b00000100", eval("OP_LOAD + 8'd1").unwrap());
+        assert_eq!("1'b1", eval("XLEN == 32 && HALF < XLEN").unwrap());
+        assert_eq!("-1", eval("HALF - 17").unwrap());
+        assert!(matches!(eval("XLEN / 0"), Err(Error::NotConstant { .. })));
+        assert!(matches!(eval("OP_BR + 1"), Err(Error::NotConstant { .. })));
+        assert!(matches!(eval("FLEN"), Err(Error::UndefinedName { .. })));
+
+        let (_, ((h, l), name)) = signal_def_expr("[XLEN-1:HALF]i_instr").unwrap();
+        assert_eq!(
+            ("i_instr", 31, 16),
+            (
+                name,
+                h.eval_index(&model).unwrap(),
+                l.eval_index(&model).unwrap()
+            )
+        );
+        let (_, ((h, _), _)) = signal_def_expr("[HALF - 17:0]x").unwrap();
+        assert!(matches!(
+            h.eval_index(&model),
+            Err(Error::NotConstant { .. })
+        ));
+        assert!(param_def("XLEN 32").is_err());
+        assert!(matches!(
+            model.new_param("XLEN".into(), constant("1").unwrap().1),
+            Err(Error::SignalRedefined { .. })
+        ));
+        assert!(matches!(
+            model.new_signal("HALF".into(), 1),
+            Err(Error::SignalRedefined { .. })
+        ));
+
+        model.new_input("op".into(), 7).unwrap();
+        let logic = expression_cell("op == OP_BR || op == OP_LOAD")
+            .unwrap()
+            .logic(&model, 1)
+            .unwrap();
+        model
+            .add_signal(Signal::new("t".into(), 1, SignalSource::Logic(logic)))
+            .unwrap();
+        let verilog = VerilogPrinter::new(&model, "m", Dialect::Verilog).to_string();
+        assert!(
+            verilog.contains("assign t = ((op & 7'b1101111) == 7'b1100011) || (op == OP_LOAD);"),
+            "{}",
+            verilog
+        );
+        assert!(verilog.contains("localparam OP_LOAD = 7'b0000011;"));
+        assert!(!verilog.contains("localparam OP_BR"), "{}", verilog);
+    }
+}
@@ -1,7 +1,7 @@
 use super::identifier;
-use super::{constant, sginal_ref};
+use super::{constant, sginal_ref, wire_ref, WireRef};
 use crate::{MatchTableColumn, MatchTableContent};
-use nom::{branch::alt, bytes::complete::tag, sequence::delimited, IResult};
+use nom::{branch::alt, bytes::complete::tag, combinator::verify, sequence::delimited, IResult};
 
 fn match_flag(input: &str) -> IResult<&str, MatchTableColumn> {
     let (input, prefix) = delimited(tag("#flag("), identifier, tag(")"))(input)?;
@@ -24,9 +24,17 @@ fn match_wire_case(input: &str) -> IResult<&str, MatchTableContent> {
 
 fn match_constant(input: &str) -> IResult<&str, MatchTableContent> {
     let (input, c) = constant(input)?;
-    return Ok((input, MatchTableContent::Constant(c.0, c.1)));
+    return Ok((input, MatchTableContent::Constant(c)));
+}
+
+/// like: sext32(imm[12|10:5]), {rd, rs1}
+fn match_compose(input: &str) -> IResult<&str, MatchTableContent> {
+    let (input, w) = verify(wire_ref, |w| {
+        !matches!(w, WireRef::Signal(..) | WireRef::Constant(_))
+    })(input)?;
+    return Ok((input, MatchTableContent::Compose(w)));
 }
 
 pub fn match_content(input: &str) -> IResult<&str, MatchTableContent> {
-    return alt((match_wire_case, match_constant))(input);
+    return alt((match_compose, match_wire_case, match_constant))(input);
 }
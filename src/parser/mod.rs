@@ -4,10 +4,13 @@ mod constant;
 mod signal;
 #[allow(dead_code)]
 mod match_cmd;
+#[allow(dead_code)]
+mod expression;
 
 pub use constant::*;
 pub use signal::*;
 pub use match_cmd::*;
+pub use expression::*;
 
 use nom::{
     branch::alt,
@@ -25,3 +28,23 @@ pub fn identifier(input: &str) -> IResult<&str, &str> {
         many0_count(alt((alphanumeric1, tag("_")))),
     ))(input)
 }
+
+/// the `assign t` line of a module of `inputs` where `t` of `width` bits is driven by `from`
+#[cfg(test)]
+fn assign_line(
+    inputs: &[(&str, u16)],
+    width: u16,
+    from: crate::verilog_model::SignalSource,
+) -> Result<String, crate::verilog_model::Error> {
+    use crate::backend::{Dialect, VerilogPrinter};
+    use crate::verilog_model::{Module, Signal};
+
+    let mut model = Module::new();
+    for (name, len) in inputs {
+        model.new_input((*name).into(), *len).unwrap();
+    }
+    model.add_signal(Signal::new("t".into(), width, from))?;
+    let verilog = VerilogPrinter::new(&model, "m", Dialect::Verilog).to_string();
+    let line = verilog.lines().find(|l| l.starts_with("assign t")).unwrap();
+    return Ok(line.to_string());
+}
@@ -1,13 +1,16 @@
-use super::identifier;
+use super::{constant, identifier, Literal};
+use crate::utils::width_mask;
+use crate::verilog_model::{Error, Module, Wire, WireIndex};
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::u16 as uint16,
-    combinator::opt,
+    character::complete::{space0, u16 as uint16},
+    combinator::{map, opt, verify},
     multi::separated_list1,
-    sequence::{delimited, pair, separated_pair},
+    sequence::{delimited, pair, preceded, separated_pair, tuple},
     IResult,
 };
+use std::fmt;
 
 pub fn signal_range(input: &str) -> IResult<&str, (u16, u16)> {
     return delimited(tag("["), separated_pair(uint16, tag(":"), uint16), tag("]"))(input);
@@ -47,3 +50,289 @@ pub fn signal_ref_range(input: &str) -> IResult<&str, Vec<(u16, u16)>> {
 pub fn sginal_ref(input: &str) -> IResult<&str, (&str, Option<Vec<(u16, u16)>>)> {
     return pair(identifier, opt(signal_ref_range))(input);
 }
+
+/// like: [20|10:1], ranges from MSB to LSB
+pub fn ranges_format(ranges: &[(u16, u16)]) -> String {
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|(h, l)| match h == l {
+            true => h.to_string(),
+            false => format!("{}:{}", h, l),
+        })
+        .collect();
+    return format!("[{}]", ranges.join("|"));
+}
+
+/// bits of a declared signal, all of them without ranges
+pub fn signal_wire(
+    model: &Module,
+    name: &str,
+    ranges: &Option<Vec<(u16, u16)>>,
+) -> Result<Wire, Error> {
+    let signal = match model.get_signals().get(name) {
+        Some(signal) => signal,
+        None => {
+            return Err(Error::UndefinedSignal {
+                signal: name.into(),
+            })
+        }
+    };
+    let ranges = match ranges {
+        Some(ranges) => ranges,
+        None => return signal.range(0..signal.length),
+    };
+    if let Some(&(h, _)) = ranges.iter().find(|(h, l)| h < l) {
+        return Err(Error::SignalIndexOutOfRange {
+            len: signal.length,
+            idx: h,
+        });
+    }
+    let mut idxs: Vec<WireIndex> = ranges.iter().map(|&(h, l)| WireIndex::new(h, l)).collect();
+    return match idxs.len() {
+        1 => signal.indep(idxs.pop().unwrap()),
+        _ => signal.multiple(idxs),
+    };
+}
+
+/// a composition of signal bits, like: sext32(imm[12|10:5]), {rs1, 3'b000}, {i_instr[31]*20, i_instr[31:20]}
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireRef {
+    Signal(String, Option<Vec<(u16, u16)>>),
+    /// a sized number
+    Constant(Literal),
+    /// parts from MSB to LSB
+    Concat(Vec<WireRef>),
+    Replicate(Box<WireRef>, u16),
+    /// `sext32(..)` extends by the MSB, `zext32(..)` by zeros
+    Extend {
+        signed: bool,
+        width: u16,
+        wire: Box<WireRef>,
+    },
+}
+
+/// like: sext32(imm[12|10:1]), zext8(rs1)
+fn extend(input: &str) -> IResult<&str, WireRef> {
+    let (input, signed) = alt((map(tag("sext"), |_| true), map(tag("zext"), |_| false)))(input)?;
+    let (input, (width, wire)) = pair(
+        uint16,
+        delimited(pair(tag("("), space0), wire_ref, pair(space0, tag(")"))),
+    )(input)?;
+    return Ok((
+        input,
+        WireRef::Extend {
+            signed,
+            width,
+            wire: Box::new(wire),
+        },
+    ));
+}
+
+/// a part of a concatenation, like: i_instr[31]*20
+fn concat_part(input: &str) -> IResult<&str, WireRef> {
+    let (input, (wire, count)) = pair(
+        wire_ref,
+        opt(preceded(tuple((space0, tag("*"), space0)), uint16)),
+    )(input)?;
+    return Ok((
+        input,
+        match count {
+            Some(count) => WireRef::Replicate(Box::new(wire), count),
+            None => wire,
+        },
+    ));
+}
+
+/// like: {rs1, 3'b000}, {20{i_instr[31]}}
+fn concat(input: &str) -> IResult<&str, WireRef> {
+    let parts = |input| separated_list1(tuple((space0, tag(","), space0)), concat_part)(input);
+    let replicate = map(
+        pair(
+            uint16,
+            delimited(
+                pair(space0, tag("{")),
+                delimited(space0, parts, space0),
+                tag("}"),
+            ),
+        ),
+        |(count, mut parts)| {
+            let wire = match parts.len() {
+                1 => parts.pop().unwrap(),
+                _ => WireRef::Concat(parts),
+            };
+            WireRef::Replicate(Box::new(wire), count)
+        },
+    );
+    return delimited(
+        pair(tag("{"), space0),
+        alt((replicate, map(parts, WireRef::Concat))),
+        pair(space0, tag("}")),
+    )(input);
+}
+
+pub fn wire_ref(input: &str) -> IResult<&str, WireRef> {
+    return alt((
+        extend,
+        concat,
+        map(verify(constant, |c| c.width.is_some()), WireRef::Constant),
+        map(sginal_ref, |(name, ranges)| {
+            WireRef::Signal(name.into(), ranges)
+        }),
+    ))(input);
+}
+
+impl WireRef {
+    /// bits the composition stands for, replications and extensions repeat their bits
+    pub fn wire(&self, model: &Module) -> Result<Wire, Error> {
+        return match self {
+            WireRef::Signal(name, ranges) => signal_wire(model, name, ranges),
+            WireRef::Constant(c) => match c.width {
+                Some(w) if c.mask == width_mask(w) => Ok(Wire::bit(w, c.value)),
+                _ => Err(Error::NotConstant {
+                    expr: self.to_string(),
+                }),
+            },
+            WireRef::Concat(parts) => {
+                let wires: Result<Vec<Wire>, Error> = parts.iter().map(|p| p.wire(model)).collect();
+                Ok(Wire::compose(wires?))
+            }
+            WireRef::Replicate(part, count) => {
+                let wire = part.wire(model)?;
+                if *count == 0 || wire.len().checked_mul(*count).is_none() {
+                    return Err(Error::NotConstant {
+                        expr: self.to_string(),
+                    });
+                }
+                Ok(Wire::compose(vec![wire; *count as usize]))
+            }
+            WireRef::Extend {
+                signed,
+                width,
+                wire,
+            } => {
+                let wire = wire.wire(model)?;
+                let len = wire.len();
+                if *width < len {
+                    return Err(Error::OperandWidthMismatch {
+                        expr: self.to_string(),
+                        left: *width,
+                        right: len,
+                    });
+                }
+                let fill = match (signed, wire.msb()) {
+                    _ if *width == len => return Ok(wire),
+                    (true, Some(msb)) => Wire::compose(vec![msb; (width - len) as usize]),
+                    _ => Wire::bit(width - len, 0),
+                };
+                Ok(Wire::compose(vec![fill, wire]))
+            }
+        };
+    }
+}
+
+impl fmt::Display for WireRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WireRef::Signal(name, None) => write!(f, "{}", name),
+            WireRef::Signal(name, Some(ranges)) => write!(f, "{}{}", name, ranges_format(ranges)),
+            WireRef::Constant(c) => write!(f, "{}", c),
+            WireRef::Concat(parts) => {
+                let parts: Vec<String> = parts.iter().map(|p| p.to_string()).collect();
+                write!(f, "{{{}}}", parts.join(", "))
+            }
+            WireRef::Replicate(part, count) => match &**part {
+                WireRef::Concat(_) => write!(f, "{{{}{}}}", count, part),
+                _ => write!(f, "{{{}{{{}}}}}", count, part),
+            },
+            WireRef::Extend {
+                signed,
+                width,
+                wire,
+            } => {
+                let ext = match signed {
+                    true => "sext",
+                    false => "zext",
+                };
+                write!(f, "{}{}({})", ext, width, wire)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::assign_line;
+    use crate::verilog_model::SignalSource;
+
+    #[test]
+    fn wire_ref_compose() {
+        // the text of a composition parses back to it
+        let text = |input: &str| {
+            let (rest, wire) = wire_ref(input).unwrap();
+            assert_eq!("", rest, "{}", input);
+            assert_eq!(Ok(("", wire.clone())), wire_ref(&wire.to_string()));
+            wire.to_string()
+        };
+        assert_eq!("sext32(imm[12|10:5])", text("sext32(imm[12|10:5])"));
+        assert_eq!("{rs1, 3'b000}", text("{ rs1 , 3'b000 }"));
+        assert_eq!(
+            "{{20{i_instr[31]}}, i_instr[31:20]}",
+            text("{i_instr[31]*20, i_instr[31:20]}")
+        );
+        assert_eq!("{2{rs1, rs2}}", text("{2{rs1, rs2}}"));
+        assert_eq!("{4{rs1[0]}}", text("{4{rs1[0]}}"));
+        assert_eq!("zext8({a[1], 1'b0})", text("zext8({a[1], 1'b0})"));
+        assert_eq!("sext", text("sext"));
+
+        let mut model = Module::new();
+        model.new_input("i_instr".into(), 32).unwrap();
+        model.new_input("rs1".into(), 5).unwrap();
+        let build = |text: &str| {
+            let wire = wire_ref(text).unwrap().1.wire(&model)?;
+            let width = wire.len();
+            let line = assign_line(
+                &[("i_instr", 32), ("rs1", 5)],
+                width,
+                SignalSource::Wire(wire),
+            )?;
+            Ok::<(u16, String), Error>((width, line))
+        };
+        let (width, line) = build("sext32(i_instr[31:20])").unwrap();
+        assert_eq!(32, width);
+        assert!(
+            line.starts_with("assign t = {{i_instr[31], i_instr[31],"),
+            "{}",
+            line
+        );
+        assert!(line.ends_with("i_instr[31]}, i_instr[31:20]};"), "{}", line);
+        assert_eq!(
+            (8, "assign t = {3'b000, rs1};".into()),
+            build("zext8(rs1)").unwrap()
+        );
+        assert_eq!(
+            (8, "assign t = {rs1, 3'b000};".into()),
+            build("{rs1, 3'b000}").unwrap()
+        );
+        assert_eq!(
+            (
+                4,
+                "assign t = {{i_instr[31], i_instr[31]}, rs1[1:0]};".into()
+            ),
+            build("{i_instr[31]*2, rs1[1:0]}").unwrap()
+        );
+        assert!(matches!(
+            build("sext4(rs1)"),
+            Err(Error::OperandWidthMismatch {
+                left: 4,
+                right: 5,
+                ..
+            })
+        ));
+        assert!(matches!(build("{rs1*0}"), Err(Error::NotConstant { .. })));
+        assert!(matches!(
+            build("{rd, rs1}"),
+            Err(Error::UndefinedSignal { .. })
+        ));
+    }
+}
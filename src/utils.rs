@@ -1,12 +1,33 @@
-use std::ops::Shr;
+use std::sync::atomic::{AtomicU8, Ordering};
 
 use super::verilog_model::SignalWidth;
 
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> u8 {
+    return VERBOSITY.load(Ordering::Relaxed);
+}
+
+/// print to stderr when the verbosity is at least `level`
+#[macro_export]
+macro_rules! verbose {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::utils::verbosity() >= $level {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// bits above the 128 bits of `constant` are zeros
 pub fn binary_format(width: SignalWidth, constant: u128) -> String {
     let width = width as usize;
     let mut result = String::with_capacity(width);
     for i in (0..width).rev() {
-        result.push(if constant.shr(i) & 0x01 == 1 {
+        result.push(if constant.checked_shr(i as u32).unwrap_or(0) & 0x01 == 1 {
             '1'
         } else {
             '0'
@@ -14,3 +35,21 @@ pub fn binary_format(width: SignalWidth, constant: u128) -> String {
     }
     return result;
 }
+
+/// the low `width` bits set
+pub fn width_mask(width: SignalWidth) -> u128 {
+    return match width {
+        0 => 0,
+        w if w >= 128 => u128::MAX,
+        w => (1 << w) - 1,
+    };
+}
+
+/// like `binary_format`, don't care bits, the clear bits of `mask`, are `x`
+pub fn pattern_format(width: SignalWidth, constant: u128, mask: u128) -> String {
+    return binary_format(width, constant)
+        .chars()
+        .zip(binary_format(width, mask).chars())
+        .map(|(bit, care)| if care == '1' { bit } else { 'x' })
+        .collect();
+}
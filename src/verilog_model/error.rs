@@ -1,15 +1,124 @@
 use std::fmt;
 
-use super::SignalWidth;
+use super::{SignalKey, SignalWidth};
 
 #[derive(Debug)]
 pub enum Error {
-    SignalIndexOutOfRange { len: SignalWidth, idx: SignalWidth },
+    SignalIndexOutOfRange {
+        len: SignalWidth,
+        idx: SignalWidth,
+    },
+    SignalRedefined {
+        signal: SignalKey,
+    },
+    SignalWidthMismatch {
+        signal: SignalKey,
+        expect: SignalWidth,
+        found: SignalWidth,
+    },
+    OutputUndriven {
+        signal: SignalKey,
+    },
+    /// a cell refers to a name the workbook does not define
+    UndefinedName {
+        name: String,
+    },
+    /// a match cell that is neither a constant nor a signal
+    BadMatchCell {
+        cell: String,
+        text: String,
+    },
+    /// an expression refers to a signal that is not declared
+    UndefinedSignal {
+        signal: String,
+    },
+    /// operands of an expression, or an expression and its target, differ in width
+    OperandWidthMismatch {
+        expr: String,
+        left: SignalWidth,
+        right: SignalWidth,
+    },
+    /// a part of an expression that has to be a sized constant, like a shift amount
+    NotConstant {
+        expr: String,
+    },
+    /// a cell that is not an expression
+    BadExpression {
+        cell: String,
+        text: String,
+    },
+    /// a name of a range is used where a single value is needed
+    NameNotSingle {
+        name: String,
+        values: usize,
+    },
+    /// a cell of a declaration row that declares nothing, like `d` for `[7:0]d`
+    BadDeclaration {
+        cell: String,
+        text: String,
+    },
+    /// an `#end` row without a `#match` row before it
+    UnmatchedEnd {
+        cell: String,
+    },
+    /// a match table without a `#primary` column
+    MissingPrimary {
+        cell: String,
+    },
+    /// an error of the signals of a cell
+    InCell {
+        cell: String,
+        error: Box<Error>,
+    },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        return write!(f, "{:?}", self);
+        return match self {
+            Error::SignalIndexOutOfRange { len, idx } => {
+                write!(f, "bit {} out of range of a {} bit signal", idx, len)
+            }
+            Error::SignalRedefined { signal } => {
+                write!(f, "`{}` is defined more than once", signal.as_str())
+            }
+            Error::SignalWidthMismatch {
+                signal,
+                expect,
+                found,
+            } => write!(
+                f,
+                "width mismatch of `{}`, expected {} bits, found {}",
+                signal.as_str(),
+                expect,
+                found
+            ),
+            Error::OutputUndriven { signal } => {
+                write!(f, "output `{}` is not driven", signal.as_str())
+            }
+            Error::UndefinedName { name } => write!(f, "undefined name `{}`", name),
+            Error::BadMatchCell { cell, text } => write!(f, "{}: bad match cell `{}`", cell, text),
+            Error::UndefinedSignal { signal } => write!(f, "undefined signal `{}`", signal),
+            Error::OperandWidthMismatch { expr, left, right } => write!(
+                f,
+                "operands of `{}` differ in width, {} bits and {} bits",
+                expr, left, right
+            ),
+            Error::NotConstant { expr } => write!(f, "`{}` is not a constant", expr),
+            Error::BadExpression { cell, text } => write!(f, "{}: bad expression `{}`", cell, text),
+            Error::NameNotSingle { name, values } => write!(
+                f,
+                "name `{}` has {} values, a single value is needed",
+                name, values
+            ),
+            Error::BadDeclaration { cell, text } => {
+                write!(f, "{}: bad declaration `{}`", cell, text)
+            }
+            Error::UnmatchedEnd { cell } => write!(f, "{}: `#end` without `#match`", cell),
+            Error::MissingPrimary { cell } => {
+                write!(f, "{}: match table without a `#primary` column", cell)
+            }
+            Error::InCell { cell, error } => write!(f, "{}: {}", cell, error),
+        };
     }
 }
 
@@ -1,6 +1,6 @@
 use super::signal::*;
 use super::SignalWidth;
-use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Shl, Shr, Sub};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Deref, Div, Mul, Not, Shl, Shr, Sub};
 use std::rc::Rc;
 
 #[derive(Clone, Debug)]
@@ -11,6 +11,8 @@ pub struct LogicTree(Rc<LogicElem>);
 pub enum LogicElem {
     Unit(Wire),
     Combine(Vec<LogicTree>),
+    /// a param of the module by its name, its value is known to every bit
+    Param(String),
     BitNot(LogicTree),
     BitAnd(LogicTree, LogicTree),
     BitOr(LogicTree, LogicTree),
@@ -168,3 +170,11 @@ impl Div for LogicTree {
         return LogicElem::Div(self, rhs).into();
     }
 }
+
+impl Deref for LogicTree {
+    type Target = LogicElem;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
@@ -1,12 +1,15 @@
 use super::SignalKey;
 use super::SignalWidth;
-use super::{Signal, SignalSource};
+use super::{Error, Signal, SignalSource};
+use crate::parser::Literal;
 use std::collections::HashMap;
 
 pub struct Module {
     input: Vec<SignalKey>,
     output: Vec<SignalKey>,
     signals: HashMap<SignalKey, Signal>,
+    /// named constants in declaration order, emitted as localparams
+    params: Vec<(String, Literal)>,
 }
 
 #[allow(dead_code)]
@@ -16,25 +19,76 @@ impl Module {
             input: Vec::new(),
             output: Vec::new(),
             signals: HashMap::new(),
+            params: Vec::new(),
         };
     }
 
-    pub fn new_input(&mut self, name: String, length: SignalWidth) {
-        let key = self.new_signal(name, length);
+    pub fn new_input(&mut self, name: String, length: SignalWidth) -> Result<(), Error> {
+        let key = self.new_signal(name, length)?;
+        self.signals.get_mut(&key).unwrap().from = SignalSource::Input;
         self.input.push(key);
+        return Ok(());
     }
 
-    pub fn new_output(&mut self, name: String, length: SignalWidth) {
-        let key = self.new_signal(name, length);
+    pub fn new_output(&mut self, name: String, length: SignalWidth) -> Result<(), Error> {
+        let key = self.new_signal(name, length)?;
         self.output.push(key);
+        return Ok(());
     }
 
-    pub fn new_signal(&mut self, name: String, length: SignalWidth) -> SignalKey {
+    /// declare a signal, a declaration of an unconnected output port binds to that port
+    pub fn new_signal(&mut self, name: String, length: SignalWidth) -> Result<SignalKey, Error> {
+        self.check_not_param(&name)?;
         let key: SignalKey = name.into();
+        if self.signals.contains_key(&key) {
+            self.bind_output(&key, length)?;
+            return Ok(key);
+        }
         let signal = Signal::new(key.clone(), length, SignalSource::Unconnected);
-        assert_eq!(false, self.signals.contains_key(&key));
         self.signals.insert(key.clone(), signal);
-        return key;
+        return Ok(key);
+    }
+
+    /// declare a named constant, its name is shared with the signals
+    pub fn new_param(&mut self, name: String, value: Literal) -> Result<(), Error> {
+        self.check_not_param(&name)?;
+        if self.signals.contains_key(name.as_str()) {
+            return Err(Error::SignalRedefined {
+                signal: name.into(),
+            });
+        }
+        self.params.push((name, value));
+        return Ok(());
+    }
+
+    /// signals and params share one namespace, a name a param takes is taken
+    fn check_not_param(&self, name: &str) -> Result<(), Error> {
+        if self.param(name).is_some() {
+            return Err(Error::SignalRedefined {
+                signal: name.into(),
+            });
+        }
+        return Ok(());
+    }
+
+    pub fn param(&self, name: &str) -> Option<&Literal> {
+        return self
+            .params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value);
+    }
+
+    pub fn get_params(&self) -> &Vec<(String, Literal)> {
+        return &self.params;
+    }
+
+    pub fn get_inputs(&self) -> &Vec<SignalKey> {
+        return &self.input;
+    }
+
+    pub fn get_outputs(&self) -> &Vec<SignalKey> {
+        return &self.output;
     }
 
     pub fn get_signals(&self) -> &HashMap<SignalKey, Signal> {
@@ -45,8 +99,166 @@ impl Module {
         return &mut self.signals;
     }
 
-    pub fn add_signal(&mut self, signal: Signal) {
-        assert_eq!(false, self.signals.contains_key(&signal.key));
+    /// add a generated signal, a signal named after an unconnected output port drives that port
+    pub fn add_signal(&mut self, signal: Signal) -> Result<(), Error> {
+        self.check_not_param(signal.key.as_str())?;
+        if self.signals.contains_key(&signal.key) {
+            self.bind_output(&signal.key, signal.length)?;
+            self.signals.get_mut(&signal.key).unwrap().from = signal.from;
+            return Ok(());
+        }
         self.signals.insert(signal.key.clone(), signal);
+        return Ok(());
+    }
+
+    /// attach a note to a signal, the same note is kept once
+    pub fn document(&mut self, key: &str, doc: &str) {
+        if let Some(signal) = self.signals.get_mut(key) {
+            let doc = doc.trim();
+            if !doc.is_empty() && !signal.doc.iter().any(|d| d == doc) {
+                signal.doc.push(doc.into());
+            }
+        }
+    }
+
+    /// connect a declared signal to its source
+    pub fn drive(&mut self, key: &SignalKey, from: SignalSource) -> Result<(), Error> {
+        let signal = match self.signals.get_mut(key) {
+            Some(signal) => signal,
+            None => {
+                return Err(Error::UndefinedSignal {
+                    signal: key.as_str().into(),
+                })
+            }
+        };
+        if let SignalSource::Unconnected = signal.from {
+            if let SignalSource::Wire(w) = &from {
+                if w.len() != signal.length {
+                    return Err(Error::SignalWidthMismatch {
+                        signal: key.clone(),
+                        expect: signal.length,
+                        found: w.len(),
+                    });
+                }
+            }
+            signal.from = from;
+            return Ok(());
+        }
+        return Err(Error::SignalRedefined {
+            signal: key.clone(),
+        });
+    }
+
+    fn bind_output(&self, key: &SignalKey, length: SignalWidth) -> Result<(), Error> {
+        let port = &self.signals[key];
+        if !self.output.contains(key) {
+            return Err(Error::SignalRedefined {
+                signal: key.clone(),
+            });
+        }
+        if let SignalSource::Unconnected = port.from {
+            if port.length != length {
+                return Err(Error::SignalWidthMismatch {
+                    signal: key.clone(),
+                    expect: port.length,
+                    found: length,
+                });
+            }
+            return Ok(());
+        }
+        return Err(Error::SignalRedefined {
+            signal: key.clone(),
+        });
+    }
+
+    /// every output port must be driven by a signal of the same width
+    pub fn check_outputs(&self) -> Result<(), Error> {
+        for key in &self.output {
+            let port = &self.signals[key];
+            let found = match &port.from {
+                SignalSource::Unconnected | SignalSource::Input => {
+                    return Err(Error::OutputUndriven {
+                        signal: key.clone(),
+                    });
+                }
+                SignalSource::Wire(w) => w.len(),
+                SignalSource::Logic(_) => port.length,
+            };
+            if found != port.length {
+                return Err(Error::SignalWidthMismatch {
+                    signal: key.clone(),
+                    expect: port.length,
+                    found,
+                });
+            }
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verilog_model::Wire;
+
+    #[test]
+    fn bind_output() {
+        let mut module = Module::new();
+        module.new_output("inst_add".into(), 1).unwrap();
+        module.new_output("rd".into(), 5).unwrap();
+        assert!(matches!(
+            module.check_outputs(),
+            Err(Error::OutputUndriven { .. })
+        ));
+
+        let driver = Signal::new("inst_add".into(), 1, SignalSource::Wire(Wire::bit(1, 1)));
+        module.add_signal(driver.clone()).unwrap();
+        assert!(matches!(
+            module.add_signal(driver),
+            Err(Error::SignalRedefined { .. })
+        ));
+        assert!(matches!(
+            module.new_signal("rd".into(), 4),
+            Err(Error::SignalWidthMismatch {
+                expect: 5,
+                found: 4,
+                ..
+            })
+        ));
+
+        let rd = module.new_signal("rd".into(), 5).unwrap();
+        module
+            .drive(&rd, SignalSource::Wire(Wire::bit(5, 0)))
+            .unwrap();
+        assert!(module.check_outputs().is_ok());
+        assert!(matches!(
+            module.drive(&"rs".into(), SignalSource::Wire(Wire::bit(5, 0))),
+            Err(Error::UndefinedSignal { .. })
+        ));
+    }
+
+    #[test]
+    fn shared_names() {
+        let mut module = Module::new();
+        let one = crate::parser::constant("1").unwrap().1;
+        module.new_param("inst_a".into(), one).unwrap();
+        module.new_input("op".into(), 7).unwrap();
+        let generated = Signal::new("inst_a".into(), 1, SignalSource::Wire(Wire::bit(1, 1)));
+        assert!(matches!(
+            module.add_signal(generated),
+            Err(Error::SignalRedefined { .. })
+        ));
+        assert!(matches!(
+            module.new_signal("inst_a".into(), 1),
+            Err(Error::SignalRedefined { .. })
+        ));
+        assert!(matches!(
+            module.new_param("op".into(), one),
+            Err(Error::SignalRedefined { .. })
+        ));
+        assert!(matches!(
+            module.new_param("inst_a".into(), one),
+            Err(Error::SignalRedefined { .. })
+        ));
     }
 }
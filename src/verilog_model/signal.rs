@@ -8,6 +8,18 @@ impl WireIndex {
     pub fn new(h: SignalWidth, l: SignalWidth) -> Self {
         return WireIndex(l..h + 1);
     }
+
+    pub fn high(&self) -> SignalWidth {
+        return self.0.end - 1;
+    }
+
+    pub fn low(&self) -> SignalWidth {
+        return self.0.start;
+    }
+
+    pub fn len(&self) -> SignalWidth {
+        return self.0.len() as SignalWidth;
+    }
 }
 
 impl From<Range<SignalWidth>> for WireIndex {
@@ -35,10 +47,12 @@ pub enum Wire {
         signal: SignalKey,
         idx: WireIndex,
     },
+    /// indexes are ordered from MSB to LSB, like `imm[20|10:1|11|19:12]`
     Multiple {
         signal: SignalKey,
         idxs: Vec<WireIndex>,
     },
+    /// wires are ordered from MSB to LSB, like verilog concatenation
     Compose {
         wires: Vec<Wire>,
     },
@@ -92,6 +106,25 @@ impl Wire {
         return Wire::Compose { wires };
     }
 
+    /// the most significant bit, `None` for a wire without bits
+    pub fn msb(&self) -> Option<Wire> {
+        return match self {
+            Wire::Constant(c, w) if *w > 0 => {
+                Some(Wire::bit(1, c.checked_shr(*w as u32 - 1).unwrap_or(0) & 1))
+            }
+            Wire::Constant(..) => None,
+            Wire::Independent { signal, idx } => Some(Wire::Independent {
+                signal: signal.clone(),
+                idx: WireIndex::new(idx.high(), idx.high()),
+            }),
+            Wire::Multiple { signal, idxs } => idxs.first().map(|idx| Wire::Independent {
+                signal: signal.clone(),
+                idx: WireIndex::new(idx.high(), idx.high()),
+            }),
+            Wire::Compose { wires } => wires.iter().find_map(|w| w.msb()),
+        };
+    }
+
     pub fn len(&self) -> SignalWidth {
         match &self {
             Wire::Constant(_, w) => {
@@ -132,6 +165,8 @@ pub struct Signal {
     pub key: SignalKey,
     pub length: SignalWidth,
     pub from: SignalSource,
+    /// notes of the cells the signal comes from, like spec references
+    pub doc: Vec<String>,
 }
 
 impl std::fmt::Display for Signal {
@@ -150,7 +185,12 @@ impl std::fmt::Debug for Signal {
 #[allow(dead_code)]
 impl Signal {
     pub fn new(key: SignalKey, length: SignalWidth, from: SignalSource) -> Signal {
-        return Signal { key, length, from };
+        return Signal {
+            key,
+            length,
+            from,
+            doc: Vec::new(),
+        };
     }
 
     fn assert_index(&self, idx: &WireIndex) -> Option<Error> {